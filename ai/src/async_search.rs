@@ -0,0 +1,149 @@
+﻿use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Instant;
+
+use env::board::Board;
+use env::env::Env;
+use env::ojama_status::OjamaStatus;
+use env::puyo_kind::PuyoKind;
+
+use crate::ai_move::AIMove;
+use crate::build_ai::AI;
+use crate::debug::Debug;
+use crate::evaluator::Evaluator;
+use crate::move_generator::MoveOrigin;
+use crate::opponent_status::OpponentStatus;
+
+///探索する局面、AI::searchの引数をまとめたもの
+pub struct SearchRequest {
+	pub board: Board,
+	pub current: MoveOrigin,
+	pub next: Vec<[PuyoKind; 2]>,
+	pub ojama: OjamaStatus,
+	pub center_puyo: PuyoKind,
+	pub movable_puyo: PuyoKind,
+	pub all_cleared: bool,
+	pub ojama_rate: usize,
+	pub opponent_status: OpponentStatus,
+}
+
+impl SearchRequest {
+	///envの今のぷよを置く局面
+	pub fn from_env(env: &Env, opponent_status: &OpponentStatus) -> Self {
+		SearchRequest {
+			board: env.board.clone(),
+			current: MoveOrigin::from_env(env),
+			next: env.next.to_vec(),
+			ojama: env.ojama.clone(),
+			center_puyo: env.center_puyo,
+			movable_puyo: env.movable_puyo,
+			all_cleared: env.all_cleared,
+			ojama_rate: env.ojama_rate,
+			opponent_status: opponent_status.clone(),
+		}
+	}
+}
+
+///AsyncSearchの探索結果
+#[derive(Debug, Clone)]
+pub struct SearchResult {
+	///startが返した番号
+	pub id: u64,
+	pub best_move: AIMove,
+	pub debug: Option<Debug>,
+}
+
+///AIを別のスレッドで探索させる、操作している間も次の局面を考えておける
+///startし直すと探索中のものは打ち切られ、結果は最後にstartした局面のものだけが返る
+pub struct AsyncSearch {
+	///最後にstartした番号、探索中の番号と違えば打ち切る
+	latest_id: Arc<AtomicU64>,
+	requests: Option<Sender<(u64, SearchRequest)>>,
+	results: Receiver<SearchResult>,
+	result: Option<SearchResult>,
+	worker: Option<JoinHandle<()>>,
+}
+
+impl AsyncSearch {
+	///aiは探索用のスレッドに移る、rulesやsearch_configは渡す前に設定しておく
+	pub fn new<E: Evaluator + 'static>(mut ai: AI<E>) -> Self {
+		let latest_id = Arc::new(AtomicU64::new(0));
+		let (request_sender, request_receiver) = mpsc::channel::<(u64, SearchRequest)>();
+		let (result_sender, result_receiver) = mpsc::channel();
+
+		let worker_latest_id = latest_id.clone();
+		let worker = thread::spawn(move || {
+			while let Ok(mut request) = request_receiver.recv() {
+				//溜まっていたら最後の局面だけを探索する
+				while let Ok(newer) = request_receiver.try_recv() {
+					request = newer;
+				}
+
+				let (id, request) = request;
+				let should_stop = || worker_latest_id.load(Ordering::Relaxed) != id;
+				unsafe {
+					ai.search_until(&request.board, &request.current, &request.next, &request.ojama, request.center_puyo, request.movable_puyo,
+									request.all_cleared, &request.ojama_rate, &request.opponent_status, &should_stop);
+				}
+				if should_stop() {
+					continue;
+				}
+
+				let result = SearchResult {
+					id,
+					best_move: ai.best_move.clone().unwrap(),
+					debug: ai.debug.clone(),
+				};
+				if result_sender.send(result).is_err() {
+					break;
+				}
+			}
+		});
+
+		AsyncSearch {
+			latest_id,
+			requests: Some(request_sender),
+			results: result_receiver,
+			result: None,
+			worker: Some(worker),
+		}
+	}
+
+	///requestの探索を始める、前の探索は打ち切る。返す番号はSearchResult::idと同じ
+	pub fn start(&mut self, request: SearchRequest) -> u64 {
+		let id = self.latest_id.load(Ordering::Relaxed) + 1;
+		self.latest_id.store(id, Ordering::Relaxed);
+		//探索スレッドが止まっていたら結果が来ないだけなので無視する
+		let _ = self.requests.as_ref().unwrap().send((id, request));
+		id
+	}
+
+	///最後にstartした番号
+	pub fn latest_id(&self) -> u64 {
+		self.latest_id.load(Ordering::Relaxed)
+	}
+
+	///最後にstartした局面の結果、deadlineまでに探索が終わらなければNone
+	pub fn result(&mut self, deadline: Instant) -> Option<&SearchResult> {
+		let id = self.latest_id();
+		while self.result.as_ref().is_none_or(|result| result.id != id) {
+			//打ち切られる前に終わった古い結果も届くので読み捨てる
+			let timeout = deadline.saturating_duration_since(Instant::now());
+			self.result = Some(self.results.recv_timeout(timeout).ok()?);
+		}
+
+		self.result.as_ref()
+	}
+}
+
+impl Drop for AsyncSearch {
+	fn drop(&mut self) {
+		self.latest_id.fetch_add(1, Ordering::Relaxed);
+		self.requests = None;
+		if let Some(worker) = self.worker.take() {
+			let _ = worker.join();
+		}
+	}
+}
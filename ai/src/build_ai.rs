@@ -1,34 +1,52 @@
-﻿use std::arch::x86_64::{__m128i, _mm_and_si128, _mm_andnot_si128, _mm_cvtsi128_si64, _mm_extract_epi16, _mm_extract_epi64, _mm_set_epi64x, _mm_setzero_si128, _mm_store_si128, _mm_xor_si128, _popcnt64};
-use std::collections::hash_map::Entry;
-use std::collections::HashMap;
+﻿use std::time::{Duration, Instant};
 
-use rand::rngs::ThreadRng;
-use rand::thread_rng;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
+use rayon::prelude::*;
 use revonet::neuro::MultilayeredNetwork;
 use serde::__private::de::Content::U8;
 
 use env::board::Board;
 use env::board_bit::BoardBit;
-use env::env::{ALL_CLEAR_BONUS, DEAD_POSITION, Env, FrameNeeded, SPAWN_POS};
+use env::env::{DEAD_POSITION, FrameNeeded};
+use env::ojama_placer::{OjamaDropOrder, OjamaPlacer};
 use env::ojama_status::OjamaStatus;
-use env::puyo_kind::{COLOR_PUYOS, PuyoKind};
-use env::puyo_status::PuyoStatus;
-use env::rotation::Rotation;
-use env::split_board::SplitBoard;
+use env::puyo_kind::PuyoKind;
+use env::rules::Rules;
 use env::vector2::Vector2;
 
 use crate::ai_move::AIMove;
 use crate::debug::Debug;
-use crate::evaluator::Evaluator;
+use crate::evaluator::{EvalContext, Evaluator};
 use crate::evaluator::nn_evaluator::NNEvaluator;
 use crate::key_type::KeyType;
-use crate::key_type::KeyType::{Drop, Left, Right, Rotate180, RotateLeft, RotateRight};
+use crate::key_type::KeyType::Drop;
 use crate::opponent_status::OpponentStatus;
-use crate::path::Path;
+use crate::move_generator::{MoveGenerator, MoveOrigin, Placement};
 use crate::potential::Potential;
+use crate::search_config::SearchConfig;
+use crate::transposition_table::{TableEntry, TranspositionTable};
 
 pub const POTENTIAL_SEARCH_DEPTH: usize = 4;
 
+///ビームサーチで残す盤面と、そこまでの累計
+#[derive(Clone)]
+struct SearchNode {
+	///連鎖とお邪魔の落下まで済ませた盤面
+	board: Board,
+	ojama: OjamaStatus,
+	score: usize,
+	elapsed_frame: usize,
+	all_cleared: bool,
+	waste_chain_link: usize,
+	attacked_value: usize,
+	///最初に置いたカレントの置き方の番号
+	root: usize,
+	eval: f32,
+	debug: Debug,
+}
+
 //盤面とネクスト、カレントを渡す
 //凝視情報？相手のそのまま？
 pub struct AI<E: Evaluator> {
@@ -36,6 +54,11 @@ pub struct AI<E: Evaluator> {
 	pub eval: Option<f32>,
 	pub best_move: Option<AIMove>,
 	pub debug: Option<Debug>,
+	///全消しボーナスなど、探索中の得点計算に使う
+	pub rules: Rules,
+	pub search_config: SearchConfig,
+	///探索の間で共有する、潜在連鎖の結果は次の探索でも使う
+	pub transposition_table: TranspositionTable,
 }
 
 impl<E: Evaluator> AI<E> {
@@ -45,6 +68,9 @@ impl<E: Evaluator> AI<E> {
 			eval: None,
 			evaluator,
 			debug: None,
+			rules: Rules::default(),
+			search_config: SearchConfig::default(),
+			transposition_table: TranspositionTable::new(TranspositionTable::DEFAULT_SIZE_LOG2),
 		}
 	}
 
@@ -54,39 +80,178 @@ impl<E: Evaluator> AI<E> {
 			eval: self.eval,
 			evaluator: self.evaluator.clone(),
 			debug: Option::from(self.debug.clone()),
+			rules: self.rules.clone(),
+			search_config: self.search_config.clone(),
+			transposition_table: TranspositionTable::new(self.transposition_table.size_log2()),
 		}
 	}
 
-	pub unsafe fn search(&mut self, board: &Board, current: &PuyoStatus, next: &Vec<PuyoKind>, ojama: &OjamaStatus, center_puyo: PuyoKind, movable_puyo: PuyoKind, all_cleared: bool, ojama_rate: &usize, opponent_status: &OpponentStatus) {
-		//let debug = board.get_not_empty_board();
+	///currentとnextを順に置いてビームサーチし、最も評価の高い置き方をbest_moveに入れる
+	///nextはEnv::nextと同じく[子ぷよ, 軸ぷよ]のペアを近い順に並べる
+	pub unsafe fn search(&mut self, board: &Board, current: &MoveOrigin, next: &[[PuyoKind; 2]], ojama: &OjamaStatus, center_puyo: PuyoKind, movable_puyo: PuyoKind, all_cleared: bool, ojama_rate: &usize, opponent_status: &OpponentStatus) {
+		self.search_until(board, current, next, ojama, center_puyo, movable_puyo, all_cleared, ojama_rate, opponent_status, &|| false);
+	}
+
+	///searchと同じ、should_stopがtrueを返したら時間切れと同じくそこまでの結果で決める
+	pub unsafe fn search_until(&mut self, board: &Board, current: &MoveOrigin, next: &[[PuyoKind; 2]], ojama: &OjamaStatus, center_puyo: PuyoKind, movable_puyo: PuyoKind, all_cleared: bool, ojama_rate: &usize, opponent_status: &OpponentStatus, should_stop: &dyn Fn() -> bool) {
 		self.best_move = Option::from(AIMove::new(-999., vec![Drop]));
-		//self.best_move = None;
 		self.debug = None;
 
-		let mut rng = thread_rng();
+		let config = self.search_config.clone();
+		let deadline = config.time_budget_ms.map(|ms| Instant::now() + Duration::from_millis(ms));
+		let timed_out = || should_stop() || deadline.is_some_and(|deadline| Instant::now() >= deadline);
+
+		let instant_attack_count = AI::<NNEvaluator<MultilayeredNetwork>>::get_instant_attack(&board, &ojama_rate, self.rules.colors());
+
+		let root = SearchNode {
+			board: board.clone(),
+			ojama: ojama.clone(),
+			score: 0,
+			elapsed_frame: 0,
+			all_cleared,
+			waste_chain_link: 0,
+			attacked_value: 0,
+			root: 0,
+			eval: 0.,
+			debug: Debug::new(),
+		};
 
-		let instant_attack_count = AI::<NNEvaluator<MultilayeredNetwork>>::get_instant_attack(&board, &ojama_rate);
+		//カレントの置き方ごとにrootの番号を振る
+		let mut root_paths = Vec::new();
+		let mut children = Vec::new();
+		for (mut child, path) in self.expand(&root, current, center_puyo, movable_puyo, ojama_rate, opponent_status, &instant_attack_count) {
+			child.root = root_paths.len();
+			root_paths.push(path);
+			children.push(child);
+		}
+		let mut beam = self.select(children, config.beam_width);
 
+		let visible_depth = config.depth.min(next.len() + 1);
+		for pair in &next[..visible_depth.saturating_sub(1)] {
+			if beam.is_empty() || timed_out() {
+				break;
+			}
 
-		self.search_internal(&board, &current, &next, ojama, center_puyo, movable_puyo, &Vec::new(), 0, 0, all_cleared, ojama_rate, &mut rng, opponent_status, 0, &instant_attack_count, 0);
+			let children = self.expand_beam(&beam, *pair, ojama_rate, opponent_status, &instant_attack_count);
+			//全て死ぬなら1手前までの評価で決める
+			if children.is_empty() {
+				break;
+			}
+			beam = children;
+		}
 
+		let Some(best) = beam.first() else {
+			return;
+		};
+
+		//見えていないツモを引き直して、置き方ごとに最後の盤面の評価を平均する
+		let mut totals = vec![0f32; root_paths.len()];
+		let mut completed = 0;
+		//ツモは袋から引く、乱数は局面ごとに決まるので同じ局面なら同じ置き方になる
+		let mut rng = StdRng::seed_from_u64(config.seed ^ board.hash());
+		let mut bag = self.rules.bag();
+		'sample: for _ in 0..config.sample_count {
+			let (sampled, _) = bag.partial_shuffle(&mut rng, (config.depth - visible_depth) * 2);
+			let sampled = sampled.to_vec();
+			let mut sampled_beam = beam.clone();
+			for pair in sampled.chunks(2) {
+				if timed_out() {
+					break 'sample;
+				}
+
+				let pair = [pair[0], pair[1]];
+				let children = self.expand_beam(&sampled_beam, pair, ojama_rate, opponent_status, &instant_attack_count);
+				if children.is_empty() {
+					break;
+				}
+				sampled_beam = children;
+			}
 
-		if let Some(pos) = self.best_move.as_mut().unwrap().path.iter().position(|&x| x == Drop) {
-			let mut new = self.best_move.clone().unwrap().path;
-			new.truncate(pos + 1);
-			self.best_move.as_mut().unwrap().path = new;
+			//残らなかった置き方はビームの最低の評価として扱う
+			let floor = sampled_beam.last().unwrap().eval;
+			let mut best_evals = vec![floor; root_paths.len()];
+			for node in &sampled_beam {
+				best_evals[node.root] = best_evals[node.root].max(node.eval);
+			}
+			for (total, eval) in totals.iter_mut().zip(best_evals) {
+				*total += eval;
+			}
+			completed += 1;
 		}
 
+		let (root, eval, debug) = if completed == 0 {
+			(best.root, best.eval, best.debug.clone())
+		} else {
+			//ビームに残っている置き方の中から選ぶ、beamは評価の高い順なので最初に見つかったものがその置き方の最善
+			let best = beam.iter()
+				.fold(None::<&SearchNode>, |best, node| match best {
+					Some(best) if totals[best.root] >= totals[node.root] => Some(best),
+					_ => Some(node),
+				})
+				.unwrap();
+			(best.root, totals[best.root] / completed as f32, best.debug.clone())
+		};
+
+		self.best_move = Option::from(AIMove::new(eval, root_paths.swap_remove(root)));
+		self.debug = Option::from(debug);
+	}
+
+	///beamの全ての盤面にpairを置いて、次のビームを選ぶ
+	unsafe fn expand_beam(&mut self, beam: &[SearchNode], pair: [PuyoKind; 2], ojama_rate: &usize, opponent_status: &OpponentStatus, instant_attack_count: &u8) -> Vec<SearchNode> {
+		let current = MoveOrigin::spawn(&self.rules);
+
+		//collectは並列でもbeamの順に並ぶので、同じ評価の盤面の選ばれ方も変わらない
+		let expand = |node: &SearchNode| self.expand(node, &current, pair[1], pair[0], ojama_rate, opponent_status, instant_attack_count).into_iter().map(|(child, _)| child);
+		let children = if self.search_config.parallel {
+			beam.par_iter().flat_map_iter(expand).collect()
+		} else {
+			beam.iter().flat_map(expand).collect()
+		};
+
+		self.select(children, self.search_config.beam_width)
+	}
+
+	///同じ盤面になったものは評価の高い方だけを残し、評価の高い順にwidth個選ぶ
+	///置換表で上書きされて見つからなかった重複はそのまま残る
+	fn select(&mut self, nodes: Vec<SearchNode>, width: usize) -> Vec<SearchNode> {
+		let generation = self.transposition_table.next_generation();
+		let mut unique: Vec<SearchNode> = Vec::with_capacity(nodes.len());
+		for node in nodes {
+			let hash = node.board.hash();
+			match self.transposition_table.get(hash) {
+				Some(TableEntry::Node { generation: node_generation, index }) if node_generation == generation => {
+					if unique[index as usize].eval < node.eval {
+						unique[index as usize] = node;
+					}
+				}
+				_ => {
+					self.transposition_table.insert(hash, TableEntry::Node { generation, index: unique.len() as u32 });
+					unique.push(node);
+				}
+			}
+		}
+
+		unique.sort_by(|a, b| b.eval.total_cmp(&a.eval));
+		unique.truncate(width);
+		unique
+	}
+
+	///get_potential_chainの結果を置換表に残しておき、同じ盤面では計算し直さない
+	unsafe fn cached_potential_chain(&self, board: &Board, chain: u8, cleared_pos_flag: u8) -> Potential {
+		let colors = self.rules.colors();
+		let key = TranspositionTable::potential_key(board.hash(), chain, cleared_pos_flag, colors.len());
+		if let Some(TableEntry::Potential { chain, added_count }) = self.transposition_table.get(key) {
+			return Potential { chain, added_count, ..Potential::default() };
+		}
 
-		//	let mut debug = Debug::new();
-		/*		debug.pos = potential.added_pos;
-				debug.added = potential.added_count as usize;
-				debug.empty = potential.empty_around_count as usize;*/
-		//self.debug = Option::from(debug);
+		let mut potential = Potential::default();
+		AI::<NNEvaluator<MultilayeredNetwork>>::get_potential_chain(board, &board.get_heights(), &chain, &cleared_pos_flag, 0, &mut potential, 0, colors);
+		self.transposition_table.insert(key, TableEntry::Potential { chain: potential.chain, added_count: potential.added_count });
+		potential
 	}
 
-	///1,2つの同色ぷよを全68パターン仮想落下し、潜在的連鎖情報を取得
-	pub unsafe fn get_potential_chain_all(board: &Board) -> Potential {
+	///1,2つの同色ぷよを色ごとに全17パターン仮想落下し、潜在的連鎖情報を取得
+	pub unsafe fn get_potential_chain_all(board: &Board, colors: &[PuyoKind]) -> Potential {
 		let mut best_potential = Potential::default();
 		let mut best_potential_count = 0;
 
@@ -98,7 +263,7 @@ impl<E: Evaluator> AI<E> {
 			Self::simulate(put_board, &mut chain, &mut cleared_pos_flag);
 
 
-			Self::get_potential_chain(&put_board, &heights, &chain, &cleared_pos_flag, 1 + *added_count, &mut potential, 0);
+			Self::get_potential_chain(&put_board, &heights, &chain, &cleared_pos_flag, 1 + *added_count, &mut potential, 0, colors);
 			if best_potential.chain < potential.chain {
 				best_potential = Potential::new(potential.chain, potential.added_count, put_board.clone(), u8::MAX, ignite_pos);
 				best_potential_count = 1;
@@ -114,7 +279,7 @@ impl<E: Evaluator> AI<E> {
 
 
 		//ぷよの種類
-		for puyo_type in COLOR_PUYOS {
+		for &puyo_type in colors {
 			//縦
 			'put: for x in 1..=6u8 {
 				//落下するぷよの数
@@ -136,7 +301,7 @@ impl<E: Evaluator> AI<E> {
 		}
 
 		//横2
-		for puyo_type in COLOR_PUYOS {
+		for &puyo_type in colors {
 			//縦
 			'put: for x in 1..6u8 {
 				let mut board_clone = board.clone();
@@ -156,7 +321,7 @@ impl<E: Evaluator> AI<E> {
 	}
 	#[inline]
 	///仮想落下で、
-	pub unsafe fn get_instant_attack(board: &Board, ojama_rate: &usize) -> u8 {
+	pub unsafe fn get_instant_attack(board: &Board, ojama_rate: &usize, colors: &[PuyoKind]) -> u8 {
 		let mut instant_attack_count = 0;
 
 		let mut eval = |board: &Board| {
@@ -175,12 +340,8 @@ impl<E: Evaluator> AI<E> {
 					break;
 				}
 
-				elapsed_frame += FrameNeeded::VANISH_PUYO_ANIMATION;
 				let drop_count = test_board.drop_after_erased(&erase_mask);
-				if drop_count > 0 {
-					elapsed_frame += drop_count as usize * FrameNeeded::TEAR_PUYO_DROP_PER_1_BLOCK;
-					elapsed_frame += FrameNeeded::LAND_PUYO_ANIMATION;
-				}
+				elapsed_frame += FrameNeeded::chain_step(drop_count);
 
 				chain += 1;
 				score += temp_score as usize;
@@ -193,7 +354,7 @@ impl<E: Evaluator> AI<E> {
 
 
 		//ぷよの種類
-		for puyo_type in COLOR_PUYOS {
+		for &puyo_type in colors {
 			//縦
 			'put: for x in 1..=6u8 {
 				//落下するぷよの数
@@ -217,454 +378,169 @@ impl<E: Evaluator> AI<E> {
 		instant_attack_count
 	}
 
-	unsafe fn search_internal(&mut self,
-							  board: &Board,
-							  current: &PuyoStatus,
-							  next: &Vec<PuyoKind>,
-							  ojama: &OjamaStatus,
-							  center_puyo: PuyoKind,
-							  movable_puyo: PuyoKind,
-							  movements: &Vec<KeyType>,
-							  mut elapsed_frame: usize,
-							  score: usize,
-							  mut all_cleared: bool,
-							  ojama_rate: &usize,
-							  rng: &mut ThreadRng,
-							  opponent_status: &OpponentStatus,
-							  mut waste_chain_link: usize,
-							  instant_attack_count: &u8,
-							  mut attacked_value: usize,
-	) {
-		let mut places: HashMap<u16, (u8, PuyoStatus)> = HashMap::new();
-		let mut hash_position = HashMap::new();
-		Self::get_put_places(&board, &current, &mut hash_position, 0, &mut places, &(center_puyo as u8), &(movable_puyo as u8));
-
-		for place in places {
-			///操作ミノを適用しただけの盤面
-			let mut put_board = board.clone();
-			let mut put_place = Vector2::default();
-			put_board.put_puyo(&place.1.1, &center_puyo, &movable_puyo, &mut put_place);
-			///連鎖、落下のシミュレーションを実行した盤面
-			let mut sim_board = put_board.clone();
-			let mut ojama_clone = ojama.clone();
-			if ojama_clone.get_receivable_ojama_size() != 0 {
-				sim_board.try_put_ojama(&mut ojama_clone);
-			}
-
-			//	let mut waste_chain_link = 0;
-
-			//let mut new_score = score;
-			let mut new_score = 0;
-			let mut chain = 0u8;
-			let mut chain_one_side = 0u8;
-			let mut cleared_pos_flag = 0;
-
-			let mut erase_mask = BoardBit::default();
-			loop {
-				let temp_score = sim_board.erase_if_needed(&chain, &mut erase_mask, &mut waste_chain_link);
-				if temp_score == 0 {
-					break;
-				}
-
-				let board_popcount = erase_mask.popcnt128();
-				let left_popcount = _popcnt64(_mm_extract_epi64::<0>(erase_mask.0));
-				let right_popcount = _popcnt64(_mm_extract_epi64::<1>(erase_mask.0));
-
-				if board_popcount == left_popcount
-					|| board_popcount == right_popcount {
-					chain_one_side += 1;
-				}
-
-				elapsed_frame += FrameNeeded::VANISH_PUYO_ANIMATION;
-				let drop_count = sim_board.drop_after_erased(&erase_mask);
-				if drop_count > 0 {
-					elapsed_frame += drop_count as usize * FrameNeeded::TEAR_PUYO_DROP_PER_1_BLOCK;
-					elapsed_frame += FrameNeeded::LAND_PUYO_ANIMATION;
-				}
-
-				let mut new_x_pos_flag = 0u8;
-
-				let value = _mm_extract_epi16::<1>(erase_mask.0);
-				new_x_pos_flag |= (!(value == 0) as u8) << 1;
-				let value = _mm_extract_epi16::<2>(erase_mask.0);
-				new_x_pos_flag |= (!(value == 0) as u8) << 2;
-				let value = _mm_extract_epi16::<3>(erase_mask.0);
-				new_x_pos_flag |= (!(value == 0) as u8) << 3;
-				let value = _mm_extract_epi16::<4>(erase_mask.0);
-				new_x_pos_flag |= (!(value == 0) as u8) << 4;
-				let value = _mm_extract_epi16::<5>(erase_mask.0);
-				new_x_pos_flag |= (!(value == 0) as u8) << 5;
-				let value = _mm_extract_epi16::<6>(erase_mask.0);
-				new_x_pos_flag |= (!(value == 0) as u8) << 6;
-
-				if new_x_pos_flag != 0 {
-					cleared_pos_flag = new_x_pos_flag;
-				}
-
-				chain += 1;
-				new_score += temp_score as usize;
-			}
-
-
-			let all_ojama_size = ojama_clone.get_all_ojama_size();
-
-			let temp_attack = new_score / ojama_rate;
-			let left_attack = temp_attack.saturating_sub(all_ojama_size);
-			attacked_value += left_attack;
-
-			ojama_clone.use_ojama(temp_attack - left_attack);
-
-			new_score += score;
-
-			if !sim_board.is_empty_cell(DEAD_POSITION.x as i16, DEAD_POSITION.y as i16) {
-				continue;
-			}
-
-			if sim_board.is_same(&_mm_setzero_si128(),
-								 &_mm_set_epi64x(0b1111111111111111000000000000000100000000000000010000000000000001u64 as i64,
-												 0b0000000000000001000000000000000100000000000000011111111111111111u64 as i64),
-								 &_mm_setzero_si128()) {
-				all_cleared = true;
-			}
-
-			if all_cleared {
-				new_score += ALL_CLEAR_BONUS;
-				all_cleared = false;
-			}
-
-
-			let calculated_movement = Self::calculate_move(&hash_position, &place.1.1, current.position.x, current.position.y, current.rotation);
-
-			//TODO: 
-			elapsed_frame += calculated_movement.len() * FrameNeeded::MOVE;
-
-			let mut new_movements = movements.clone();
-			new_movements.extend(calculated_movement);
-
-
-			//path
-			if next.len() != 0 {
-				let new_current = PuyoStatus::new(Vector2::new(SPAWN_POS.0, SPAWN_POS.1), Rotation::new(3));
-
-				let mut new_next = next.clone();
-
-				let new_center_puyo = new_next.pop().unwrap();
-				let new_movable_puyo = new_next.pop().unwrap();
-
-
-				self.search_internal(&sim_board, &new_current, &new_next, &ojama_clone, new_center_puyo, new_movable_puyo, &new_movements, elapsed_frame, new_score, all_cleared, ojama_rate, rng, opponent_status, waste_chain_link, instant_attack_count, attacked_value);
-			} else {
-				let mut potential = Potential::default();
-				AI::<NNEvaluator<MultilayeredNetwork>>::get_potential_chain(&put_board, &put_board.get_heights(), &chain, &cleared_pos_flag, 0, &mut potential, 0);
-
-				/*	let mask = board.get_not_empty_board();
-					let mut diff_board = Board::default();
-					diff_board.0[0] = _mm_andnot_si128(mask.0, potential.diff_board.0[0]);
-					diff_board.0[1] = _mm_andnot_si128(mask.0, potential.diff_board.0[1]);
-					diff_board.0[2] = _mm_andnot_si128(mask.0, potential.diff_board.0[2]);
-					potential.diff_board = diff_board;*/
-
-				potential.ignite_pos = put_place;
-
-				//置く前と置いた後の差分で置いた場所を取得
-				let diff_board = _mm_xor_si128(board.get_not_empty_board().0, put_board.get_not_empty_board().0);
-				//置いた場所を一回り拡張
-				let neighbor_mask = BoardBit(diff_board).expand_1_without_mask();
-				//置いた後のboardとand演算して置いてある場所を列挙
-				let neighbor_flag = _mm_and_si128(neighbor_mask.0, put_board.get_not_empty_board().0);
-				let empty_count = neighbor_mask.popcnt128() - BoardBit(neighbor_flag).popcnt128();
-				potential.near_empty_count = empty_count as u8;
-
-
-				let mut debug = Debug::new();
-				debug.near_empty_count = potential.near_empty_count as usize;
-				debug.ignite_pos = potential.ignite_pos;
-				debug.waste_chain_link = waste_chain_link;
-				debug.one_side_chain_count = chain_one_side as usize;
-				debug.potential_added_count = potential.added_count as usize;
-
-				let eval = self.evaluator.evaluate(&put_board, &sim_board, &potential, &chain, &new_score, &(elapsed_frame as u32), &mut debug, &ojama_clone, ojama_rate, opponent_status, &waste_chain_link, &chain_one_side, instant_attack_count, &attacked_value);
-
-
-				//highest_evalよりも評価が高かったら、計算したpath、
-				if self.best_move == None || self.best_move.as_ref().unwrap().eval < eval {
-					//現在の位置（最初はplace）の位置ハッシュを求め、見つかる間pathの行動を登録し続ける、元の位置は引数のやつ
-					self.best_move = Option::from(AIMove::new(eval, new_movements));
-					self.debug = Option::from(debug);
-				}
-			}
+	///nodeの盤面にcenter_puyoとmovable_puyoを置ける全ての置き方を評価する、死ぬ置き方は除く
+	///置き方はMoveGeneratorの順に並ぶ
+	unsafe fn expand(&self,
+					 node: &SearchNode,
+					 current: &MoveOrigin,
+					 center_puyo: PuyoKind,
+					 movable_puyo: PuyoKind,
+					 ojama_rate: &usize,
+					 opponent_status: &OpponentStatus,
+					 instant_attack_count: &u8,
+	) -> Vec<(SearchNode, Vec<KeyType>)> {
+		let placements = MoveGenerator::generate(&node.board, current, center_puyo, movable_puyo, &self.rules.input_timing);
+		let evaluate = |worker: &mut E::Worker, placement: Placement| self.evaluate_placement(node, placement, center_puyo, movable_puyo, ojama_rate, opponent_status, instant_attack_count, worker);
+
+		//workerはスレッドごとに作るので、評価の途中でロックを取り合わない
+		if self.search_config.parallel {
+			placements.into_par_iter().map_init(|| self.evaluator.worker(), evaluate).flatten().collect()
+		} else {
+			let mut worker = self.evaluator.worker();
+			placements.into_iter().filter_map(|placement| evaluate(&mut worker, placement)).collect()
 		}
 	}
 
-	pub fn calculate_move(hash_position: &HashMap<u16, Path>, puyo_status: &PuyoStatus, x: i8, y: i8, rotation: Rotation) -> Vec<KeyType> {
-		//TODO: こういうvecとかなんとか
-		let mut vec = vec![Drop];
-		let mut new_puyo_status = puyo_status.clone();
+	///nodeの盤面にplacementの通りに置いて連鎖とお邪魔の落下を済ませ、評価する。死ぬならNone
+	unsafe fn evaluate_placement(&self,
+								 node: &SearchNode,
+								 placement: Placement,
+								 center_puyo: PuyoKind,
+								 movable_puyo: PuyoKind,
+								 ojama_rate: &usize,
+								 opponent_status: &OpponentStatus,
+								 instant_attack_count: &u8,
+								 worker: &mut E::Worker,
+	) -> Option<(SearchNode, Vec<KeyType>)> {
+		let board = &node.board;
+		let mut elapsed_frame = node.elapsed_frame;
+		let mut waste_chain_link = node.waste_chain_link;
+		let mut attacked_value = node.attacked_value;
+		let mut all_cleared = node.all_cleared;
+		///操作ミノを適用しただけの盤面
+		let mut put_board = board.clone();
+		let mut put_place = Vector2::default();
+		put_board.put_puyo(&placement.status, &center_puyo, &movable_puyo, &mut put_place);
+		///連鎖、落下のシミュレーションを実行した盤面
+		let mut sim_board = put_board.clone();
+		let mut ojama_clone = node.ojama.clone();
+		if ojama_clone.get_receivable_ojama_size() != 0 {
+			//実際の列はわからないので探索中は固定のシードで代用
+			let mut ojama_placer = OjamaPlacer::new(&0, OjamaDropOrder::Random);
+			sim_board.try_put_ojama(&mut ojama_clone, &mut ojama_placer, self.rules.max_ojama_per_drop);
+		}
+
+		let mut new_score = 0;
+		let mut chain = 0u8;
+		let mut chain_one_side = 0u8;
+		let mut cleared_pos_flag = 0;
 
+		let mut erase_mask = BoardBit::default();
 		loop {
-			if vec.len() > 40 {
-				//逆計算できなくね？ 
-				//dump
-				panic!("どこさまよってるの");
-				/*Debug::save_hashtable_as_csv(&hash_position, 0).unwrap();
-				Debug::save_hashtable_as_csv(&hash_position, 90).unwrap();
-				Debug::save_hashtable_as_csv(&hash_position, 270).unwrap();
-				Debug::save_hashtable_as_csv(&hash_position, 180).unwrap();*/
+			let temp_score = sim_board.erase_if_needed(&chain, &mut erase_mask, &mut waste_chain_link);
+			if temp_score == 0 {
+				break;
 			}
 
-			if x == new_puyo_status.position.x && y == new_puyo_status.position.y && new_puyo_status.rotation == rotation {
-				//所定の位置になったら終わり
-				vec.reverse();
-				return vec;
+			//0から3列目か4から7列目の片側だけで消えた
+			let erased_columns = erase_mask.column_flags();
+			if erased_columns & 0xF0 == 0
+				|| erased_columns & 0x0F == 0 {
+				chain_one_side += 1;
 			}
 
-			match hash_position.get(&new_puyo_status.create_hash(0, 0)) {
-				Some(item) => {
-					vec.push(item.key_type);
-					match item.key_type {
-						Right => {
-							new_puyo_status.position.x -= 1;
-						}
-						Left => {
-							new_puyo_status.position.x += 1;
-						}
-						KeyType::Top => {}
-						KeyType::Down => {}
-						KeyType::Drop => {}
-						RotateRight => {
-							//壁キックの代わりに事前保存を復元
-							//rotation変えてもdiffのこう変わらないとだめ
-							new_puyo_status.position.x = item.before_x;
-							new_puyo_status.position.y = item.before_y;
-							new_puyo_status.position_diff.x = item.before_x_diff;
-							new_puyo_status.position_diff.y = item.before_y_diff;
-
-							new_puyo_status.rotation.rotate_ccw();
-						}
-						RotateLeft => {
-							new_puyo_status.position.x = item.before_x;
-							new_puyo_status.position.y = item.before_y;
-							new_puyo_status.position_diff.x = item.before_x_diff;
-							new_puyo_status.position_diff.y = item.before_y_diff;
-
-							new_puyo_status.rotation.rotate_cw();
-						}
-						Rotate180 => {
-							new_puyo_status.position.x = item.before_x;
-							new_puyo_status.position.y = item.before_y;
-							new_puyo_status.position_diff.x = item.before_x_diff;
-							new_puyo_status.position_diff.y = item.before_y_diff;
+			let drop_count = sim_board.drop_after_erased(&erase_mask);
+			elapsed_frame += FrameNeeded::chain_step(drop_count);
 
-							new_puyo_status.rotation.rotate_180();
-						}
-					}
-				}
-				None => {
-					//noneだとまずい、戻るべき位置を指定しないと
-					panic!("目的地までつけないが？");
-				}
-			}
-		}
-	}
-
-	//置きうる場所列挙、同色とかどうする？
-	//盤面とcurrent
-	//再帰、一度位置が決定したらソフドロのみ
-	//	
-	unsafe fn get_put_places(board: &Board,
-							 puyo_status: &PuyoStatus,
-							 mut hash_position: &mut HashMap<u16, Path>,
-							 move_count: u8,
-							 mut results: &mut HashMap<u16, (u8, PuyoStatus)>,
-							 center_puyo: &u8,
-							 movable_puyo: &u8,
-	) {
-		let mut key;
-		//TODO: 最初に到達チェックできないかな？
-
-		//右移動
-		//さきにhash調べたほうがパフォーンス良さそう
-		if Env::is_valid_position(board, &puyo_status, 1, 0) {
-			key = puyo_status.create_hash(1, 0);
-			match hash_position.get_mut(&key) {
-				Some(value) => {
-					//すでに到達済みということはこの後も全て探索済みだからパス、
-					if value.move_count > move_count {
-						value.key_type = Right;
-						value.move_count = move_count + 1;
-
-						value.before_x = puyo_status.position.x;
-						value.before_y = puyo_status.position.y;
-						value.before_x_diff = puyo_status.position_diff.x;
-						value.before_y_diff = puyo_status.position_diff.y;
-					}
-				}
-				None => {
-					hash_position.insert(key, Path::new(Right, move_count + 1, puyo_status.position.x, puyo_status.position.y, puyo_status.position_diff.x as i8, puyo_status.position_diff.y as i8));
-
-					let mut new_puyo_status = puyo_status.clone();
-					Env::move_puyo(&board, &mut new_puyo_status, 1, 0);
-					Self::get_put_places(&board, &new_puyo_status, &mut hash_position, move_count + 1, &mut results, center_puyo, movable_puyo);
-				}
+			let new_x_pos_flag = erased_columns & 0b0111_1110;
+			if new_x_pos_flag != 0 {
+				cleared_pos_flag = new_x_pos_flag;
 			}
-		}
-
-
-		//左移動
-		if Env::is_valid_position(&board, &puyo_status, -1, 0) {
-			key = puyo_status.create_hash(-1, 0);
-			match hash_position.get_mut(&key) {
-				Some(value) => {
-					if value.move_count > move_count {
-						if value.move_count > move_count {
-							value.key_type = Left;
-							value.move_count = move_count + 1;
-
-							value.before_x = puyo_status.position.x;
-							value.before_y = puyo_status.position.y;
-							value.before_x_diff = puyo_status.position_diff.x;
-							value.before_y_diff = puyo_status.position_diff.y;
-						}
-					}
-				}
-				None => {
-					hash_position.insert(key, Path::new(Left, move_count + 1, puyo_status.position.x, puyo_status.position.y, puyo_status.position_diff.x as i8, puyo_status.position_diff.y as i8));
 
-					let mut new_puyo_status = puyo_status.clone();
-					Env::move_puyo(&board, &mut new_puyo_status, -1, 0);
-					Self::get_put_places(&board, &new_puyo_status, &mut hash_position, move_count + 1, &mut results, center_puyo, movable_puyo);
-				}
-			}
+			chain += 1;
+			new_score += temp_score as usize;
 		}
 
 
-		//右回転
-		let mut kick = Vector2::new(0, 0);
+		let all_ojama_size = ojama_clone.get_all_ojama_size();
 
-		if Env::is_valid_rotation(puyo_status, board, true, &mut kick) {
-			let mut new_puyo_status = puyo_status.clone();
-			Env::rotate_puyo(&mut new_puyo_status, 0);
-			Env::move_puyo(&board, &mut new_puyo_status, kick.x, kick.y);
+		let temp_attack = new_score / ojama_rate;
+		let left_attack = temp_attack.saturating_sub(all_ojama_size);
+		attacked_value += left_attack;
 
-			let key = new_puyo_status.create_hash(0, 0);
+		ojama_clone.use_ojama(temp_attack - left_attack);
 
-			match hash_position.get_mut(&key) {
-				Some(value) => {
-					if value.move_count > move_count {
-						value.key_type = RotateRight;//これいらんかも
-						value.move_count = move_count + 1;
+		new_score += node.score;
 
-						value.before_x = puyo_status.position.x;
-						value.before_y = puyo_status.position.y;
-						value.before_x_diff = puyo_status.position_diff.x;
-						value.before_y_diff = puyo_status.position_diff.y;
-					}
-				}
-				None => {
-					hash_position.insert(key, Path::new(RotateRight, move_count + 1, puyo_status.position.x, puyo_status.position.y, puyo_status.position_diff.x as i8, puyo_status.position_diff.y as i8));
-
-					Self::get_put_places(&board, &new_puyo_status, &mut hash_position, move_count + 1, &mut results, center_puyo, movable_puyo);
-				}
-			}
+		if !sim_board.is_empty_cell(DEAD_POSITION.x as i16, DEAD_POSITION.y as i16) {
+			return None;
 		}
 
-
-		//左回転
-		let mut kick = Vector2::new(0, 0);
-
-		if Env::is_valid_rotation(puyo_status, board, false, &mut kick) {
-			let mut new_puyo_status = puyo_status.clone();
-			Env::rotate_puyo(&mut new_puyo_status, 1);
-			Env::move_puyo(&board, &mut new_puyo_status, kick.x, kick.y);
-
-			let key = new_puyo_status.create_hash(0, 0);
-
-			match hash_position.get_mut(&key) {
-				Some(value) => {
-					if value.move_count > move_count {
-						value.key_type = RotateLeft;
-						value.move_count = move_count + 1;
-
-						value.before_x = puyo_status.position.x;
-						value.before_y = puyo_status.position.y;
-						value.before_x_diff = puyo_status.position_diff.x;
-						value.before_y_diff = puyo_status.position_diff.y;
-					}
-				}
-				None => {
-					hash_position.insert(key, Path::new(RotateLeft, move_count + 1, puyo_status.position.x, puyo_status.position.y, puyo_status.position_diff.x as i8, puyo_status.position_diff.y as i8));
-
-					Self::get_put_places(&board, &new_puyo_status, &mut hash_position, move_count + 1, &mut results, center_puyo, movable_puyo);
-				}
-			}
-		} else if move_count == 0 {
-			//180回転。回転ができなかった時に見る、初期状態飲み
-
-			let mut new_puyo_status = puyo_status.clone();
-			Env::rotate_puyo(&mut new_puyo_status, 2);
-			//Env::move_puyo(&board, &mut new_puyo_status, kick.x, kick.y);
-			if new_puyo_status.rotation.0 == 3 {
-				new_puyo_status.position.y -= 1;
-			} else if new_puyo_status.rotation.0 == 1 {
-				new_puyo_status.position.y += 1;
-			}
-
-			let key = new_puyo_status.create_hash(0, 0);
-
-			match hash_position.get_mut(&key) {
-				Some(value) => {
-					if value.move_count > move_count {
-						value.key_type = Rotate180;
-						value.move_count = move_count + 1;
-
-						value.before_x = puyo_status.position.x;
-						value.before_y = puyo_status.position.y;
-						value.before_x_diff = puyo_status.position_diff.x;
-						value.before_y_diff = puyo_status.position_diff.y;
-					}
-				}
-				None => {
-					hash_position.insert(key, Path::new(Rotate180, move_count + 1, puyo_status.position.x, puyo_status.position.y, puyo_status.position_diff.x as i8, puyo_status.position_diff.y as i8));
-
-					Self::get_put_places(&board, &new_puyo_status, &mut hash_position, move_count + 1, &mut results, center_puyo, movable_puyo);
-				}
-			}
+		if sim_board.is_all_cleared() {
+			all_cleared = true;
 		}
 
-
-		//ハードドロップ
-		//この検索時点での最速だからおかしくなる、
-		{
-			let new_puyo_status = puyo_status.clone();
-
-			let mut hash: u16 = 0;
-
-			hash |= (*center_puyo as u16 & 0b111) << 0;
-			hash |= (*movable_puyo as u16 & 0b111) << 3;
-			hash |= (puyo_status.position.x as u16 & 0b111) << 6;
-			hash |= ((puyo_status.position.x + puyo_status.position_diff.x) as u16 & 0b111) << 9;
-
-
-			let data = results.entry(hash);
-
-			match data {
-				Entry::Occupied(mut value) => {
-					let value = value.get_mut();
-					if value.0 > move_count {
-						value.0 = move_count;
-						value.1 = new_puyo_status;
-					}
-				}
-				Entry::Vacant(_) => {
-					results.insert(hash, (move_count, new_puyo_status));
-				}
-			}
+		if all_cleared {
+			new_score += self.rules.all_clear_bonus;
+			all_cleared = false;
 		}
-	}
 
+		elapsed_frame += placement.frames;
+
+		let mut potential = self.cached_potential_chain(&put_board, chain, cleared_pos_flag);
+		potential.ignite_pos = put_place;
+
+		//置く前と置いた後の差分で置いた場所を取得
+		let diff_board = board.get_not_empty_board() ^ put_board.get_not_empty_board();
+		//置いた場所を一回り拡張
+		let neighbor_mask = diff_board.expand_1_without_mask();
+		//置いた後のboardとand演算して置いてある場所を列挙
+		let neighbor_flag = neighbor_mask & put_board.get_not_empty_board();
+		let empty_count = neighbor_mask.popcnt128() - neighbor_flag.popcnt128();
+		potential.near_empty_count = empty_count as u8;
+
+
+		let mut debug = Debug::new();
+		debug.near_empty_count = potential.near_empty_count as usize;
+		debug.ignite_pos = potential.ignite_pos;
+		debug.waste_chain_link = waste_chain_link;
+		debug.one_side_chain_count = chain_one_side as usize;
+		debug.potential_added_count = potential.added_count as usize;
+
+		let eval = self.evaluator.evaluate(&mut EvalContext {
+			put_board: &put_board,
+			sim_board: &sim_board,
+			potential: &potential,
+			chain,
+			score: new_score,
+			elapse_frame: elapsed_frame as u32,
+			ojama: &ojama_clone,
+			ojama_rate: *ojama_rate,
+			opponent_status,
+			waste_chain_link,
+			one_side_chain_count: chain_one_side,
+			instant_attack_count: *instant_attack_count,
+			attack_value: attacked_value,
+			colors: self.rules.colors(),
+			debug: &mut debug,
+		}, worker);
+
+		Some((SearchNode {
+			board: sim_board,
+			ojama: ojama_clone,
+			score: new_score,
+			elapsed_frame,
+			all_cleared,
+			waste_chain_link,
+			attacked_value,
+			root: node.root,
+			eval,
+			debug,
+		}, placement.path))
+	}
 	///現在の連鎖フラグの箇所にぷよをドロップして連鎖が伸びるかを見る
 	#[inline]
-	pub unsafe fn get_potential_chain(board: &Board, heights: &[u16; 8], current_chain: &u8, cleared_pos_flag: &u8, added_count: u8, best_potential: &mut Potential, current_depth: usize) {
+	pub unsafe fn get_potential_chain(board: &Board, heights: &[u16; 8], current_chain: &u8, cleared_pos_flag: &u8, added_count: u8, best_potential: &mut Potential, current_depth: usize, colors: &[PuyoKind]) {
 		/*if current_depth > POTENTIAL_SEARCH_DEPTH {
 			return;
 		}*/
@@ -683,7 +559,7 @@ impl<E: Evaluator> AI<E> {
 		//フラグが立ってるx一覧を取得
 		'pos_x: for x in 1..=6u8 {
 			if ((*cleared_pos_flag >> x) & 1) == 1 {
-				'puyo: for puyo in COLOR_PUYOS {
+				'puyo: for &puyo in colors {
 					//置いて連鎖実行した結果、置く前の連鎖と比べて連鎖が伸びたら
 					///最後の連鎖のx情報
 					let mut test_board = board.clone();
@@ -704,7 +580,7 @@ impl<E: Evaluator> AI<E> {
 
 					//連鎖が伸びた場合
 					if *current_chain < test_chain {
-						Self::get_potential_chain(&test_board, &test_heights, &test_chain, &test_cleared_pos_flag, added_count + 1, best_potential, current_depth + 1);
+						Self::get_potential_chain(&test_board, &test_heights, &test_chain, &test_cleared_pos_flag, added_count + 1, best_potential, current_depth + 1, colors);
 
 						//最高連鎖
 						if best_potential.chain < test_chain {
@@ -738,24 +614,9 @@ impl<E: Evaluator> AI<E> {
 			if temp_score == 0 {
 				break;
 			}
-			let mut new_x_pos_flag = 0u8;
-
 			//*x_pos_flag = 0u8;
 			//マスクの情報を使って消えるラインを特定
-
-			let value = _mm_extract_epi16::<1>(erase_mask.0);
-			new_x_pos_flag |= (!(value == 0) as u8) << 1;
-			let value = _mm_extract_epi16::<2>(erase_mask.0);
-			new_x_pos_flag |= (!(value == 0) as u8) << 2;
-			let value = _mm_extract_epi16::<3>(erase_mask.0);
-			new_x_pos_flag |= (!(value == 0) as u8) << 3;
-			let value = _mm_extract_epi16::<4>(erase_mask.0);
-			new_x_pos_flag |= (!(value == 0) as u8) << 4;
-			let value = _mm_extract_epi16::<5>(erase_mask.0);
-			new_x_pos_flag |= (!(value == 0) as u8) << 5;
-			let value = _mm_extract_epi16::<6>(erase_mask.0);
-			new_x_pos_flag |= (!(value == 0) as u8) << 6;
-
+			let new_x_pos_flag = erase_mask.column_flags() & 0b0111_1110;
 
 			if new_x_pos_flag != 0 {
 				*x_pos_flag = new_x_pos_flag;
@@ -806,14 +667,7 @@ mod tests {
 			if temp_score == 0 { //panic!();
 			}
 
-			let mut board_split_aligned: SplitBoard = SplitBoard([0; 8]);
-			_mm_store_si128(board_split_aligned.0.as_mut_ptr() as *mut __m128i, erase_mask.0);
-
-
-			let mut cleared_pos_flag = 0u8;
-			for i in 1..7 {
-				cleared_pos_flag |= (!(board_split_aligned.0[i] == 0) as u8) << i;
-			}
+			let cleared_pos_flag = erase_mask.column_flags() & 0b0111_1110;
 
 
 			let mut chain = 0;
@@ -834,7 +688,7 @@ mod tests {
 			let heights = board.get_heights();
 			let mut potential = Potential::default();
 
-			AI::<NNEvaluator<MultilayeredNetwork>>::get_potential_chain(&board, &heights, &chain, &cleared_pos_flag, 1, &mut potential, 0);
+			AI::<NNEvaluator<MultilayeredNetwork>>::get_potential_chain(&board, &heights, &chain, &cleared_pos_flag, 1, &mut potential, 0, Rules::default().colors());
 			dbg!(potential.diff_board.to_str());
 			dbg!(potential);
 		}
@@ -1,33 +1,52 @@
 ﻿//pub mod test_evaluator;
-//pub mod simple_evaluator;
-
-//pub mod simple_evaluator;
+pub mod simple_evaluator;
 pub mod nn_evaluator;
 //mod test_evaluator;
 
 use env::board::Board;
 use env::ojama_status::OjamaStatus;
-use env::vector2::Vector2;
+use env::puyo_kind::PuyoKind;
 use crate::debug::Debug;
 use crate::opponent_status::OpponentStatus;
 use crate::potential::Potential;
 
-pub trait Evaluator {
-	fn evaluate(&mut self,
-				put_board: &Board,
-				sim_board: &Board,
-				potential: &Potential,
-				chain: &u8,
-				score: &usize,
-				elapse_frame: &u32,
-				debug: &mut Debug,
-				ojama: &OjamaStatus,
-				ojama_rate: &usize,
-				opponent_status: &OpponentStatus,
-				waste_chain_link: &usize,
-				one_side_chain_count: &u8,
-				instant_attack_count: &u8,
-				attack_value: &usize,
-	) -> f32;
+///1つの置き方を評価するのに使える情報、評価に使うものを増やす時はここに足す
+pub struct EvalContext<'a> {
+	///操作ぷよを置いただけの盤面
+	pub put_board: &'a Board,
+	///連鎖とお邪魔の落下まで済ませた盤面
+	pub sim_board: &'a Board,
+	///put_boardの潜在連鎖、ignite_posは置いた場所
+	pub potential: &'a Potential,
+	///この置き方で起きた連鎖数
+	pub chain: u8,
+	///探索の最初の手からの得点の合計
+	pub score: usize,
+	///探索の最初の手からの経過フレーム
+	pub elapse_frame: u32,
+	///相殺した後に受けるお邪魔
+	pub ojama: &'a OjamaStatus,
+	pub ojama_rate: usize,
+	pub opponent_status: &'a OpponentStatus,
+	///発火した際、理論値の連鎖数*4からどれだけ連結が多かったか
+	pub waste_chain_link: usize,
+	///左右どちらか3列のみで連鎖した数
+	pub one_side_chain_count: u8,
+	///探索を始めた盤面ですぐ打てる連鎖の数
+	pub instant_attack_count: u8,
+	///探索の最初の手から相殺しきって送った火力
+	pub attack_value: usize,
+	///ルールで使う色
+	pub colors: &'a [PuyoKind],
+	///評価の内訳を残す、AIの結果と一緒に表示される
+	pub debug: &'a mut Debug,
+}
+
+///探索中に複数のスレッドから同時に呼ばれる、evaluateは同じ入力なら同じ値を返す
+///評価の途中で書き換えるものはWorkerに持たせる、workerで評価するスレッドごとに1つ作って渡す
+pub trait Evaluator: Send + Sync {
+	type Worker: Send;
+	fn worker(&self) -> Self::Worker;
+	fn evaluate(&self, context: &mut EvalContext, worker: &mut Self::Worker) -> f32;
 	fn clone(&self) -> Self;
-}
\ No newline at end of file
+}
@@ -1,16 +1,10 @@
-﻿use std::arch::x86_64::{_mm_andnot_si128, _mm_or_si128, _mm_slli_epi16, _mm_slli_si128, _mm_srli_epi16, _mm_srli_si128};
-use env::board::{Board, WIDTH_WITH_BORDER};
+﻿use env::board::Board;
 use env::board_bit::BoardBit;
 use env::env::DEAD_POSITION;
-use env::ojama_status::OjamaStatus;
 use env::puyo_kind::{COLOR_PUYOS, PuyoKind};
-use crate::debug::Debug;
-use crate::evaluator::Evaluator;
-use crate::ignite_key::IgniteKey;
-use crate::opponent_status::OpponentStatus;
-use crate::potential::Potential;
+use crate::evaluator::{EvalContext, Evaluator};
+use crate::features::find_links;
 
-static PUYOS: [PuyoKind; 4] = [PuyoKind::Blue, PuyoKind::Green, PuyoKind::Red, PuyoKind::Yellow];
 /*pub static DIRECTIONS: [(i32, i32); 4] = [
 	(1, 0),   // 右
 	(-1, 0),  // 左
@@ -18,18 +12,28 @@ static PUYOS: [PuyoKind; 4] = [PuyoKind::Blue, PuyoKind::Green, PuyoKind::Red, P
 	(0, -1)   // 下
 ];*/
 
+///発火に必要なぷよの数と、その連鎖の得点
+struct IgniteKey {
+	ignite_count: u8,
+	score: u32,
+}
+
+///連結数や発火点などに手で重みを付けて足すだけの評価、学習したネットワークと比べる基準にする
 pub struct SimpleEvaluator {
 	weight: [f32; 8],
 }
 
 impl Evaluator for SimpleEvaluator {
-	fn evaluate(&mut self, board: &Board, sim_board: &Board, chain: &u8, score: &usize, elapse_frame: &u32, debug: &mut Debug, ojama: &OjamaStatus, ojama_rate: &usize, best_potential: &Potential, opponent_status: &OpponentStatus, waste_chain_link: &usize) -> f32 {
-		panic!();
+	type Worker = ();
+
+	fn worker(&self) {}
+
+	fn evaluate(&self, context: &mut EvalContext, _worker: &mut ()) -> f32 {
+		let sim_board = context.sim_board;
 		//	Console::print_board(&board);
 		let mut result = 0.;
 		unsafe {
 			if !sim_board.is_empty_cell(DEAD_POSITION.x as i16, DEAD_POSITION.y as i16) {
-				debug.dead = true;
 				return f32::MIN;
 			}
 		}
@@ -99,8 +103,8 @@ impl Evaluator for SimpleEvaluator {
 		//火力数、経過フレーム、火力数2、経過フレーム2、キーぷよの数、キーぷよの所有数
 		//相手の状況なども
 		//let mut keys = Vec::new();
-		let mut key = IgniteKey::new(PuyoKind::Empty, 0, 0, 0, 0);
-		unsafe { Self::calc_num_of_key_puyos(&sim_board, &mut key); }
+		let mut key = IgniteKey { ignite_count: 0, score: 0 };
+		unsafe { Self::calc_num_of_key_puyos(sim_board, context.colors, &mut key); }
 
 		//keysを使ってそれぞれの起こりうる連鎖を評価
 		//選ぶのはニューラルネットワーク？	いやさすがに無理、防御ならお邪魔を減らすことの重みが強い、攻撃なら
@@ -125,7 +129,7 @@ impl Evaluator for SimpleEvaluator {
 		unsafe {
 			for color_puyo in COLOR_PUYOS {
 				let mask = sim_board.get_bits(color_puyo)/*.mask_board_12()*/;
-				Self::find_links(&mask, &mut link2, &mut link3);
+				find_links(&mask, &mut link2, &mut link3);
 			}
 		}
 
@@ -134,7 +138,7 @@ impl Evaluator for SimpleEvaluator {
 
 		result += key.ignite_count as f32 * self.weight[2];
 		result += key.score as f32 * self.weight[3];
-		result += *score as f32 * self.weight[4];
+		result += context.score as f32 * self.weight[4];
 
 		let height = unsafe { sim_board.get_heights() };
 		result += height[DEAD_POSITION.x as usize] as f32 * self.weight[5];
@@ -147,14 +151,16 @@ impl Evaluator for SimpleEvaluator {
 				bumpness += (heights[x] as i16 - heights[x + 1] as i16).abs();
 			}
 
-			for x in 1..=6 {
-				height_sum += heights[x];
+			for height in &heights[1..=6] {
+				height_sum += height;
 			}
 		}
 
 		result += bumpness as f32 * self.weight[6];
 		result += height_sum as f32 * self.weight[7];
 
+		context.debug.link2_count = link2 as usize;
+		context.debug.link3_count = link3 as usize;
 
 //		(link2 * 2 + link3 * 5) as f32
 		result
@@ -162,12 +168,20 @@ impl Evaluator for SimpleEvaluator {
 
 	fn clone(&self) -> Self {
 		SimpleEvaluator {
-			weight: self.weight.clone()
+			weight: self.weight
 		}
 	}
 }
 
+impl Default for SimpleEvaluator {
+	///連結を増やして3列目を低く保ち、少ないぷよで大きく発火できる形を好む
+	fn default() -> Self {
+		Self::new([1., 3., -2., 0.01, 0.001, -4., -1., 0.])
+	}
+}
+
 impl SimpleEvaluator {
+	///重みは2連結、3連結、発火に必要なぷよ数、発火した時の得点、得点、3列目の高さ、でこぼこ、高さの合計の順
 	pub fn new(weight: [f32; 8]) -> SimpleEvaluator {
 		SimpleEvaluator {
 			weight
@@ -176,18 +190,22 @@ impl SimpleEvaluator {
 
 
 	///発火に必要なぷよの数と種類を列挙、[ぷよの種類、必要な数、段差変更度]
-	unsafe fn calc_num_of_key_puyos(board: &Board, main: &mut IgniteKey/*, keys: &mut Vec<IgniteKey>*/) {
+	unsafe fn calc_num_of_key_puyos(board: &Board, colors: &[PuyoKind], main: &mut IgniteKey/*, keys: &mut Vec<IgniteKey>*/) {
 		//それぞれの列に1~3置いて可能性のある連鎖を列挙 6列*3通り
 		//それぞれの列に横で2~3個置いて可能性のある連鎖を列挙 5通り+4通り
 
-		for puyo_type in PUYOS {
+		for &puyo_type in colors {
 			for puyo_count in 1..=2 {
-				for x in 1..WIDTH_WITH_BORDER - 1 {
+				'put: for x in 1..=6u8 {
 					let mut new_board = board.clone();
+					let mut heights = board.get_heights();
 
 					//ここから上(-1)に積みあげる
 					for _i in 0..puyo_count {
-						new_board.put_puyo_1(x, &puyo_type);
+						if heights[x as usize] > 12 {
+							continue 'put;
+						}
+						new_board.put_puyo_direct(&x, &mut heights, &puyo_type);
 					}
 
 					let mut chain: u8 = 0;
@@ -205,8 +223,6 @@ impl SimpleEvaluator {
 					}
 
 					if main.score < score {
-						main.puyo_kind = puyo_type;
-						main.chain = 0;
 						main.score = score;
 						main.ignite_count = puyo_count;
 					}
@@ -257,34 +273,6 @@ impl SimpleEvaluator {
 			*/
 	}
 
-	pub unsafe fn find_links(mask: &BoardBit, link2: &mut i32, link3: &mut i32) {
-		//連結を2つもってる→3連結、周りのマスクも作成
-		//右との連結、下との連結を取得し、3連結とのnot-and
-
-
-		let right = BoardBit(_mm_srli_si128::<2>(mask.0)) & *mask;
-		let left = BoardBit(_mm_slli_si128::<2>(mask.0)) & *mask;
-		let up = BoardBit(_mm_srli_epi16::<1>(mask.0)) & *mask;
-		let down = BoardBit(_mm_slli_epi16::<1>(mask.0)) & *mask;
-
-		let up_down_and = up & down;
-		let left_right_and = left & right;
-		let up_down_or = up | down;
-		let left_right_or = left | right;
-
-		let twos = up_down_and | left_right_and | (up_down_or & left_right_or);
-
-		*link3 += twos.popcnt128();
-		let link_3_mask = twos.expand_1(mask);
-
-		//link3のマスクをして、調べる。downとrightのorをマスク
-		let mask2_frags = BoardBit(_mm_andnot_si128(link_3_mask.0, _mm_or_si128(down.0, right.0)));
-		*link2 += BoardBit(mask2_frags.0).popcnt128();
-
-		//	let two_down = BoardBit(_mm_slli_epi16::<1>(twos.0)) & twos;
-//		let two_left = BoardBit(_mm_slli_si128::<2>(twos.0)) & twos;
-	}
-
 	//
 }
 
@@ -0,0 +1,275 @@
+﻿use std::io::{self, BufRead, Write};
+
+use serde_derive::{Deserialize, Serialize};
+
+use env::board_bit::BoardBit;
+use env::puyo_kind::{COLOR_PUYOS, PuyoKind};
+
+use crate::evaluator::EvalContext;
+use crate::opener_book::Template;
+
+///V1の特徴量の名前、extractの並びと同じ
+const V1_NAMES: [&str; 31] = [
+	"link2",
+	"link3",
+	"chain",
+	"score",
+	"elapse_frame",
+	"bump",
+	"template_score",
+	"ojama_size",
+	"time_to_receive",
+	"ojama_offset",
+	"ojama_in_board",
+	"potential_added_count",
+	"potential_chain",
+	"opponent_height",
+	"opponent_ojama",
+	"opponent_instant_attack",
+	"opponent_potential_added_count",
+	"opponent_potential_chain",
+	"height1",
+	"height2",
+	"height3",
+	"height4",
+	"height5",
+	"height6",
+	"waste_chain_link",
+	"one_side_chain_count",
+	"near_empty_count",
+	"ignite_x",
+	"ignite_y",
+	"instant_attack_count",
+	"attack_value",
+];
+
+///ネットワークの入力にする特徴量の組み合わせ、並びや中身を変える時は新しい版を足して前の版の並びは変えない
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FeatureSet {
+	///連結数、連鎖、お邪魔、潜在連鎖、相手の状態、各列の高さなど31個
+	#[default]
+	V1,
+}
+
+impl FeatureSet {
+	///特徴量の数、ネットワークの入力の数と同じ
+	pub fn count(self) -> usize {
+		self.names().len()
+	}
+
+	///CSVの列名などに使う
+	pub fn names(self) -> &'static [&'static str] {
+		match self {
+			FeatureSet::V1 => &V1_NAMES,
+		}
+	}
+
+	///置いた結果から特徴量をnamesの順に並べる、連結数と潜在連鎖はdebugにも残す
+	pub unsafe fn extract(self, context: &mut EvalContext, templates: &[Template]) -> Vec<f32> {
+		match self {
+			FeatureSet::V1 => Self::extract_v1(context, templates),
+		}
+	}
+
+	unsafe fn extract_v1(context: &mut EvalContext, templates: &[Template]) -> Vec<f32> {
+		let EvalContext { sim_board, potential, chain, score, elapse_frame, ojama, ojama_rate, opponent_status, waste_chain_link, one_side_chain_count, instant_attack_count, attack_value, .. } = *context;
+
+		let nn_added_count = potential.added_count;
+		let nn_ojama_count_in_board = sim_board.get_bits(PuyoKind::Ojama).popcnt128();
+		let height = sim_board.get_heights();
+
+		let mut nn_link2 = 0u32;
+		let mut nn_link3 = 0u32;
+		for color_puyo in COLOR_PUYOS {
+			let mask = sim_board.get_bits(color_puyo).mask_board_12();
+			find_links(&mask, &mut nn_link2, &mut nn_link3);
+		}
+
+		let mut nn_bump = 0;
+		for x in 1..6 {
+			nn_bump += (height[x] as i16 - height[x + 1] as i16).abs();
+		}
+
+		let mut nn_highest_template_score = 0;
+		for template in templates {
+			let score = template.evaluate(sim_board);
+			if nn_highest_template_score < score {
+				nn_highest_template_score = score;
+			}
+		}
+
+		let debug = &mut *context.debug;
+		debug.link2_count = nn_link2 as usize;
+		debug.link3_count = nn_link3 as usize;
+		debug.potential_chain_count = potential.chain as usize;
+
+		//全消し状態はスコアとして
+		//相殺した後のお邪魔と送る火力+2
+		//連鎖の位置平均を算出し、前連鎖との距離の合計
+		//盤面のお邪魔数+1
+		//毎フレーム更新される相手の盤面情報　仮想発火の連鎖数、ありうる最大の連鎖数、
+		//置いたぷよの
+		let nn_ojama_size = ojama.get_all_ojama_size();
+		vec![
+			nn_link2 as f32,//2連結数
+			nn_link3 as f32,//3連結数
+			chain as f32,//連鎖数
+			score as f32,//連鎖スコア
+			elapse_frame as f32,//連鎖経過時間
+			nn_bump as f32,//盤面のでこぼこ
+			nn_highest_template_score as f32,//土台
+			nn_ojama_size as f32,//相手からもらってるお邪魔の合計
+			ojama.get_time_to_receive() as f32,//お邪魔を受けるまでの時間
+			(nn_ojama_size as isize - (score / ojama_rate) as isize) as f32,//自分の火力で相殺できるか
+			nn_ojama_count_in_board as f32,//盤面上のお邪魔数
+			nn_added_count as f32,//ポテンシャル連鎖の追加数
+			potential.chain as f32,//ポテンシャル連鎖の連鎖数
+			opponent_status.board_height as f32,//相手の盤面の高さ合計
+			opponent_status.board_ojama_count as f32,//相手の盤面のお邪魔合計
+			opponent_status.instant_attack as f32,//相手の盤面の一定時間内の2列以上の火力
+			opponent_status.potential_added_count as f32,//相手の盤面のポテンシャル連鎖の発火カウント
+			opponent_status.potential_chain_count as f32,//相手の盤面のポテンシャル連鎖の連鎖数
+			height[1] as f32,
+			height[2] as f32,
+			height[3] as f32,
+			height[4] as f32,
+			height[5] as f32,
+			height[6] as f32,
+			waste_chain_link as f32,//発火した際、理論値の連鎖数*4からどれだけ離れていた（連結が多かったか）
+			one_side_chain_count as f32,//左右どちらか3列のみで連鎖した数
+			potential.near_empty_count as f32,//発火点周辺の空白マス数
+			potential.ignite_pos.x as f32,//発火点のx座標
+			potential.ignite_pos.y as f32,//発火点のy座標
+			instant_attack_count as f32,
+			attack_value as f32,
+		]
+	}
+}
+
+///特徴量ごとに(x - offset) / scaleで値の大きさを揃える
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Normalization {
+	pub offset: Vec<f32>,
+	pub scale: Vec<f32>,
+}
+
+impl Normalization {
+	///何も変えない
+	pub fn identity(count: usize) -> Self {
+		Normalization {
+			offset: vec![0.; count],
+			scale: vec![1.; count],
+		}
+	}
+
+	///平均を0、標準偏差を1にする
+	pub fn mean_std(samples: &[Vec<f32>], count: usize) -> Self {
+		let mut normalization = Self::identity(count);
+		if samples.is_empty() {
+			return normalization;
+		}
+
+		let n = samples.len() as f64;
+		for i in 0..count {
+			let mean = samples.iter().map(|sample| sample[i] as f64).sum::<f64>() / n;
+			let variance = samples.iter().map(|sample| (sample[i] as f64 - mean).powi(2)).sum::<f64>() / n;
+			normalization.offset[i] = mean as f32;
+			normalization.scale[i] = Self::nonzero(variance.sqrt() as f32);
+		}
+
+		normalization
+	}
+
+	///最小値を0、最大値を1にする
+	pub fn min_max(samples: &[Vec<f32>], count: usize) -> Self {
+		let mut normalization = Self::identity(count);
+		if samples.is_empty() {
+			return normalization;
+		}
+
+		for i in 0..count {
+			let min = samples.iter().map(|sample| sample[i]).fold(f32::INFINITY, f32::min);
+			let max = samples.iter().map(|sample| sample[i]).fold(f32::NEG_INFINITY, f32::max);
+			normalization.offset[i] = min;
+			normalization.scale[i] = Self::nonzero(max - min);
+		}
+
+		normalization
+	}
+
+	#[inline]
+	pub fn apply(&self, features: &mut [f32]) {
+		for ((value, offset), scale) in features.iter_mut().zip(&self.offset).zip(&self.scale) {
+			*value = (*value - offset) / scale;
+		}
+	}
+
+	///どのサンプルでも同じ値の特徴量は割らずに0にする
+	fn nonzero(scale: f32) -> f32 {
+		if scale > f32::EPSILON { scale } else { 1. }
+	}
+}
+
+///1行目にfeature_setの特徴量の名前を書く
+pub fn write_csv_header<W: Write>(writer: &mut W, feature_set: FeatureSet) -> io::Result<()> {
+	writeln!(writer, "{}", feature_set.names().join(","))
+}
+
+///extractの結果を1行書く
+pub fn write_csv_row<W: Write>(writer: &mut W, features: &[f32]) -> io::Result<()> {
+	let row = features.iter().map(|value| value.to_string()).collect::<Vec<_>>();
+	writeln!(writer, "{}", row.join(","))
+}
+
+///write_csv_headerとwrite_csv_rowで書いたCSVを読む、列名がfeature_setと違えばエラー
+pub fn read_csv<R: BufRead>(reader: R, feature_set: FeatureSet) -> io::Result<Vec<Vec<f32>>> {
+	let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidData, message);
+
+	let mut lines = reader.lines();
+	let header = lines.next().transpose()?.unwrap_or_default();
+	if header != feature_set.names().join(",") {
+		return Err(invalid(format!("header does not match {feature_set:?}")));
+	}
+
+	let mut samples = Vec::new();
+	for (index, line) in lines.enumerate() {
+		let line = line?;
+		let sample = line.split(',')
+			.map(|value| value.parse::<f32>())
+			.collect::<Result<Vec<_>, _>>()
+			.map_err(|e| invalid(format!("line {}: {e}", index + 2)))?;
+		if sample.len() != feature_set.count() {
+			return Err(invalid(format!("line {}: expected {} values", index + 2, feature_set.count())));
+		}
+		samples.push(sample);
+	}
+
+	Ok(samples)
+}
+
+///色ごとのマスクの2連結と3連結以上の数を足す
+#[inline]
+pub unsafe fn find_links(mask: &BoardBit, link2: &mut u32, link3: &mut u32) {
+	//連結を2つもってる→3連結、周りのマスクも作成
+	//右との連結、下との連結を取得し、3連結とのnot-and
+
+
+	let right = mask.u_shift() & *mask;
+	let left = mask.d_shift() & *mask;
+	let up = mask.r_shift() & *mask;
+	let down = mask.l_shift() & *mask;
+
+	let up_down_and = up & down;
+	let left_right_and = left & right;
+	let up_down_or = up | down;
+	let left_right_or = left | right;
+
+	let twos = up_down_and | left_right_and | (up_down_or & left_right_or);
+	*link3 += twos.popcnt128() as u32;
+	let link_3_mask = twos.expand_1(mask);
+
+	//link3のマスクをして、調べる。downとrightのorをマスク
+	let mask2_frags = (down | right).and_not(&link_3_mask);
+	*link2 += mask2_frags.popcnt128() as u32;
+}
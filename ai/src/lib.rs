@@ -1,10 +1,15 @@
 pub use env::puyo_kind;
+pub use env::key_type;
 
 pub mod evaluator;
+pub mod features;
 pub mod build_ai;
+pub mod async_search;
 pub mod ai_move;
-pub mod key_type;
-pub mod path;
+pub mod move_generator;
+pub mod network_file;
+pub mod search_config;
+pub mod transposition_table;
 pub mod debug;
 pub mod opener_book;
 pub mod opponent_status;
@@ -0,0 +1,241 @@
+﻿use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+
+use env::board::Board;
+use env::env::{Env, FrameNeeded, SPAWN_POS};
+use env::input_timing::{FallClock, InputClock, InputTiming};
+use env::puyo_kind::PuyoKind;
+use env::puyo_status::PuyoStatus;
+use env::rotation::Rotation;
+use env::rules::Rules;
+use env::vector2::Vector2;
+
+use crate::key_type::KeyType;
+
+///create_hashが取りうる値の数、回転2bit・x3bit・y4bit
+const STATE_COUNT: usize = 1 << 9;
+///置き方の重複を調べるキーの数、軸のx3bit・子のx3bit・上下1bit
+const PLACEMENT_COUNT: usize = 1 << 7;
+///横移動の続き方、なし・右1回・右2回以上・左1回・左2回以上
+const MOVE_RUN_COUNT: usize = 5;
+///Drop以外の入力
+const KEYS: [KeyType; 6] = [KeyType::Right, KeyType::Left, KeyType::Down, KeyType::RotateRight, KeyType::RotateLeft, KeyType::Rotate180];
+
+///ぷよを置ける場所と、そこへ最速で動かす入力
+#[derive(Debug, Clone)]
+pub struct Placement {
+	///Dropを入力する直前の位置
+	pub status: PuyoStatus,
+	///最後がDropの入力列
+	pub path: Vec<KeyType>,
+	///MoveOrigin::frameからDropを押すまでのフレーム、出現したぷよならInputTiming::framesと同じ
+	pub frames: usize,
+}
+
+///入力を始める時の操作中のぷよと、入力の間隔と自然落下の状態
+#[derive(Debug, Clone)]
+pub struct MoveOrigin {
+	pub status: PuyoStatus,
+	pub input_clock: InputClock,
+	///frameの自然落下を進める前の状態
+	pub fall_clock: FallClock,
+	///最初の入力を押すフレーム、input_clockと同じ数え方
+	pub frame: usize,
+}
+
+impl MoveOrigin {
+	///envの今のフレームから入力する
+	pub fn from_env(env: &Env) -> Self {
+		MoveOrigin {
+			status: env.puyo_status.clone(),
+			input_clock: env.input_clock,
+			fall_clock: FallClock::new(env.rules.fall_interval_frame, env.fall_frame),
+			frame: env.current_frame,
+		}
+	}
+
+	///出現したぷよ、操作できるようになるまでの間も自然落下は数える
+	///その間に1段下がるほど短い間隔は考えない
+	pub fn spawn(rules: &Rules) -> Self {
+		let mut fall_clock = FallClock::new(rules.fall_interval_frame, 0);
+		for _ in 0..FrameNeeded::SPAWN_NEW_PUYO {
+			fall_clock.tick();
+		}
+
+		MoveOrigin {
+			status: PuyoStatus::new(Vector2::new(SPAWN_POS.0, SPAWN_POS.1), Rotation::new(3)),
+			input_clock: InputClock::new(0),
+			fall_clock,
+			frame: FrameNeeded::SPAWN_NEW_PUYO,
+		}
+	}
+}
+
+///入力の間隔を考えて、置ける場所を全て列挙する
+pub enum MoveGenerator {}
+
+struct Node {
+	status: PuyoStatus,
+	clock: InputClock,
+	///frameの自然落下を進める前の状態
+	fall: FallClock,
+	///keyを押したフレーム
+	frame: usize,
+	parent: usize,
+	key: KeyType,
+}
+
+impl MoveGenerator {
+	///originから入力で行ける全ての位置を調べ、盤面が変わる置き方ごとにDropまでが最も早い経路を返す
+	///押すまでの間は自然落下させ、その間に設置されてしまう経路は使わない
+	///位置と横移動の続き方が同じなら先に着いた方だけを残す
+	pub unsafe fn generate(board: &Board, origin: &MoveOrigin, center_puyo: PuyoKind, movable_puyo: PuyoKind, timing: &InputTiming) -> Vec<Placement> {
+		let mut settled = [false; STATE_COUNT * MOVE_RUN_COUNT];
+		let mut nodes = vec![Node {
+			status: origin.status.clone(),
+			clock: origin.input_clock,
+			fall: origin.fall_clock,
+			frame: origin.frame,
+			parent: 0,
+			key: KeyType::Drop,
+		}];
+		let mut queue = BinaryHeap::from([Reverse((Self::press_frame(&nodes[0], timing, KeyType::Drop), 0))]);
+
+		let mut placed = [false; PLACEMENT_COUNT];
+		let mut placements = Vec::new();
+
+		//Dropを押せるフレームの早い順に確定する、同じフレームなら先に見つけた入力の少ない方
+		while let Some(Reverse((drop_frame, index))) = queue.pop() {
+			let node = &nodes[index];
+			let state = Self::state_index(&node.status, &node.clock);
+			if settled[state] {
+				continue;
+			}
+			settled[state] = true;
+
+			//クイックターンの2回目を待つ間に設置されるならDropできない
+			let mut drop_status = node.status.clone();
+			if Self::fall_until(board, &mut drop_status, &mut node.fall.clone(), node.frame, drop_frame) {
+				let placement_hash = Self::placement_hash(&drop_status, center_puyo, movable_puyo);
+				if !placed[placement_hash] {
+					placed[placement_hash] = true;
+					placements.push(Placement {
+						status: drop_status,
+						path: Self::path_to(&nodes, index),
+						frames: drop_frame - origin.frame,
+					});
+				}
+			}
+
+			for key in KEYS {
+				let node = &nodes[index];
+				let frame = Self::press_frame(node, timing, key);
+				let mut status = node.status.clone();
+				let mut fall = node.fall;
+				if !Self::fall_until(board, &mut status, &mut fall, node.frame, frame) {
+					continue;
+				}
+				if !Self::transition(board, &mut status, key) {
+					continue;
+				}
+
+				let mut clock = node.clock;
+				clock.press(timing, key, frame);
+				if key == KeyType::Down {
+					fall.reset();
+				}
+
+				if !settled[Self::state_index(&status, &clock)] {
+					let node = Node { status, clock, fall, frame, parent: index, key };
+					queue.push(Reverse((Self::press_frame(&node, timing, KeyType::Drop), nodes.len())));
+					nodes.push(node);
+				}
+			}
+		}
+
+		placements
+	}
+
+	///nodeの後にkeyを押せる最も早いフレーム
+	fn press_frame(node: &Node, timing: &InputTiming, key: KeyType) -> usize {
+		node.clock.earliest(timing, key).max(node.frame)
+	}
+
+	///fromからtoの前のフレームまで自然落下させる、接地して設置されたらfalse
+	unsafe fn fall_until(board: &Board, status: &mut PuyoStatus, fall: &mut FallClock, from: usize, to: usize) -> bool {
+		for _ in from..to {
+			if fall.tick() && !Env::move_puyo(board, status, 0, -1) {
+				return false;
+			}
+		}
+
+		true
+	}
+
+	///位置と、押しっぱなしを続けられるかで状態を分ける
+	fn state_index(status: &PuyoStatus, clock: &InputClock) -> usize {
+		let (direction, count) = clock.move_run();
+		let run = match direction {
+			1 => count.min(2),
+			-1 => 2 + count.min(2),
+			_ => 0,
+		};
+		status.create_hash(0, 0) as usize * MOVE_RUN_COUNT + run
+	}
+
+	///1回の入力で移る位置、Envの同じ入力と同じ動きをする。動かなければfalse
+	///クイックターンは両側が塞がれた時だけ、下に動けない時のDownは設置してしまうので使わない
+	unsafe fn transition(board: &Board, status: &mut PuyoStatus, key: KeyType) -> bool {
+		//RotateRightはEnv::rotate_ccw、RotateLeftはEnv::rotate_cwで入力される
+		let rotate = |status: &mut PuyoStatus, cw: bool, r_type: u8| {
+			let mut kick = Vector2::new(0, 0);
+			if !Env::is_valid_rotation(status, board, cw, &mut kick) {
+				return false;
+			}
+			Env::rotate_puyo(status, r_type);
+			Env::move_puyo(board, status, kick.x, kick.y);
+			true
+		};
+
+		match key {
+			KeyType::Right => Env::move_puyo(board, status, 1, 0),
+			KeyType::Left => Env::move_puyo(board, status, -1, 0),
+			KeyType::Down => Env::move_puyo(board, status, 0, -1),
+			KeyType::RotateRight => rotate(status, true, 0),
+			KeyType::RotateLeft => rotate(status, false, 1),
+			KeyType::Rotate180 => {
+				let mut kick = Vector2::new(0, 0);
+				if Env::is_valid_rotation(status, board, true, &mut kick) || Env::is_valid_rotation(status, board, false, &mut kick) {
+					return false;
+				}
+				Env::rotate_puyo_180(board, status);
+				true
+			}
+			KeyType::Top | KeyType::Drop => false,
+		}
+	}
+
+	///置いた後の盤面が同じになる位置は同じ値、同じ色のペアは向きを区別しない
+	fn placement_hash(status: &PuyoStatus, center_puyo: PuyoKind, movable_puyo: PuyoKind) -> usize {
+		let center_x = status.position.x as usize;
+		let movable_x = (status.position.x + status.position_diff.x) as usize;
+
+		if center_puyo == movable_puyo {
+			return (center_x.min(movable_x) & 0b111) | ((center_x.max(movable_x) & 0b111) << 3);
+		}
+
+		let movable_below = (status.position_diff.y < 0) as usize;
+		(center_x & 0b111) | ((movable_x & 0b111) << 3) | (movable_below << 6)
+	}
+
+	fn path_to(nodes: &[Node], mut index: usize) -> Vec<KeyType> {
+		let mut path = vec![KeyType::Drop];
+		while index != 0 {
+			path.push(nodes[index].key);
+			index = nodes[index].parent;
+		}
+
+		path.reverse();
+		path
+	}
+}
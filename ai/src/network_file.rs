@@ -0,0 +1,106 @@
+﻿use std::fs;
+use std::io::{Error, ErrorKind};
+use std::path::{Path, PathBuf};
+
+use revonet::neuro::NeuralNetwork;
+use serde::de::DeserializeOwned;
+use serde::ser;
+use serde_derive::{Deserialize, Serialize};
+
+use crate::evaluator::nn_evaluator::NNEvaluator;
+use crate::features::{FeatureSet, Normalization};
+
+///正規化を入れる前のファイルと、学習で保存されたネットワークだけのファイルの隣に置く正規化
+pub const SIDECAR_FILE_NAME: &str = "normalization.json";
+
+///ネットワークのjson、入力の並びと正規化も一緒に保存する
+///正規化を入れる前のファイルはネットワークだけが書かれているので、その形でも読める
+///ネットワークだけのファイルは同じディレクトリにSIDECAR_FILE_NAMEがあればその正規化を使う
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NetworkFile<T> {
+	pub network: T,
+	#[serde(default)]
+	pub feature_set: FeatureSet,
+	///Noneなら特徴量をそのまま入力する
+	#[serde(default)]
+	pub normalization: Option<Normalization>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum StoredNetwork<T> {
+	File(NetworkFile<T>),
+	Plain(T),
+}
+
+///ネットワークだけを保存する学習のために、入力の並びと正規化を別のファイルに書く
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NetworkSidecar {
+	#[serde(default)]
+	pub feature_set: FeatureSet,
+	pub normalization: Normalization,
+}
+
+impl NetworkSidecar {
+	///dirにSIDECAR_FILE_NAMEで保存して、そのパスを返す
+	pub fn save<P: AsRef<Path>>(&self, dir: P) -> Result<PathBuf, Error> {
+		let path = dir.as_ref().join(SIDECAR_FILE_NAME);
+		fs::write(&path, serde_json::to_string(self)?)?;
+		Ok(path)
+	}
+
+	///network_pathと同じディレクトリにあれば読む
+	pub fn find<P: AsRef<Path>>(network_path: P) -> Result<Option<Self>, Error> {
+		let path = network_path.as_ref().with_file_name(SIDECAR_FILE_NAME);
+		if !path.is_file() {
+			return Ok(None);
+		}
+
+		Ok(Some(serde_json::from_str(&fs::read_to_string(path)?)?))
+	}
+}
+
+impl<T: ser::Serialize + DeserializeOwned> NetworkFile<T> {
+	pub fn new(network: T) -> Self {
+		NetworkFile {
+			network,
+			feature_set: FeatureSet::default(),
+			normalization: None,
+		}
+	}
+
+	pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+		let json = serde_json::to_string(self)?;
+		fs::write(path, json)
+	}
+
+	pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+		let json = fs::read_to_string(&path)?;
+		let file = match serde_json::from_str(&json)? {
+			StoredNetwork::File(file) => file,
+			StoredNetwork::Plain(network) => {
+				let mut file = Self::new(network);
+				if let Some(sidecar) = NetworkSidecar::find(&path)? {
+					file.feature_set = sidecar.feature_set;
+					file.normalization = Some(sidecar.normalization);
+				}
+				file
+			}
+		};
+
+		if let Some(normalization) = &file.normalization {
+			let count = file.feature_set.count();
+			if normalization.offset.len() != count || normalization.scale.len() != count {
+				return Err(Error::new(ErrorKind::InvalidData, format!("normalization does not have {count} features")));
+			}
+		}
+
+		Ok(file)
+	}
+}
+
+impl<T: NeuralNetwork> NetworkFile<T> {
+	pub unsafe fn into_evaluator(self) -> NNEvaluator<T> {
+		NNEvaluator::with_features(self.network, self.feature_set, self.normalization)
+	}
+}
@@ -1,11 +1,9 @@
-﻿use std::arch::x86_64::{__m128i, _mm_and_si128};
-
-use env::board::{Board};
+﻿use env::board::{Board};
 use env::board_bit::BoardBit;
 use env::puyo_kind::COLOR_PUYOS;
 
 #[derive(Debug, Clone)]
-pub struct Template(pub Box<[__m128i]>);
+pub struct Template(pub Box<[BoardBit]>);
 
 impl Template {
 	#[inline]
@@ -19,14 +17,14 @@ impl Template {
 		//	let mut used_mask = _mm_setzero_si128();
 
 			for (index, test) in template.iter().enumerate() {
-				let test_expand = BoardBit(*test).expand_1_without_mask().0;
+				let test_expand = test.expand_1_without_mask();
 
 
 				//色ぷよ抽出
 				let mask = board.get_bits(color_puyo);
 				//テンプレの適応度、testでくり抜く
-				let extract = BoardBit(_mm_and_si128(mask.0, *test));
-				let extract_expand_1 = BoardBit(_mm_and_si128(mask.0, test_expand));
+				let extract = mask & *test;
+				let extract_expand_1 = mask & test_expand;
 
 				let conformity_score = extract.popcnt128() as u8;
 				let conformity_score_expand_1 = extract_expand_1.popcnt128() as u8;
@@ -13,7 +13,8 @@ pub struct OpponentStatus {
 }
 
 impl OpponentStatus {
-	pub unsafe fn new(board: &Board) -> Self {
+	///ojama_rateは相手の今のレート、すぐ打てる連鎖の火力に使う。colorsはルールで使う色
+	pub unsafe fn new(board: &Board, ojama_rate: &usize, colors: &[PuyoKind]) -> Self {
 		let mut opponent_status = Self::default();
 
 		let heights = board.get_heights();
@@ -22,8 +23,8 @@ impl OpponentStatus {
 		}
 
 		opponent_status.board_ojama_count = board.get_bits(PuyoKind::Ojama).popcnt128() as usize;
-		let result_potential = AI::<NNEvaluator<MultilayeredNetwork>>::get_potential_chain_all(board);
-		let result_instant_attack_count = AI::<NNEvaluator<MultilayeredNetwork>>::get_instant_attack(board,&70);
+		let result_potential = AI::<NNEvaluator<MultilayeredNetwork>>::get_potential_chain_all(board, colors);
+		let result_instant_attack_count = AI::<NNEvaluator<MultilayeredNetwork>>::get_instant_attack(board, ojama_rate, colors);
 
 
 		opponent_status.potential_added_count = result_potential.added_count as usize;
@@ -0,0 +1,44 @@
+﻿use serde_derive::{Deserialize, Serialize};
+
+///AI::searchの探索の広さと深さ
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SearchConfig {
+	///1手ごとに評価の高い順に残す盤面の数
+	pub beam_width: usize,
+	///カレントを含めて何手先まで置くか、見えているネクストより深い分は袋から引いたツモで補う
+	pub depth: usize,
+	///見えていないツモを引き直す回数、置き方ごとに結果の評価を平均する
+	pub sample_count: usize,
+	///Someならこの時間を過ぎた時点の結果で置き方を決める、カレントの置き方は必ず全て評価する
+	pub time_budget_ms: Option<u64>,
+	///置き方の評価をrayonのスレッドプールで並列に行う、結果は1スレッドの時と同じ
+	#[serde(default = "default_parallel")]
+	pub parallel: bool,
+	///ツモを引き直す乱数の元、盤面と混ぜるので同じ局面なら同じ置き方を選ぶ
+	#[serde(default)]
+	pub seed: u64,
+}
+
+fn default_parallel() -> bool {
+	true
+}
+
+impl SearchConfig {
+	///カレントとネクスト2つまで、引き直しはしない
+	pub const fn visible() -> Self {
+		SearchConfig {
+			beam_width: 32,
+			depth: 3,
+			sample_count: 0,
+			time_budget_ms: None,
+			parallel: true,
+			seed: 0,
+		}
+	}
+}
+
+impl Default for SearchConfig {
+	fn default() -> Self {
+		Self::visible()
+	}
+}
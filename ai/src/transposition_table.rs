@@ -0,0 +1,100 @@
+﻿use std::sync::atomic::{AtomicU64, Ordering};
+
+///置換表に残す値
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TableEntry {
+	///generation回目に選んだビームの候補のindex番目と同じ盤面
+	Node { generation: u32, index: u32 },
+	///get_potential_chainの結果、盤面が同じなら何度探索しても変わらない
+	Potential { chain: u8, added_count: u8 },
+}
+
+impl TableEntry {
+	///下位2bitが種類、0は空のスロット
+	fn pack(self) -> u64 {
+		match self {
+			TableEntry::Node { generation, index } => 1 | (index as u64) << 2 | (generation as u64) << 32,
+			TableEntry::Potential { chain, added_count } => 2 | (chain as u64) << 8 | (added_count as u64) << 16,
+		}
+	}
+
+	fn unpack(data: u64) -> Option<Self> {
+		match data & 0b11 {
+			1 => Some(TableEntry::Node { generation: (data >> 32) as u32, index: (data as u32) >> 2 }),
+			2 => Some(TableEntry::Potential { chain: (data >> 8) as u8, added_count: (data >> 16) as u8 }),
+			_ => None,
+		}
+	}
+}
+
+///盤面のハッシュで引く固定サイズの表、同じ場所に入るものは後から入れた方で上書きする
+///ビームサーチの重複の除去と潜在連鎖の計算で共有する
+///スロットはキーと値のxorと値の組で、並列に書き込まれて途中の状態を読んでもキーが合わないので見つからない扱いになる
+pub struct TranspositionTable {
+	slots: Vec<[AtomicU64; 2]>,
+	///Nodeが今選んでいるビームのものか調べる
+	generation: u32,
+}
+
+impl TranspositionTable {
+	///2^16個、1つのAIで1MB
+	pub const DEFAULT_SIZE_LOG2: u32 = 16;
+
+	pub fn new(size_log2: u32) -> Self {
+		TranspositionTable {
+			slots: (0..1usize << size_log2).map(|_| [AtomicU64::new(0), AtomicU64::new(0)]).collect(),
+			generation: 0,
+		}
+	}
+
+	#[inline]
+	pub fn get(&self, key: u64) -> Option<TableEntry> {
+		let [checked, data] = &self.slots[self.slot_index(key)];
+		let data = data.load(Ordering::Relaxed);
+		if checked.load(Ordering::Relaxed) ^ data != key {
+			return None;
+		}
+
+		TableEntry::unpack(data)
+	}
+
+	///探索中に複数のスレッドから呼べる
+	#[inline]
+	pub fn insert(&self, key: u64, entry: TableEntry) {
+		let [checked, data] = &self.slots[self.slot_index(key)];
+		let entry = entry.pack();
+		checked.store(key ^ entry, Ordering::Relaxed);
+		data.store(entry, Ordering::Relaxed);
+	}
+
+	///ビームを選び直すたびに呼ぶ、前のNodeは使われなくなる
+	#[inline]
+	pub fn next_generation(&mut self) -> u32 {
+		self.generation = self.generation.wrapping_add(1);
+		self.generation
+	}
+
+	pub fn size_log2(&self) -> u32 {
+		self.slots.len().trailing_zeros()
+	}
+
+	pub fn clear(&mut self) {
+		for slot in &mut self.slots {
+			*slot = [AtomicU64::new(0), AtomicU64::new(0)];
+		}
+	}
+
+	///潜在連鎖のキー、連鎖数と最後に消えた列と色数でも結果が変わるので盤面のハッシュに混ぜる
+	#[inline]
+	pub fn potential_key(board_hash: u64, chain: u8, cleared_pos_flag: u8, color_count: usize) -> u64 {
+		let mut z = (chain as u64 | (cleared_pos_flag as u64) << 8 | (color_count as u64) << 16).wrapping_add(0x9E3779B97F4A7C15);
+		z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+		z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+		board_hash ^ z ^ (z >> 31)
+	}
+
+	#[inline]
+	fn slot_index(&self, key: u64) -> usize {
+		key as usize & (self.slots.len() - 1)
+	}
+}
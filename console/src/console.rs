@@ -1,4 +1,5 @@
 ﻿use std::io::{stdout, Write};
+use std::time::Duration;
 
 use crossterm::{cursor, queue};
 use crossterm::cursor::{DisableBlinking, Hide};
@@ -78,6 +79,24 @@ impl Console {
 						   kind: KeyEventKind::Press,
 						   state: KeyEventState::NONE
 					   }) => "180".to_owned(),
+			Event::Key(KeyEvent {
+						   code: KeyCode::Char('z'),
+						   modifiers: KeyModifiers::NONE,
+						   kind: KeyEventKind::Press,
+						   state: KeyEventState::NONE
+					   }) => "undo".to_owned(),
+			Event::Key(KeyEvent {
+						   code: KeyCode::Char('h'),
+						   modifiers: KeyModifiers::NONE,
+						   kind: KeyEventKind::Press,
+						   state: KeyEventState::NONE
+					   }) => "hint".to_owned(),
+			Event::Key(KeyEvent {
+						   code: KeyCode::Char('q'),
+						   modifiers: KeyModifiers::NONE,
+						   kind: KeyEventKind::Press,
+						   state: KeyEventState::NONE
+					   }) => "quit".to_owned(),
 			Event::Key(KeyEvent {
 						   code: KeyCode::Enter,
 						   modifiers: KeyModifiers::NONE,
@@ -88,6 +107,81 @@ impl Console {
 			_ => "".to_owned()
 		}
 	}
+	///リプレイ操作用の入力、timeoutまでに押されなければNone
+	pub fn get_replay_input(timeout: Duration) -> Option<String> {
+		if !crossterm::event::poll(timeout).unwrap() {
+			return None;
+		}
+
+		match crossterm::event::read().unwrap() {
+			Event::Key(KeyEvent { code, kind: KeyEventKind::Press, .. }) => {
+				match code {
+					KeyCode::Right => Some("step".to_owned()),
+					KeyCode::Left => Some("back".to_owned()),
+					KeyCode::Up => Some("next_lock".to_owned()),
+					KeyCode::Down => Some("prev_lock".to_owned()),
+					KeyCode::Char(' ') => Some("play".to_owned()),
+					KeyCode::Char('q') => Some("quit".to_owned()),
+					_ => None
+				}
+			}
+			_ => None
+		}
+	}
+
+	///対戦中の操作、get_inputと同じキーに加えて矢印キーも使える。timeoutまでに押されなければNone
+	pub fn get_battle_input(timeout: Duration) -> Option<String> {
+		if !crossterm::event::poll(timeout).unwrap() {
+			return None;
+		}
+
+		match crossterm::event::read().unwrap() {
+			Event::Key(KeyEvent { code, kind: KeyEventKind::Press | KeyEventKind::Repeat, .. }) => {
+				match code {
+					KeyCode::Right | KeyCode::Char('.') => Some("right".to_owned()),
+					KeyCode::Left | KeyCode::Char('m') => Some("left".to_owned()),
+					KeyCode::Down | KeyCode::Char(',') => Some("soft_drop".to_owned()),
+					KeyCode::Up | KeyCode::Char('k') => Some("drop".to_owned()),
+					KeyCode::Char('c') => Some("cw".to_owned()),
+					KeyCode::Char('x') => Some("ccw".to_owned()),
+					KeyCode::Char('v') => Some("180".to_owned()),
+					KeyCode::Char('r') => Some("retry".to_owned()),
+					KeyCode::Char('q') | KeyCode::Esc => Some("quit".to_owned()),
+					_ => None
+				}
+			}
+			_ => None
+		}
+	}
+
+	///printした盤面の上に、置く予定のぷよを印付きで重ねる。座標は盤面の(x, y)
+	pub fn print_ghost(player_index: usize, puyos: &[(i8, i8, PuyoKind)]) {
+		let mut stdout = stdout();
+		for &(x, y, puyo_kind) in puyos {
+			if !(0..HEIGHT_WITH_BORDER as i8).contains(&y) {
+				continue;
+			}
+
+			queue!(stdout,
+				cursor::MoveTo((x * 2) as u16, (HEIGHT_WITH_BORDER as i8 - 1 - y) as u16 + (player_index * 20) as u16),
+				SetBackgroundColor(Self::get_color(&puyo_kind)),
+				Print("()")).unwrap();
+		}
+
+		queue!(stdout, SetBackgroundColor(Color::Black)).unwrap();
+		stdout.flush().unwrap();
+	}
+
+	pub fn print_message(line: u16, message: &str) {
+		let mut stdout = stdout();
+		queue!(stdout,
+			cursor::MoveTo(0, line),
+			SetBackgroundColor(Color::Black),
+			Clear(ClearType::CurrentLine),
+			Print(message)).unwrap();
+		stdout.flush().unwrap();
+	}
+
 	#[allow(unused_must_use)]
 	pub fn print_board(board: &Board) {
 		let mut stdout = stdout();
@@ -145,7 +239,7 @@ impl Console {
 
 		unsafe {
 			//	let puyo_kind = Ojama;
-			for puyo_kind in [PuyoKind::Yellow, PuyoKind::Red, PuyoKind::Blue, PuyoKind::Green, PuyoKind::Ojama, PuyoKind::Wall] {
+			for puyo_kind in [PuyoKind::Yellow, PuyoKind::Red, PuyoKind::Blue, PuyoKind::Green, PuyoKind::Purple, PuyoKind::Ojama, PuyoKind::Wall] {
 				let bitboard = env.board.get_bits(puyo_kind);
 
 
@@ -220,7 +314,7 @@ impl Console {
 	  SetBackgroundColor(Self::get_color(&env.next[1][1])),
 		Print("  "));
 
-		let mut ojama_size = unsafe { env.ojama.get_all_ojama_size() };
+		let mut ojama_size = env.ojama.get_all_ojama_size();
 		let mut yokoku_ojama = String::new();
 
 		let mut ojama = |yokoku_size: usize| {
@@ -249,20 +343,18 @@ impl Console {
 
 		queue!(stdout,SetBackgroundColor(Color::Black));
 
-		unsafe {
-			queue!(stdout, cursor::MoveTo(0 , (16+player_index*20) as u16),
-				Clear(ClearType::CurrentLine),
-	  SetBackgroundColor(Color::Black),
-		Print(yokoku_ojama),
-			cursor::MoveTo(0 , (17+player_index*20) as u16),
-				Clear(ClearType::CurrentLine),
-			Print(format!("{}{}", "連鎖数:", env.debug_status.current_chain_count)),
-		cursor::MoveTo(0 , (18+player_index*20) as u16),
-				Clear(ClearType::CurrentLine),
-			Print(format!("{}{:?}", "raw:", env.ojama.get_raw())),
-		
-		);
-		}
+		queue!(stdout, cursor::MoveTo(0 , (16+player_index*20) as u16),
+			Clear(ClearType::CurrentLine),
+  SetBackgroundColor(Color::Black),
+	Print(yokoku_ojama),
+		cursor::MoveTo(0 , (17+player_index*20) as u16),
+			Clear(ClearType::CurrentLine),
+		Print(format!("{}{}", "連鎖数:", env.debug_status.current_chain_count)),
+	cursor::MoveTo(0 , (18+player_index*20) as u16),
+			Clear(ClearType::CurrentLine),
+		Print(format!("{}{:?}", "pending:", env.ojama.pending().iter().map(|ojama| (ojama.size, ojama.receive_frame)).collect::<Vec<_>>())),
+	
+	);
 
 
 		queue!(stdout, cursor::MoveTo((( 0)*2) as u16  , (( 40 )) as u16));
@@ -280,11 +372,10 @@ impl Console {
 			PuyoKind::Blue => Color::Blue,
 			PuyoKind::Red => Color::Red,
 			PuyoKind::Green => Color::Green,
-			//	PuyoKind::Purple => Color::Rgb { r: 128, g: 0, b: 128 },
+			PuyoKind::Purple => Color::Magenta,
 			PuyoKind::Yellow => Color::Yellow,
 			PuyoKind::Ojama => Color::Grey,
 			PuyoKind::Wall => Color::DarkBlue,
-			PuyoKind::Preserved => panic!()
 		}
 	}
 }
@@ -1,16 +1,16 @@
 ﻿use std::arch::x86_64::{__m128i, _mm_and_si128, _mm_andnot_si128, _mm_cmpeq_epi32, _mm_cmpeq_epi64, _mm_extract_epi16, _mm_insert_epi16, _mm_load_si128, _mm_or_si128, _mm_set_epi16, _mm_set_epi64x, _mm_setr_epi16, _mm_setzero_si128, _mm_store_si128, _mm_test_all_ones, _pext_u32, _popcnt32};
 use std::mem;
-use rand::prelude::SliceRandom;
-use rand::rngs::ThreadRng;
-use rand::thread_rng;
 
 use crate::board_bit::BoardBit;
-use crate::env::{MAX_OJAMA_RECEIVE_COUNT, OJAMA_POS};
+use crate::board_mask::{column_mask, GHOST_ROW, VANISH_ROW};
+use crate::ojama_placer::OjamaPlacer;
 use crate::ojama_status::OjamaStatus;
 use crate::puyo_kind::{COLOR_PUYOS, PuyoKind};
 use crate::puyo_status::PuyoStatus;
+use crate::score_bonus::{get_chain_bonus, get_color_bonus, get_link_bonus};
 use crate::split_board::SplitBoard;
 use crate::vector2::Vector2;
+use crate::zobrist;
 
 pub const WIDTH: u8 = 6;
 pub const WIDTH_WITH_BORDER: u8 = 8;
@@ -18,17 +18,47 @@ pub const WIDTH_WITH_BORDER: u8 = 8;
 pub const HEIGHT: u8 = 14;
 pub const HEIGHT_WITH_BORDER: u8 = 16;
 
+///2つ目はzobristのハッシュ、ぷよを置く・消す・お邪魔を降らせるときに変わったマスの分だけ更新する
 #[derive(Debug, Clone)]
-pub struct Board(pub [__m128i; 3]);
+pub struct Board(pub [__m128i; 3], u64);
 
 
 impl Board {
 	#[inline]
 	pub unsafe fn default() -> Board {
-		Board {
-			0: [_mm_setzero_si128(), _mm_set_epi64x(0b1111111111111111000000000000000100000000000000010000000000000001u64 as i64, 0b0000000000000001000000000000000100000000000000011111111111111111u64 as i64), _mm_setzero_si128()],
+		Self::with_hash([_mm_setzero_si128(), _mm_set_epi64x(0b1111111111111111000000000000000100000000000000010000000000000001u64 as i64, 0b0000000000000001000000000000000100000000000000011111111111111111u64 as i64), _mm_setzero_si128()])
+	}
+
+	#[inline]
+	unsafe fn with_hash(raw: [__m128i; 3]) -> Board {
+		let mut board = Board(raw, 0);
+		board.1 = board.columns_hash(0xFF);
+		board
+	}
+
+	///盤面のハッシュ、同じ盤面なら同じ値
+	#[inline]
+	pub fn hash(&self) -> u64 {
+		self.1
+	}
 
+	///columnsのbitが立っている列のハッシュ、全ての列なら盤面のハッシュと同じ
+	pub unsafe fn columns_hash(&self, columns: u8) -> u64 {
+		let mut v1: SplitBoard = SplitBoard([0; 8]);
+		let mut v2: SplitBoard = SplitBoard([0; 8]);
+		let mut v3: SplitBoard = SplitBoard([0; 8]);
+		_mm_store_si128(v1.0.as_mut_ptr() as *mut __m128i, self.0[0]);
+		_mm_store_si128(v2.0.as_mut_ptr() as *mut __m128i, self.0[1]);
+		_mm_store_si128(v3.0.as_mut_ptr() as *mut __m128i, self.0[2]);
+
+		let mut hash = 0;
+		for x in 0..8 {
+			if (columns >> x) & 1 == 1 {
+				hash ^= zobrist::column_hash(x, v1.0[x], v2.0[x], v3.0[x]);
+			}
 		}
+
+		hash
 	}
 
 	/*	#[inline]
@@ -40,7 +70,7 @@ impl Board {
 	pub fn clone(&self) -> Self {
 		unsafe {
 			let new_data = [self.0[0], self.0[1], self.0[2]];
-			Board(new_data)
+			Board(new_data, self.1)
 		}
 	}
 
@@ -54,7 +84,7 @@ impl Board {
 	pub unsafe fn put_puyo(&mut self, puyo_status: &PuyoStatus, center: &PuyoKind, movable: &PuyoKind, put_pos: &mut Vector2) -> u8 {
 		let mut drop_count: u8;
 
-		//この方法の場合、14段目上書きになるけど、移動できないから問題ない
+		//14段目に置かれる分はset_flag_under_vanish_rowで消える
 		let mut heights = self.get_heights();
 
 		put_pos.x = puyo_status.position.x;
@@ -68,18 +98,18 @@ impl Board {
 		//yが1の時board_filled_countが0で対応
 		if puyo_center_y > puyo_movable_y {
 			let board_filled_count = heights[puyo_movable_x as usize];
-			self.set_flag(&puyo_movable_x, &(board_filled_count as u8), movable);
+			self.set_flag_under_vanish_row(&puyo_movable_x, &(board_filled_count as u8), movable);
 			heights[puyo_movable_x as usize] += 1;
 			//board_split_aligned.0[puyo_movable_x as usize] |= 1 << (board_filled_count);
 
-			let move_drop_count = puyo_movable_y - board_filled_count as u8;
+			let move_drop_count = puyo_movable_y.saturating_sub(board_filled_count as u8);
 			drop_count = move_drop_count as u8;
 			//---//
 
 			let board_filled_count = heights[puyo_center_x as usize];
-			self.set_flag(&puyo_center_x, &(board_filled_count as u8), center);
+			self.set_flag_under_vanish_row(&puyo_center_x, &(board_filled_count as u8), center);
 
-			let center_drop_count = puyo_center_y - board_filled_count as u8;
+			let center_drop_count = puyo_center_y.saturating_sub(board_filled_count as u8);
 			if drop_count < center_drop_count as u8 {
 				drop_count = center_drop_count as u8;
 			}
@@ -87,7 +117,7 @@ impl Board {
 			drop_count
 		} else {
 			let board_filled_count = heights[puyo_center_x as usize];
-			self.set_flag(&puyo_center_x, &(board_filled_count as u8), center);
+			self.set_flag_under_vanish_row(&puyo_center_x, &(board_filled_count as u8), center);
 			heights[puyo_center_x as usize] += 1;
 //			board_split_aligned.0[puyo_center_x as usize] |= 1 << (board_filled_count);
 
@@ -95,19 +125,19 @@ impl Board {
 				dbg!(self.to_str());
 			}*/
 
-			let move_drop_count = puyo_center_y - board_filled_count as u8;
+			let move_drop_count = puyo_center_y.saturating_sub(board_filled_count as u8);
 			drop_count = move_drop_count as u8;
 
 
 			let board_filled_count = heights[puyo_movable_x as usize];
-			self.set_flag(&puyo_movable_x, &(board_filled_count as u8), movable);
+			self.set_flag_under_vanish_row(&puyo_movable_x, &(board_filled_count as u8), movable);
 
 			/*	let aa = puyo_movable_y;
 				let aaa = board_filled_count;
 				let a = puyo_movable_y as i32 - board_filled_count;
 				assert!(!((puyo_movable_y - board_filled_count as u8) < 0));*/
 
-			let center_drop_count = puyo_movable_y - board_filled_count as u8;
+			let center_drop_count = puyo_movable_y.saturating_sub(board_filled_count as u8);
 			if drop_count < center_drop_count as u8 {
 				drop_count = center_drop_count as u8;
 			}
@@ -130,7 +160,7 @@ impl Board {
 	pub unsafe fn put_puyo_direct(&mut self, x: &u8, heights: &mut [u16; 8], puyo: &PuyoKind) {
 		let height = heights[*x as usize];
 
-		self.set_flag(x, &(height as u8), puyo);
+		self.set_flag_under_vanish_row(x, &(height as u8), puyo);
 		heights[*x as usize] += 1;
 	}
 
@@ -171,13 +201,10 @@ impl Board {
 		}*/
 
 	#[inline]
-	pub unsafe fn try_put_ojama(&mut self, ojama: &mut OjamaStatus) {
-		let mut ojama_to_receive = ojama.get_receivable_ojama_size();
-
-		if ojama_to_receive > MAX_OJAMA_RECEIVE_COUNT {
-			ojama_to_receive = MAX_OJAMA_RECEIVE_COUNT;
-		}
-		ojama.use_ojama(ojama_to_receive);
+	pub unsafe fn try_put_ojama(&mut self, ojama: &mut OjamaStatus, placer: &mut OjamaPlacer, max_ojama: usize) {
+		//1回の設置で降るのはmax_ojama個まで、残りは次の設置に回る
+		let ojama_to_receive = ojama.take_receivable(max_ojama);
+		let before_hash = self.columns_hash(0b1111110);
 
 		let mut heights = self.get_heights();
 
@@ -185,6 +212,8 @@ impl Board {
 
 		//お邪魔用のbitを作成し、状態を上書きする。
 		let ojama_mask_column_size: u16 = (1 << row) - 1;
+		//13段目より上には積もらない
+		let ghost_row_mask = column_mask(GHOST_ROW);
 
 		let mut v1: SplitBoard = SplitBoard([0; 8]);
 		let mut v2: SplitBoard = SplitBoard([0; 8]);
@@ -196,14 +225,13 @@ impl Board {
 
 		for x in 1..=6 {
 			//現在の高さ分shift
-			let ojama_mask_column = ojama_mask_column_size.wrapping_shl(heights[x] as u32);
+			let ojama_mask_column = ojama_mask_column_size.wrapping_shl(heights[x] as u32) & ghost_row_mask;
 			BoardBit::set_bit_true_column(&mut v1.0[x], &ojama_mask_column);
 			BoardBit::set_bit_false_column(&mut v2.0[x], &ojama_mask_column);
 			BoardBit::set_bit_false_column(&mut v3.0[x], &ojama_mask_column);
 		}
 
-		let ojama_pos_slice = &OJAMA_POS;  // Borrow the slice here to extend its lifetime
-		let selected_columns = ojama_pos_slice.choose_multiple(&mut thread_rng(), (ojama_to_receive % crate::env::WIDTH));
+		let selected_columns = placer.select_columns(ojama_to_receive % crate::env::WIDTH);
 
 		self.0[0] = _mm_load_si128(v1.0.as_ptr() as *const __m128i);
 		self.0[1] = _mm_load_si128(v2.0.as_ptr() as *const __m128i);
@@ -212,22 +240,36 @@ impl Board {
 		let mut heights = self.get_heights();
 
 
-		for &pos in selected_columns {
-			let ojama_mask_column = 1u16.wrapping_shl(heights[pos as usize] as u32);
+		for pos in 1..=6usize {
+			if (selected_columns >> pos) & 1 == 0 {
+				continue;
+			}
+			let ojama_mask_column = 1u16.wrapping_shl(heights[pos] as u32) & ghost_row_mask;
 
-			BoardBit::set_bit_true_column(&mut v1.0[pos as usize], &ojama_mask_column);
-			BoardBit::set_bit_false_column(&mut v2.0[pos as usize], &ojama_mask_column);
-			BoardBit::set_bit_false_column(&mut v3.0[pos as usize], &ojama_mask_column);
+			BoardBit::set_bit_true_column(&mut v1.0[pos], &ojama_mask_column);
+			BoardBit::set_bit_false_column(&mut v2.0[pos], &ojama_mask_column);
+			BoardBit::set_bit_false_column(&mut v3.0[pos], &ojama_mask_column);
 		}
 
 		self.0[0] = _mm_load_si128(v1.0.as_ptr() as *const __m128i);
 		self.0[1] = _mm_load_si128(v2.0.as_ptr() as *const __m128i);
 		self.0[2] = _mm_load_si128(v3.0.as_ptr() as *const __m128i);
+		self.1 ^= before_hash ^ self.columns_hash(0b1111110);
 	}
 
 
+	///14段目より上に置いたぷよは設置した時点で消える
+	#[inline]
+	unsafe fn set_flag_under_vanish_row(&mut self, x: &u8, y: &u8, puyo_kind: &PuyoKind) {
+		if *y < VANISH_ROW {
+			self.set_flag(x, y, puyo_kind);
+		}
+	}
+
 	#[inline]
 	pub unsafe fn set_flag(&mut self, x: &u8, y: &u8, puyo_kind: &PuyoKind) {
+		self.1 ^= zobrist::cell_key(*x, *y, self.get_cell(*x as i16, *y as i16)) ^ zobrist::cell_key(*x, *y, *puyo_kind);
+
 		match puyo_kind {
 			PuyoKind::Yellow => {
 				BoardBit::set_bit_true(&mut self.0[0], *x, *y);
@@ -249,6 +291,11 @@ impl Board {
 				BoardBit::set_bit_true(&mut self.0[1], *x, *y);
 				BoardBit::set_bit_true(&mut self.0[2], *x, *y);
 			}
+			PuyoKind::Purple => {
+				BoardBit::set_bit_true(&mut self.0[0], *x, *y);
+				BoardBit::set_bit_true(&mut self.0[1], *x, *y);
+				BoardBit::set_bit_false(&mut self.0[2], *x, *y);
+			}
 			PuyoKind::Ojama => {
 				BoardBit::set_bit_true(&mut self.0[0], *x, *y);
 				BoardBit::set_bit_false(&mut self.0[1], *x, *y);
@@ -264,14 +311,11 @@ impl Board {
 				BoardBit::set_bit_false(&mut self.0[1], *x, *y);
 				BoardBit::set_bit_false(&mut self.0[2], *x, *y);
 			}
-			_ => panic!()
 		}
 	}
 	#[inline]
 	pub unsafe fn from_str(str: &str) -> Board {
-		let mut board = Board {
-			0: [_mm_setzero_si128(); 3]
-		};
+		let mut board = Board([_mm_setzero_si128(); 3], 0);
 
 		let mut counter = 0;
 		//let mut chars = str.chars();
@@ -298,6 +342,10 @@ impl Board {
 						board.0[1] = BoardBit::set_bit(board.0[1], counter);
 						board.0[2] = BoardBit::set_bit(board.0[2], counter);
 					}
+					'P' => {
+						board.0[0] = BoardBit::set_bit(board.0[0], counter);
+						board.0[1] = BoardBit::set_bit(board.0[1], counter);
+					}
 					'O' => {
 						board.0[0] = BoardBit::set_bit(board.0[0], counter);
 						//	BoardBit::set_bit(board.0[1], counter);
@@ -315,6 +363,7 @@ impl Board {
 			}
 		}
 
+		board.1 = board.columns_hash(0xFF);
 		board
 	}
 
@@ -328,6 +377,24 @@ impl Board {
 		false
 	}
 
+	///壁と床以外が空
+	#[inline]
+	pub unsafe fn is_all_cleared(&self) -> bool {
+		let empty = Board::default();
+		self.is_same(&empty.0[0], &empty.0[1], &empty.0[2])
+	}
+
+	///壁を含む座標のぷよ
+	#[inline]
+	pub fn get_cell(&self, x: i16, y: i16) -> PuyoKind {
+		let bit_position = (x * HEIGHT_WITH_BORDER as i16 + y) as usize;
+		let v1 = Self::get_bit_from_m128i(self.0[0], bit_position);
+		let v2 = Self::get_bit_from_m128i(self.0[1], bit_position);
+		let v3 = Self::get_bit_from_m128i(self.0[2], bit_position);
+
+		PuyoKind::from_bits((v3 << 2) | (v2 << 1) | v1)
+	}
+
 	// 指定したbitの値を取得する関数
 	#[inline]
 	fn get_bit_from_m128i(value: __m128i, bit_position: usize) -> u8 {
@@ -360,7 +427,7 @@ impl Board {
 		board
 	}
 
-	///ojama,red,green,blue,yellow,(preserved)
+	///ojama,red,green,blue,yellow,purple
 	#[inline]
 	pub unsafe fn get_not_empty_board(&self) -> BoardBit {
 		let v0 = BoardBit(self.0[0]);
@@ -410,14 +477,14 @@ impl Board {
 			*waste_chain_link += pop_count as usize - 4;
 
 			if pop_count <= 7 {
-				link_bonus += Self::get_link_bonus(&pop_count);
+				link_bonus += get_link_bonus(&pop_count);
 				continue;
 			}
 
 			erasing_bit.iterate_bit_with_masking(|x: BoardBit| -> BoardBit{
 				let expanded = x.expand(mask);
 				let count = expanded.popcnt128();
-				link_bonus += Self::get_link_bonus(&count);
+				link_bonus += get_link_bonus(&count);
 				return expanded;
 			});
 		}
@@ -426,8 +493,8 @@ impl Board {
 			return 0;
 		}
 
-		let color_bonus = Self::get_color_bonus(&color_count);
-		let chain_bonus = Self::get_chain_bonus(
+		let color_bonus = get_color_bonus(&color_count);
+		let chain_bonus = get_chain_bonus(
 			&(*chain_count + if color_count == 0 { 0 } else { 1 }));
 
 		let ojama_erased = erased_flag.expand_edge().mask(&self.get_bits(PuyoKind::Ojama)/*.mask_board_12()*/);
@@ -444,7 +511,7 @@ impl Board {
 	unsafe fn pop(board: &i16, mask: &i16) -> u32 {
 		_pext_u32((*board) as u32, (*mask) as u32)
 	}
-	///落ちる量
+	///消えたぷよの上に残っていたぷよが落ちる最大の段数
 	#[inline]
 	pub unsafe fn drop_after_erased(&mut self, erased: &BoardBit) -> u8 {
 		/*	let mut drop_count: u8 = 0;
@@ -485,6 +552,12 @@ impl Board {
 		let column_mask5 = _mm_extract_epi16::<5>(erased.0) as i16;
 		let column_mask6 = _mm_extract_epi16::<6>(erased.0) as i16;
 
+		let mut erased_columns = 0u8;
+		for (x, column_mask) in [column_mask1, column_mask2, column_mask3, column_mask4, column_mask5, column_mask6].into_iter().enumerate() {
+			erased_columns |= ((column_mask != 0) as u8) << (x + 1);
+		}
+		let before_hash = self.columns_hash(erased_columns);
+
 		for i in 0..3 {
 
 			let column0 = _mm_extract_epi16::<0>(self.0[i]) as i16;
@@ -510,6 +583,7 @@ impl Board {
 
 			self.0[i] = _mm_set_epi16(column7, column6, column5, column4, column3, column2, column1, column0);
 		}
+		self.1 ^= before_hash ^ self.columns_hash(erased_columns);
 
 		drop_count
 	}
@@ -521,57 +595,20 @@ impl Board {
 		let masked_column = *column & dont_drop_mask;
 		let mut extracted_column = *column & !dont_drop_mask;
 
-		extracted_column = Self::pop(&extracted_column, &(!*mask)) as i16;
-		*column = extracted_column | masked_column;
-
-		let popcnt = _popcnt32(*mask as i32);
-		if *drop_count < popcnt as u8 {
-			*drop_count = popcnt as u8;
+		//一番上に残るぷよより下で消えた数だけ落ちる
+		let remaining = (extracted_column & !*mask) as u16;
+		if remaining != 0 {
+			let below_top = (1u16 << (15 - remaining.leading_zeros())) - 1;
+			let popcnt = _popcnt32((*mask as u16 & below_top) as i32);
+			if *drop_count < popcnt as u8 {
+				*drop_count = popcnt as u8;
+			}
 		}
-	}
 
-	#[inline]
-	fn get_color_bonus(color_count: &u32) -> u32 {
-		match color_count {
-			1 => 0,
-			2 => 3,
-			3 => 6,
-			4 => 12,
-			_ => panic!("unsupported")
-		}
-	}
-	#[inline]
-	fn get_link_bonus(link_count: &i32) -> i32 {
-		match link_count {
-			0..=4 => 0,
-			5 => 2,
-			6 => 3,
-			7 => 4,
-			8 => 5,
-			9 => 6,
-			10 => 7,
-			_ => 10
-		}
-	}
-	#[inline]
-	fn get_chain_bonus(chain_count: &u8) -> u32 {
-		return match chain_count {
-			0 => panic!("what"),
-			1 => 0,
-			2 => 8,
-			3 => 16,
-			4 => 32,
-			5 => 64,
-			6 => 96,
-			7 => 128,
-			_ => {
-				//8以降
-				128 + (*chain_count as u32 - 7) * 32
-			}
-		};
+		extracted_column = Self::pop(&extracted_column, &(!*mask)) as i16;
+		*column = extracted_column | masked_column;
 	}
 
-
 	///指定したぷよのbitboardを作成
 	#[inline]
 	pub unsafe fn get_bits(&self, puyo_color: PuyoKind) -> BoardBit {
@@ -590,7 +627,7 @@ impl Board {
 			}
 			PuyoKind::Ojama => BoardBit(_mm_andnot_si128(self.0[2], _mm_andnot_si128(self.0[1], self.0[0]))),
 			PuyoKind::Wall => BoardBit(_mm_andnot_si128(self.0[2], _mm_andnot_si128(self.0[0], self.0[1]))),
-			PuyoKind::Preserved => panic!(),
+			PuyoKind::Purple => BoardBit(_mm_andnot_si128(self.0[2], _mm_and_si128(self.0[0], self.0[1]))),
 			PuyoKind::Red => BoardBit(_mm_andnot_si128(self.0[0], _mm_andnot_si128(self.0[1], self.0[2]))),
 			PuyoKind::Green => BoardBit(_mm_and_si128(self.0[0], _mm_andnot_si128(self.0[1], self.0[2]))),
 			PuyoKind::Blue => BoardBit(_mm_andnot_si128(self.0[0], _mm_and_si128(self.0[1], self.0[2]))),
@@ -2,16 +2,16 @@
 use std::ops::{BitAnd, BitOr, BitXor};
 use std::sync::LazyLock;
 use crate::board::WIDTH_WITH_BORDER;
+use crate::board_mask;
 use crate::split_board::SplitBoard;
 
 
-//TODO:値間違ってる
 static MASK_12: LazyLock<BoardBit> = LazyLock::new(|| unsafe {
-	BoardBit(_mm_set_epi64x(-246294362775553, 2305596714850975743))
+	BoardBit(_mm_set_epi64x((board_mask::MASK_12 >> 64) as i64, board_mask::MASK_12 as i64))
 });
 
 static MASK_13: LazyLock<BoardBit> = LazyLock::new(|| unsafe {
-	BoardBit(_mm_set_epi64x(-211109453807617, 4611474908973629439))
+	BoardBit(_mm_set_epi64x((board_mask::MASK_13 >> 64) as i64, board_mask::MASK_13 as i64))
 });
 
 #[derive(Debug, Clone, Copy)]
@@ -24,6 +24,11 @@ impl BoardBit {
 			0: _mm_setzero_si128()
 		}
 	}
+	///_mm_set_epi64xと同じ並びで作る
+	#[inline]
+	pub unsafe fn from_epi64x(high: i64, low: i64) -> BoardBit {
+		BoardBit(_mm_set_epi64x(high, low))
+	}
 	#[inline]
 	pub unsafe fn l_shift(&self) -> BoardBit {
 		BoardBit(_mm_slli_epi16::<1>(self.0))
@@ -82,6 +87,11 @@ impl BoardBit {
 	pub fn mask(&self, mask: &BoardBit) -> BoardBit {
 		*self & *mask
 	}
+	///maskの立っているところを落とす
+	#[inline]
+	pub unsafe fn and_not(&self, mask: &BoardBit) -> BoardBit {
+		BoardBit(_mm_andnot_si128(mask.0, self.0))
+	}
 	///元のフラグに隣接してるやつを全列挙　マインスイーパーの空白空けるやつみたいな
 	#[inline]
 	pub unsafe fn expand(&self, mask: BoardBit) -> BoardBit {
@@ -156,6 +166,17 @@ impl BoardBit {
 		x = _mm_or_si128(_mm_srli_si128::<2>(x), x);
 		return _mm_cvtsi128_si32(x) & 0xFFFF;
 	}
+	///ビットが立っている列のフラグ、x列目ならx bit目
+	#[inline]
+	pub unsafe fn column_flags(&self) -> u8 {
+		//列ごとに0なら2byteとも立つ
+		let empty = _mm_movemask_epi8(_mm_cmpeq_epi16(self.0, _mm_setzero_si128())) as u32;
+		let mut flags = 0u8;
+		for x in 0..8 {
+			flags |= (((empty >> (x * 2)) & 1 == 0) as u8) << x;
+		}
+		flags
+	}
 	#[inline]
 	pub unsafe fn popcnt128(&self) -> i32 {
 		let low = _mm_cvtsi128_si64(self.0);
@@ -178,14 +199,14 @@ impl BoardBit {
 		let up_ones = _mm_slli_si128::<8>(down_ones);
 
 		let mut current = *self;
-		while !_mm_testz_si128(up_ones, current.0) == 1 {
+		while _mm_testz_si128(up_ones, current.0) == 0 {
 			let y = _mm_and_si128(current.0, _mm_sub_epi64(zero, current.0));
 			let z = _mm_and_si128(up_ones, y);
 			let mask = f(BoardBit(z));
 			current = BoardBit(_mm_andnot_si128(mask.0, current.0));
 		}
 
-		while !_mm_testz_si128(down_ones, current.0) == 1 {
+		while _mm_testz_si128(down_ones, current.0) == 0 {
 			let y = _mm_and_si128(current.0, _mm_sub_epi64(zero, current.0));
 			let z = _mm_and_si128(down_ones, y);
 			let mask = f(BoardBit(z));
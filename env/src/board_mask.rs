@@ -0,0 +1,41 @@
+﻿//!盤面の段ごとのルールとマスク、bitの並びは x * 16 + y でy=0が床
+
+use crate::env::{HEIGHT, WIDTH};
+
+const WIDTH_WITH_BORDER: usize = WIDTH + 2;
+const HEIGHT_WITH_BORDER: usize = 16;
+
+///見えている一番上の段、ここまでのぷよが消える
+pub const VISIBLE_ROW: u8 = HEIGHT as u8 - 2;
+///13段目、置けるが消えない。連鎖で下が消えれば落ちてくる
+pub const GHOST_ROW: u8 = HEIGHT as u8 - 1;
+///14段目、回転で一瞬入れるが設置した時点で消える
+pub const VANISH_ROW: u8 = HEIGHT as u8;
+
+///床から指定した段までの1列分のマスク
+#[inline]
+pub const fn column_mask(top_row: u8) -> u16 {
+	((1u32 << (top_row + 1)) - 1) as u16
+}
+
+///壁は全部、中の列は床から指定した段までのマスク
+pub const fn board_mask(top_row: u8) -> u128 {
+	let mut mask = 0u128;
+	let mut x = 0;
+	while x < WIDTH_WITH_BORDER {
+		let column = if x == 0 || x == WIDTH_WITH_BORDER - 1 {
+			u16::MAX
+		} else {
+			column_mask(top_row)
+		};
+		mask |= (column as u128) << (x * HEIGHT_WITH_BORDER);
+		x += 1;
+	}
+
+	mask
+}
+
+///12段目まで、消えるぷよの判定に使う
+pub const MASK_12: u128 = board_mask(VISIBLE_ROW);
+///13段目まで、高さの計算に使う
+pub const MASK_13: u128 = board_mask(GHOST_ROW);
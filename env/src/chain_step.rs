@@ -0,0 +1,88 @@
+use crate::board::Board;
+use crate::board_bit::BoardBit;
+use crate::env::FrameNeeded;
+use crate::puyo_kind::{COLOR_PUYOS, PuyoKind};
+
+///同時に消えた同色のつながり
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ChainGroup {
+	pub color: PuyoKind,
+	pub size: u8,
+}
+
+///連鎖1段分の結果、EventType::Attackで流れる
+#[derive(Debug, Clone)]
+pub struct ChainStep {
+	///1から始まる連鎖数
+	pub chain: u8,
+	///消えたぷよ、巻き込まれたお邪魔も含む
+	pub erased: BoardBit,
+	pub colors: Vec<PuyoKind>,
+	pub groups: Vec<ChainGroup>,
+	///全消しボーナスを含まない得点
+	pub score: u32,
+	///消えた後に落ちた最大の段数
+	pub drop_distance: u8,
+	///設置し終わってからこの段が消え始めるまでのフレーム
+	pub start_frame: usize,
+	///消えてから落ちて着地するまでのフレーム
+	pub frames: usize,
+}
+
+impl ChainStep {
+	///盤面を1段分進める、消えるぷよがなければNone
+	pub unsafe fn resolve(board: &mut Board, chain: u8, start_frame: usize) -> Option<ChainStep> {
+		let mut erased = BoardBit::default();
+		let score = board.erase_if_needed(&chain, &mut erased, &mut 0);
+		if score == 0 {
+			return None;
+		}
+
+		let mut colors = Vec::new();
+		let mut groups = Vec::new();
+		for color in COLOR_PUYOS {
+			let color_erased = board.get_bits(color) & erased;
+			if color_erased.is_empty() {
+				continue;
+			}
+
+			colors.push(color);
+			color_erased.iterate_bit_with_masking(|x: BoardBit| -> BoardBit {
+				let group = x.expand(color_erased);
+				groups.push(ChainGroup { color, size: group.popcnt128() as u8 });
+				group
+			});
+		}
+
+		let drop_distance = board.drop_after_erased(&erased);
+
+		Some(ChainStep {
+			chain: chain + 1,
+			erased,
+			colors,
+			groups,
+			score,
+			drop_distance,
+			start_frame,
+			frames: FrameNeeded::chain_step(drop_distance),
+		})
+	}
+
+	///設置し終わってからこの段が着地し終わるまでのフレーム
+	#[inline]
+	pub fn end_frame(&self) -> usize {
+		self.start_frame + self.frames
+	}
+
+	///連鎖がなくなるまで進める
+	pub unsafe fn resolve_all(board: &mut Board) -> Vec<ChainStep> {
+		let mut steps: Vec<ChainStep> = Vec::new();
+		loop {
+			let start_frame = steps.last().map_or(0, |step| step.end_frame());
+			match Self::resolve(board, steps.len() as u8, start_frame) {
+				Some(step) => steps.push(step),
+				None => return steps,
+			}
+		}
+	}
+}
@@ -1,22 +1,25 @@
-﻿use std::arch::x86_64::{__m128i, _mm_load_si128, _mm_set_epi64x, _mm_setzero_si128, _mm_store_si128};
-use std::collections::VecDeque;
+﻿use std::collections::VecDeque;
 use std::sync::LazyLock;
 
-use rand::prelude::SliceRandom;
-//use rand::rngs::{SmallRng, ThreadRng};
-use rand::thread_rng;
-
 use crate::board::{Board, WIDTH_WITH_BORDER};
-use crate::board_bit::BoardBit;
+use crate::chain_step::ChainStep;
 use crate::event_type::EventType;
+use crate::input_timing::InputClock;
+use crate::key_type::KeyType;
+use crate::ojama_placer::{OjamaDropOrder, OjamaPlacer};
 use crate::ojama_status::OjamaStatus;
 use crate::puyo_kind::PuyoKind;
 use crate::puyo_status::PuyoStatus;
 use crate::rotation::Rotation;
-use crate::split_board::SplitBoard;
+use crate::rules::Rules;
 use crate::vector2::Vector2;
 
+///通常のルールの色数
 pub const PUYO_COUNT: usize = 4;
+pub const MIN_PUYO_COUNT: usize = 3;
+pub const MAX_PUYO_COUNT: usize = 5;
+///袋1つに入るぷよの数、使い切ったら作り直す
+pub const BAG_SIZE: usize = 256;
 pub const HEIGHT: usize = 14;
 pub const WIDTH: usize = 6;
 pub const SPAWN_POS: (i8, i8) = (3, 12);
@@ -31,14 +34,33 @@ pub enum FrameNeeded {}
 
 impl FrameNeeded {
 	pub const LAND_PUYO_ANIMATION: usize = 15;
-	pub const MOVE: usize = 2;
+	///通の自然落下で1段下がるまで
+	pub const NATURAL_FALL: usize = 16;
 	pub const SPAWN_NEW_PUYO: usize = 2;
 	pub const TEAR_PUYO_DROP_PER_1_BLOCK: usize = 2;
 	pub const VANISH_PUYO_ANIMATION: usize = 48;
+
+	///drop_count段落ちて着地するまで、落ちなければ0
+	#[inline]
+	pub const fn drop_and_land(drop_count: u8) -> usize {
+		if drop_count == 0 {
+			return 0;
+		}
+
+		Self::TEAR_PUYO_DROP_PER_1_BLOCK * drop_count as usize + Self::LAND_PUYO_ANIMATION
+	}
+
+	///連鎖1段分、消えてから落ちて着地するまで
+	#[inline]
+	pub const fn chain_step(drop_count: u8) -> usize {
+		Self::VANISH_PUYO_ANIMATION + Self::drop_and_land(drop_count)
+	}
 }
 
 pub const ALL_CLEAR_BONUS: usize = 2100;
 pub const OJAMA_POS: [u8; 6] = [1, 2, 3, 4, 5, 6];
+///OjamaDropOrder::Rotatingで端数のお邪魔が落ちる列の順番
+pub const OJAMA_ROTATION_ORDER: [u8; 6] = [1, 4, 2, 5, 3, 6];
 pub const MAX_OJAMA_RECEIVE_COUNT: usize = 30;
 
 pub const TEAR_FRAME: [u8; 14] = [0, 19, 24, 28, 31, 34, 37, 40, 42, 44, 46, 48, 48, 48];
@@ -49,7 +71,7 @@ pub const ROTATE_DIFF: [[i8; 2]; 4] = [
 	[0, 1],
 ];
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Event {
 	pub kind: EventType,
 	pub frame: usize,
@@ -66,6 +88,7 @@ pub static DEAD_POSITION: LazyLock<Vector2> = LazyLock::new(|| {
 	dead_pos
 });
 
+#[derive(Clone)]
 pub struct DebugStatus {
 	pub current_chain_count: usize,
 	pub current_chain_attack: usize,
@@ -80,6 +103,8 @@ impl DebugStatus {
 	}
 }
 
+///待ったのためにcloneで状態を丸ごと保存できる
+#[derive(Clone)]
 pub struct Env {
 	pub board: Board,
 	pub center_puyo: PuyoKind,
@@ -92,16 +117,37 @@ pub struct Env {
 	pub ojama: OjamaStatus,
 	pub all_cleared: bool,
 	pub dead: bool,
-//	rng: SmallRng,
+	pub ojama_placer: OjamaPlacer,
+	///最後に設置したぷよで起きた連鎖
+	pub chain_steps: Vec<ChainStep>,
 	bag: VecDeque<PuyoKind>,
 	rand: u32,
 	pub debug_status: DebugStatus,
 	pub ojama_rate: usize,
+	///お邪魔にならなかった得点の端数
+	pub attack_carry: usize,
+	pub rules: Rules,
+	///最後に1段下がってからのフレーム、自然落下に使う
+	pub fall_frame: usize,
+	///縦向きで両側が塞がれて回転できなかった、もう1度回転するとクイックターンする
+	pub quick_turn: bool,
+	///今のぷよへの入力の履歴、rules.input_timingより早い入力はcan_inputがfalseになる
+	pub input_clock: InputClock,
 }
 
 
 impl Env {
 	pub unsafe fn new(seed: &u32) -> Env {
+		Self::new_with_ojama_order(seed, OjamaDropOrder::Random)
+	}
+
+	///端数のお邪魔の落とし方を指定して作成、どちらもseedから再現できる
+	pub unsafe fn new_with_ojama_order(seed: &u32, ojama_drop_order: OjamaDropOrder) -> Env {
+		Self::new_with_rules(seed, ojama_drop_order, Rules::default())
+	}
+
+	///レートや色数などのルールを指定して作成
+	pub unsafe fn new_with_rules(seed: &u32, ojama_drop_order: OjamaDropOrder, rules: Rules) -> Env {
 		Env {
 			board: Board::default(),
 			center_puyo: PuyoKind::Empty,
@@ -111,15 +157,20 @@ impl Env {
 			current_frame: 0,
 			current_score: 0,
 			events: VecDeque::new(),
-			ojama: OjamaStatus(0),
+			ojama: OjamaStatus::new(),
 			all_cleared: false,
-			//queue_rng: StdRng::seed_from_u64(*seed),
-		//	rng: thread_rng(),
 			dead: false,
-			bag: VecDeque::with_capacity(256),
+			ojama_placer: OjamaPlacer::new(seed, ojama_drop_order),
+			chain_steps: Vec::new(),
+			bag: VecDeque::with_capacity(BAG_SIZE),
 			rand: *seed,
 			debug_status: DebugStatus::new(),
-			ojama_rate: 70,
+			ojama_rate: rules.target_point,
+			attack_carry: 0,
+			rules,
+			fall_frame: 0,
+			quick_turn: false,
+			input_clock: InputClock::new(0),
 		}
 	}
 
@@ -167,10 +218,7 @@ impl Env {
 	}
 
 	fn init_bag(&mut self) {
-		for i in 0..256 {
-			let kind = PuyoKind::from_bits((i % PUYO_COUNT + 4) as u8);
-			self.bag.push_back(kind);
-		}
+		self.bag.extend(self.rules.bag());
 		self.shuffle_bag();
 	}
 
@@ -192,11 +240,12 @@ impl Env {
 		next_for_pop
 	}
 
+	///自分の連鎖が終わってから呼ばれる、確定したお邪魔はここで降る
 	pub unsafe fn create_new_puyo(&mut self) {
 		self.debug_status.current_chain_count = 0;
 
 		if self.ojama.get_receivable_ojama_size() != 0 {
-			self.board.try_put_ojama(&mut self.ojama/*, &mut self.rng*/);
+			self.board.try_put_ojama(&mut self.ojama, &mut self.ojama_placer, self.rules.max_ojama_per_drop);
 		}
 
 		if !self.board.is_empty_cell(DEAD_POSITION.x as i16, DEAD_POSITION.y as i16) {
@@ -216,6 +265,9 @@ impl Env {
 		self.movable_puyo = poped_next[0];
 		self.center_puyo = poped_next[1];
 		self.puyo_status = PuyoStatus::new(Vector2::new(SPAWN_POS.0, SPAWN_POS.1), Rotation::new(3));
+		self.fall_frame = 0;
+		self.quick_turn = false;
+		self.input_clock = InputClock::new(self.current_frame);
 	}
 
 	/*#[inline]
@@ -232,28 +284,67 @@ impl Env {
 		return Some(board.get_cell(x as i16, y as i16));
 	}*/
 
-	pub unsafe fn move_right(&mut self) {
-		if Self::move_puyo(&self.board, &mut self.puyo_status, 1, 0) {
-			self.events.push_back(Event {
-				frame: self.current_frame,
-				kind: EventType::Wait,
-				value: FrameNeeded::MOVE,
-				value2: Default::default(),
-			});
+	///キー入力を1つ適用する、対戦もリプレイの再生もここを通る
+	pub unsafe fn input(&mut self, key: KeyType) {
+		self.input_clock.press(&self.rules.input_timing, key, self.current_frame);
+
+		match key {
+			KeyType::Right => self.move_right(),
+			KeyType::Left => self.move_left(),
+			//通では上入力に効果がない
+			KeyType::Top => {}
+			KeyType::Down => self.soft_drop(),
+			KeyType::Drop => self.quick_drop(),
+			KeyType::RotateRight => self.rotate_ccw(),
+			KeyType::RotateLeft => self.rotate_cw(),
+			KeyType::Rotate180 => self.rotate_180(),
 		}
 	}
 
+	///rules.input_timingの間隔を空けていて、このフレームにkeyを入力できるか
+	#[inline]
+	pub fn can_input(&self, key: KeyType) -> bool {
+		self.input_clock.is_ready(&self.rules.input_timing, key, self.current_frame)
+	}
+
+	pub unsafe fn move_right(&mut self) {
+		Self::move_puyo(&self.board, &mut self.puyo_status, 1, 0);
+	}
+
 	pub unsafe fn move_left(&mut self) {
-		if Self::move_puyo(&self.board, &mut self.puyo_status, -1, 0) {
-			self.events.push_back(Event {
-				frame: self.current_frame,
-				kind: EventType::Wait,
-				value: FrameNeeded::MOVE,
-				value2: Default::default(),
-			});
+		Self::move_puyo(&self.board, &mut self.puyo_status, -1, 0);
+	}
+
+	///1段下げる、下がれなければその場で設置する
+	pub unsafe fn soft_drop(&mut self) {
+		if Self::move_puyo(&self.board, &mut self.puyo_status, 0, -1) {
+			self.fall_frame = 0;
+		} else {
+			self.quick_drop();
 		}
 	}
 
+	///操作中のぷよをrules.fall_interval_frameごとに1段下げる、入力を処理した後に毎フレーム呼ぶ
+	///接地していればその場で設置してtrueを返す
+	pub unsafe fn fall_one_frame(&mut self) -> bool {
+		if self.rules.fall_interval_frame == 0 || self.center_puyo == PuyoKind::Empty {
+			return false;
+		}
+
+		self.fall_frame += 1;
+		if self.fall_frame < self.rules.fall_interval_frame {
+			return false;
+		}
+
+		self.fall_frame = 0;
+		if Self::move_puyo(&self.board, &mut self.puyo_status, 0, -1) {
+			return false;
+		}
+
+		self.quick_drop();
+		true
+	}
+
 	#[inline]
 	pub unsafe fn is_valid_rotation(puyo_status: &PuyoStatus, board: &Board, cw: bool, kick: &mut Vector2) -> bool {
 		let mut rotation = puyo_status.rotation;
@@ -315,14 +406,11 @@ impl Env {
 	pub unsafe fn rotate_cw(&mut self) {
 		let mut kick = Vector2::new(0, 0);
 		if Self::is_valid_rotation(&self.puyo_status, &self.board, false, &mut kick) {
-			self.events.push_back(Event {
-				frame: self.current_frame,
-				kind: EventType::Wait,
-				value: FrameNeeded::MOVE,
-				value2: Default::default(),
-			});
 			Self::rotate_puyo(&mut self.puyo_status, 1);
 			Self::move_puyo(&self.board, &mut self.puyo_status, kick.x, kick.y);
+			self.quick_turn = false;
+		} else {
+			self.try_quick_turn();
 		}
 	}
 
@@ -330,30 +418,27 @@ impl Env {
 	pub unsafe fn rotate_ccw(&mut self) {
 		let mut kick = Vector2::new(0, 0);
 		if Self::is_valid_rotation(&self.puyo_status, &self.board, true, &mut kick) {
-			self.events.push_back(Event {
-				frame: self.current_frame,
-				kind: EventType::Wait,
-				value: FrameNeeded::MOVE,
-				value2: Default::default(),
-			});
 			Self::rotate_puyo(&mut self.puyo_status, 0);
 			Self::move_puyo(&self.board, &mut self.puyo_status, kick.x, kick.y);
+			self.quick_turn = false;
+		} else {
+			self.try_quick_turn();
 		}
 	}
 
 	#[inline]
+	///180度回転する、反対側が塞がれていれば軸ぷよと子ぷよを入れ替える
 	pub unsafe fn rotate_180(&mut self) {
-		Self::rotate_puyo(&mut self.puyo_status, 2);
-		self.events.push_back(Event {
-			frame: self.current_frame,
-			kind: EventType::Wait,
-			value: FrameNeeded::MOVE,
-			value2: Default::default(),
-		});
-		if self.puyo_status.rotation.0 == 3 {
-			self.puyo_status.position.y -= 1;
-		} else if self.puyo_status.rotation.0 == 1 {
-			self.puyo_status.position.y += 1;
+		Self::rotate_puyo_180(&self.board, &mut self.puyo_status);
+		self.quick_turn = false;
+	}
+
+	///回転できなかったとき、縦向きなら2回目の回転で上下を入れ替える
+	unsafe fn try_quick_turn(&mut self) {
+		if self.quick_turn {
+			self.rotate_180();
+		} else {
+			self.quick_turn = self.puyo_status.position_diff.x == 0;
 		}
 	}
 
@@ -361,21 +446,7 @@ impl Env {
 	pub unsafe fn update(&mut self) {
 		self.current_frame += 1;
 
-		let ojama_rate = match self.current_frame / 60 {
-			v if v <= 95 => { 70 }
-			v if v <= 111 => { 52 }
-			v if v <= 127 => { 34 }
-			v if v <= 143 => { 25 }
-			v if v <= 159 => { 16 }
-			v if v <= 175 => { 12 }
-			v if v <= 191 => { 8 }
-			v if v <= 207 => { 6 }
-			v if v <= 223 => { 4 }
-			v if v <= 239 => { 3 }
-			v if v <= 255 => { 2 }
-			v if v >= 256 => { 1 }
-			_ => { panic!() }
-		};
+		let ojama_rate = self.rules.ojama_rate(self.current_frame);
 
 		if self.ojama_rate != ojama_rate {
 			self.ojama_rate = ojama_rate;
@@ -390,7 +461,8 @@ impl Env {
 	}
 
 	#[inline]
-	pub unsafe fn quick_drop(&mut self, opponent: Option<&mut Env>) {
+	///ぷよを設置して連鎖を処理する、お邪魔は連鎖の各段が消え始めるフレームでprocess_eventsが送る
+	pub unsafe fn quick_drop(&mut self) {
 		let drop_count = self.board.put_puyo(&self.puyo_status, &self.center_puyo, &self.movable_puyo, &mut Vector2::default());
 
 		self.center_puyo = PuyoKind::Empty;
@@ -413,75 +485,99 @@ impl Env {
 		}
 
 
-		let mut chain: u8 = 0;
-		let mut board_mask = BoardBit::default();
-
-		let mut chain_score: usize = 0;
-		let mut elapsed_frame = 0usize;
-		loop {
-			let score = self.board.erase_if_needed(&chain, &mut board_mask, &mut 0);
-			if score == 0 {
-				break;
-			}
+		self.chain_steps = ChainStep::resolve_all(&mut self.board);
+		let elapsed_frame = self.chain_steps.last().map_or(0, |step| step.end_frame());
 
+		for step in &self.chain_steps {
+			let mut step_score = step.score as usize;
 			if self.all_cleared {
-				chain_score += ALL_CLEAR_BONUS;
+				step_score += self.rules.all_clear_bonus;
 				self.all_cleared = false;
 			}
 
+			self.current_score += step.score as usize;
+
+			//割り切れなかった得点は次の段、次の連鎖に持ち越す
+			let score_with_carry = self.attack_carry + step_score;
+			self.attack_carry = score_with_carry % self.ojama_rate;
+
+			self.events.push_back(Event {
+				frame: self.current_frame,
+				kind: EventType::Attack(step.clone()),
+				value: score_with_carry / self.ojama_rate,
+				value2: elapsed_frame - step.start_frame,
+			});
+
 			self.events.push_back(Event {
 				frame: self.current_frame,
 				kind: EventType::Wait,
 				value: FrameNeeded::VANISH_PUYO_ANIMATION,
 				value2: Default::default(),
 			});
-			elapsed_frame += FrameNeeded::VANISH_PUYO_ANIMATION;
-
-			self.current_score += score as usize;
-			chain_score += score as usize;
 
-			let drop_count = self.board.drop_after_erased(&board_mask);
-
-
-			if drop_count > 0 {
+			if step.drop_distance > 0 {
 				self.events.push_back(Event {
 					frame: self.current_frame,
 					kind: EventType::Wait,
-					value: FrameNeeded::TEAR_PUYO_DROP_PER_1_BLOCK * drop_count as usize,
+					value: FrameNeeded::TEAR_PUYO_DROP_PER_1_BLOCK * step.drop_distance as usize,
 					value2: Default::default(),
 				});
-				elapsed_frame += FrameNeeded::TEAR_PUYO_DROP_PER_1_BLOCK * drop_count as usize;
 
 				self.events.push_back(Event {
 					frame: self.current_frame,
 					kind: EventType::Wait,
-					value: FrameNeeded::VANISH_PUYO_ANIMATION,
+					value: FrameNeeded::LAND_PUYO_ANIMATION,
 					value2: Default::default(),
 				});
-				elapsed_frame += FrameNeeded::LAND_PUYO_ANIMATION;
 			}
-
-			chain += 1;
 		}
 
-		self.debug_status.current_chain_count = chain as usize;
-
-		if self.board.is_same(&_mm_setzero_si128(),
-							  &_mm_set_epi64x(0b1111111111111111000000000000000100000000000000010000000000000001u64 as i64,
-											  0b0000000000000001000000000000000100000000000000011111111111111111u64 as i64),
-							  &_mm_setzero_si128()) {
+		if self.board.is_all_cleared() {
 			self.all_cleared = true;
 		}
+	}
 
+	///current_frameまでのイベントを処理する、操作できる状態ならtrue
+	///Attackでは自分の予告お邪魔を相殺し、余りを相手に送る。相手側では連鎖が終わるまで確定しない
+	pub fn process_events(&mut self, current_frame: usize, opponent: Option<&mut Env>) -> bool {
+		let mut opponent = opponent;
+		while let Some(event) = self.events.front_mut() {
+			if event.frame > current_frame {
+				break;
+			}
 
-		let mut attack: usize = chain_score / self.ojama_rate;
-		attack = self.ojama.offset(attack);
-
-		if attack != 0 {
-			if let Some(opponent) = opponent {
-				opponent.ojama.push(attack, (self.current_frame + elapsed_frame).saturating_sub(opponent.current_frame));
+			match &event.kind {
+				EventType::Wait => {
+					if event.value == 0 {
+						self.events.pop_front();
+						continue;
+					}
+
+					event.value -= 1;
+					if event.value == 0 {
+						self.events.pop_front();
+					} else {
+						return false;
+					}
+				}
+				EventType::Attack(step) => {
+					self.debug_status.current_chain_count = step.chain as usize;
+					self.debug_status.current_chain_attack = step.score as usize;
+
+					let attack = self.ojama.offset(event.value);
+					if attack != 0 {
+						if let Some(opponent) = opponent.as_deref_mut() {
+							//このフレームで続くWaitも1つ進むので、その分早く確定する
+							opponent.ojama.push(attack, event.value2.saturating_sub(1));
+						}
+					}
+
+					self.events.pop_front();
+				}
 			}
 		}
+
+		true
 	}
 
 
@@ -497,6 +593,18 @@ impl Env {
 		return false;
 	}
 
+	///180度回転した位置にする、入れ替えた位置は元と同じマスなので必ず回転できる
+	#[inline]
+	pub unsafe fn rotate_puyo_180(board: &Board, puyo_status: &mut PuyoStatus) {
+		let before_diff = puyo_status.position_diff;
+		Self::rotate_puyo(puyo_status, 2);
+
+		if !Self::is_valid_position(board, puyo_status, 0, 0) {
+			puyo_status.position.x += before_diff.x;
+			puyo_status.position.y += before_diff.y;
+		}
+	}
+
 	///0:cw 1:ccw 2:180
 	#[inline]
 	pub fn rotate_puyo(puyo_status: &mut PuyoStatus, r_type: u8) {
@@ -1,6 +1,9 @@
-﻿#[derive(Debug)]
+﻿use crate::chain_step::ChainStep;
+
+#[derive(Debug, Clone)]
 pub enum EventType{
 	Wait,
-	Attack
+	///連鎖1段分、消え始めるフレームに処理される
+	Attack(ChainStep)
 	
 }
\ No newline at end of file
@@ -0,0 +1,179 @@
+﻿use serde_derive::{Deserialize, Serialize};
+
+use crate::key_type::KeyType;
+
+///入力の間隔、Envはこの通りにしか入力を受け付けないのでframesは実際にかかるフレームと一致する
+///横移動と回転とソフトドロップは別のボタンなので同じフレームに押せる
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InputTiming {
+	///同じボタンを連打できる間隔
+	pub tap_frame: usize,
+	///横移動を押しっぱなしにしてから2回目の移動まで
+	pub das_frame: usize,
+	///押しっぱなしの3回目以降の移動の間隔
+	pub repeat_frame: usize,
+	///ソフトドロップで1段下がる間隔
+	pub soft_drop_frame: usize,
+}
+
+impl InputTiming {
+	///通の操作、連打は2フレームごと、押しっぱなしは4フレーム後から毎フレーム動く
+	pub const fn tsu() -> Self {
+		InputTiming {
+			tap_frame: 2,
+			das_frame: 4,
+			repeat_frame: 1,
+			soft_drop_frame: 2,
+		}
+	}
+
+	///最初の入力を0フレーム目として、最後の入力を押せるフレーム
+	pub fn frames(&self, keys: &[KeyType]) -> usize {
+		let mut clock = InputClock::new(0);
+		let mut frame = 0;
+		for &key in keys {
+			frame = clock.earliest(self, key);
+			clock.press(self, key, frame);
+		}
+
+		frame
+	}
+}
+
+impl Default for InputTiming {
+	fn default() -> Self {
+		Self::tsu()
+	}
+}
+
+///ぷよが出てからの入力の履歴、次の入力をいつ押せるかを決める
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InputClock {
+	///最後に何かを押したフレーム、入力の順番は入れ替えられない
+	last_press: usize,
+	last_move: Option<usize>,
+	///続けて同じ向きに動かした回数と、その1回目のフレーム
+	move_direction: i8,
+	move_count: usize,
+	move_start: usize,
+	last_rotate: Option<usize>,
+	last_down: Option<usize>,
+}
+
+impl InputClock {
+	///frameから入力を受け付ける
+	pub fn new(frame: usize) -> Self {
+		InputClock {
+			last_press: frame,
+			last_move: None,
+			move_direction: 0,
+			move_count: 0,
+			move_start: frame,
+			last_rotate: None,
+			last_down: None,
+		}
+	}
+
+	///keyを最も早く押せるフレーム
+	pub fn earliest(&self, timing: &InputTiming, key: KeyType) -> usize {
+		let frame = match key {
+			KeyType::Right | KeyType::Left => {
+				let direction = Self::direction(key);
+				if self.move_count != 0 && self.move_direction == direction {
+					//連打と押しっぱなしの早い方
+					let count = self.move_count;
+					self.move_start + (count * timing.tap_frame).min(timing.das_frame + (count - 1) * timing.repeat_frame)
+				} else {
+					self.last_move.map_or(0, |frame| frame + timing.tap_frame)
+				}
+			}
+			KeyType::RotateRight | KeyType::RotateLeft | KeyType::Rotate180 => self.last_rotate.map_or(0, |frame| frame + timing.tap_frame),
+			KeyType::Down => self.last_down.map_or(0, |frame| frame + timing.soft_drop_frame),
+			KeyType::Top | KeyType::Drop => 0,
+		};
+
+		frame.max(self.last_press)
+	}
+
+	///frameにkeyを押したことを記録する
+	pub fn press(&mut self, timing: &InputTiming, key: KeyType, frame: usize) {
+		self.last_press = frame;
+
+		match key {
+			KeyType::Right | KeyType::Left => {
+				let direction = Self::direction(key);
+				if self.move_count == 0 || self.move_direction != direction {
+					self.move_direction = direction;
+					self.move_count = 0;
+					self.move_start = frame;
+				}
+				self.move_count += 1;
+				self.last_move = Some(frame);
+			}
+			KeyType::RotateRight | KeyType::RotateLeft => self.last_rotate = Some(frame),
+			//クイックターンは回転を2回押す
+			KeyType::Rotate180 => {
+				self.last_press = frame + timing.tap_frame;
+				self.last_rotate = Some(frame + timing.tap_frame);
+			}
+			KeyType::Down => self.last_down = Some(frame),
+			KeyType::Top | KeyType::Drop => {}
+		}
+	}
+
+	///続けて動かしている向き(1:右 -1:左 0:なし)と回数
+	#[inline]
+	pub fn move_run(&self) -> (i8, usize) {
+		(self.move_direction, self.move_count)
+	}
+
+	///押せるようになっていればtrue
+	#[inline]
+	pub fn is_ready(&self, timing: &InputTiming, key: KeyType, frame: usize) -> bool {
+		self.earliest(timing, key) <= frame
+	}
+
+	fn direction(key: KeyType) -> i8 {
+		if key == KeyType::Right { 1 } else { -1 }
+	}
+}
+
+///自然落下の数え方、Env::fall_one_frameと同じく入力を処理した後に毎フレーム進める
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct FallClock {
+	///1段下がる間隔、0なら自然落下しない
+	pub interval: usize,
+	///最後に1段下がってからのフレーム、Env::fall_frameと同じ
+	pub fall_frame: usize,
+}
+
+impl FallClock {
+	pub fn new(interval: usize, fall_frame: usize) -> Self {
+		FallClock {
+			interval,
+			fall_frame,
+		}
+	}
+
+	///1フレーム進める、1段下がるフレームならtrue
+	#[inline]
+	pub fn tick(&mut self) -> bool {
+		if self.interval == 0 {
+			return false;
+		}
+
+		self.fall_frame += 1;
+		if self.fall_frame < self.interval {
+			return false;
+		}
+
+		self.fall_frame = 0;
+		true
+	}
+
+	///ソフトドロップで下がったので数え直す
+	#[inline]
+	pub fn reset(&mut self) {
+		self.fall_frame = 0;
+	}
+}
@@ -0,0 +1,14 @@
+﻿use serde_derive::{Deserialize, Serialize};
+
+#[repr(u8)]
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum KeyType {
+	Right,
+	Left,
+	Top,
+	Down,
+	Drop,
+	RotateRight,
+	RotateLeft,
+	Rotate180
+}
\ No newline at end of file
@@ -1,10 +1,26 @@
-pub mod env;
+﻿pub mod env;
 pub mod puyo_status;
 pub mod rotation;
+#[cfg(all(target_arch = "x86_64", not(feature = "portable")))]
 pub mod board_bit;
+#[cfg(all(target_arch = "x86_64", not(feature = "portable")))]
 pub mod board;
+#[cfg(all(target_arch = "x86_64", not(feature = "portable")))]
+pub mod split_board;
+//x86以外、またはportable featureではu128版を使う
+#[cfg(any(not(target_arch = "x86_64"), feature = "portable"))]
+pub use portable::{board, board_bit};
+pub mod portable;
+pub mod score_bonus;
+pub mod board_mask;
+pub mod zobrist;
 pub mod vector2;
 pub mod puyo_kind;
-pub mod split_board;
 pub mod event_type;
+pub mod chain_step;
 pub mod ojama_status;
+pub mod ojama_placer;
+pub mod rules;
+pub mod key_type;
+pub mod input_timing;
+pub mod simulator;
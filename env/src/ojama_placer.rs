@@ -0,0 +1,58 @@
+﻿use fastrand::Rng;
+
+use crate::env::{OJAMA_POS, OJAMA_ROTATION_ORDER};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OjamaDropOrder {
+	///端数の列をシードから作った乱数で選ぶ
+	Random,
+	///公式の順番で端数の列をローテーションする
+	Rotating,
+}
+
+///端数のお邪魔を落とす列を決める。Envが持ち、同じシードなら同じ列になる
+#[derive(Debug, Clone)]
+pub struct OjamaPlacer {
+	rng: Rng,
+	order: OjamaDropOrder,
+	cursor: usize,
+}
+
+impl OjamaPlacer {
+	pub fn new(seed: &u32, order: OjamaDropOrder) -> Self {
+		OjamaPlacer {
+			rng: Rng::with_seed(*seed as u64),
+			order,
+			cursor: 0,
+		}
+	}
+
+	#[inline]
+	pub fn order(&self) -> OjamaDropOrder {
+		self.order
+	}
+
+	///count個の列を重複なしで選び、xのbitを立てて返す
+	#[inline]
+	pub fn select_columns(&mut self, count: usize) -> u8 {
+		let mut column_flag = 0u8;
+
+		match self.order {
+			OjamaDropOrder::Random => {
+				let mut columns = OJAMA_POS;
+				self.rng.shuffle(&mut columns);
+				for &x in columns.iter().take(count) {
+					column_flag |= 1 << x;
+				}
+			}
+			OjamaDropOrder::Rotating => {
+				for _ in 0..count.min(OJAMA_ROTATION_ORDER.len()) {
+					column_flag |= 1 << OJAMA_ROTATION_ORDER[self.cursor];
+					self.cursor = (self.cursor + 1) % OJAMA_ROTATION_ORDER.len();
+				}
+			}
+		}
+
+		column_flag
+	}
+}
@@ -1,209 +1,125 @@
-﻿#[derive(Debug)]
-pub struct OjamaStatus(pub u64);
+﻿use std::collections::VecDeque;
+
+///相手の連鎖1段で届いたお邪魔
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PendingOjama {
+	pub size: usize,
+	///相手の連鎖が終わって確定するまでのフレーム、0なら次の設置で降る
+	pub receive_frame: usize,
+}
+
+///予告お邪魔の一覧、届いた順に並ぶ
+#[derive(Debug, Clone, Default)]
+pub struct OjamaStatus {
+	pending: VecDeque<PendingOjama>,
+}
 
 impl OjamaStatus {
-	pub fn clone(&self) -> Self {
+	pub fn new() -> Self {
 		OjamaStatus {
-			0: self.0
+			pending: VecDeque::new(),
 		}
 	}
 
-	//1つの火力のセットをsizeとtimeで計32bitで入れる
-	//お邪魔一覧はそのセット2つまで入れられる、それ以上は起こりえないため強制シフト
+	///相手の連鎖1段分のお邪魔を後ろに積む
 	#[inline]
-	pub unsafe fn push(&mut self, ojama_size: usize, receive_frame: usize) {
-		let mut values = std::mem::transmute::<u64, [u16; 4]>(self.0);
-		values[2] = values[2 - 2];
-		values[3] = values[3 - 2];
-		values[0] = ojama_size as u16;
-		values[1] = receive_frame as u16;
-
-		if values[0] == 0 && values[1] == 0 {
-			values[0] = values[2];
-			values[1] = values[3];
-			values[2] = 0;
-			values[3] = 0;
+	pub fn push(&mut self, ojama_size: usize, receive_frame: usize) {
+		if ojama_size == 0 {
+			return;
 		}
 
-		self.0 = std::mem::transmute(values);
+		self.pending.push_back(PendingOjama {
+			size: ojama_size,
+			receive_frame,
+		});
 	}
-	#[inline]
-	pub unsafe fn update_one_frame(&mut self) {
-		let mut values = std::mem::transmute::<u64, [u16; 4]>(self.0);
-
-		values[1] = values[1].saturating_sub(1);
-		values[3] = values[3].saturating_sub(1);
 
-		if values[0] == 0 && values[1] == 0 {
-			values[0] = values[2];
-			values[1] = values[3];
-			values[2] = 0;
-			values[3] = 0;
+	#[inline]
+	pub fn update_one_frame(&mut self) {
+		for ojama in &mut self.pending {
+			ojama.receive_frame = ojama.receive_frame.saturating_sub(1);
 		}
-
-		self.0 = std::mem::transmute(values);
 	}
 
 	///与えた火力分相殺します。余りが返ります。
+	///確定していない予告も相殺できる
 	#[inline]
-	pub unsafe fn offset(&mut self, mut attack: usize) -> usize {
+	pub fn offset(&mut self, mut attack: usize) -> usize {
 		//相殺はfrontから
-		let mut values = std::mem::transmute::<u64, [u16; 4]>(self.0);
-
-		if values[0] >= attack as u16 {
-			values[0] -= attack as u16;
-			attack = 0;
-		} else {
-			attack -= values[0] as usize;
-			values[0] = 0;
-		}
-
-		if values[0] == 0 {
-			values[1] = 0;
-		}
-
-
-		if values[2] >= attack as u16 {
-			values[2] -= attack as u16;
-			attack = 0;
-		} else {
-			attack -= values[2] as usize;
-			values[2] = 0;
-		}
-
-		if values[2] == 0 {
-			values[3] = 0;
-		}
-
-
-		if values[0] == 0 {
-			values[0] = values[2];
-			values[1] = values[3];
-			values[2] = 0;
-			values[3] = 0;
+		while attack != 0 {
+			let Some(front) = self.pending.front_mut() else {
+				break;
+			};
+
+			if front.size > attack {
+				front.size -= attack;
+				attack = 0;
+			} else {
+				attack -= front.size;
+				self.pending.pop_front();
+			}
 		}
 
-		self.0 = std::mem::transmute(values);
-
 		attack
 	}
 	#[inline]
 	pub fn is_empty(&self) -> bool {
-		self.0 == 0
+		self.pending.is_empty()
 	}
 
-	//1と2のお邪魔両方ともreceiveまでの時間が0だったらまとめる
-	//それぞれの操作の前に
-	// 必ず行う
-	#[allow(dead_code)]
 	#[inline]
-	unsafe fn try_collect(&mut self) {
-		let mut values = std::mem::transmute::<u64, [u16; 4]>(self.0);
-		if values[1] == 0 && values[3] == 0 {
-			values[0] = values[0] + values[2];
-			values[2] = 0;
-		}
-
-		self.0 = std::mem::transmute(values);
-	}
-	#[inline]
-	pub unsafe fn get_all_ojama_size(&self) -> usize {
-		let mut ojama_size = 0;
-		let values = std::mem::transmute::<u64, [u16; 4]>(self.0);
-
-		ojama_size += values[0];
-		ojama_size += values[2];
-
-		ojama_size as usize
+	pub fn get_all_ojama_size(&self) -> usize {
+		self.pending.iter().map(|ojama| ojama.size).sum()
 	}
 
-	//receive_timeが0のお邪魔の数 関数
+	///receive_frameが0のお邪魔の数
 	#[inline]
-	pub unsafe fn get_receivable_ojama_size(&self) -> usize {
-		let mut ojama_size = 0;
-		let values = std::mem::transmute::<u64, [u16; 4]>(self.0);
-
-		if values[1] == 0 {
-			ojama_size += values[0];
-		}
-		if values[3] == 0 {
-			ojama_size += values[2];
-		}
-
-		ojama_size as usize
+	pub fn get_receivable_ojama_size(&self) -> usize {
+		self.pending.iter()
+			.filter(|ojama| ojama.receive_frame == 0)
+			.map(|ojama| ojama.size)
+			.sum()
 	}
-	///use_garbage 関数 offsetとちょっと似てるかもね receive_timeが0だったら使うよ
-	//TODO: 色々怪しい
+
+	///確定したお邪魔を前から使う、足りなければ確定分が全部なくなる
 	#[inline]
-	#[allow(unused_assignments)]
-	pub unsafe fn use_ojama(&mut self, mut use_size: usize) {
-		//使うのはfrontとか関係ない
-		let mut values = std::mem::transmute::<u64, [u16; 4]>(self.0);
-		if values[1] == 0 {
-			if values[0] >= use_size as u16 {
-				values[0] -= use_size as u16;
-				use_size = 0;
-			} else {
-				values[0] = 0;
-			//	use_size -= values[0] as usize;
+	pub fn use_ojama(&mut self, mut use_size: usize) {
+		for ojama in &mut self.pending {
+			if use_size == 0 {
+				break;
 			}
-		}
-
-
-		if values[3] == 0 {
-			if values[2] >= use_size as u16 {
-				values[2] -= use_size as u16;
-				use_size = 0;
-			} else {
-				values[2] = 0;
-				//use_size -= values[2] as usize;
+			if ojama.receive_frame != 0 {
+				continue;
 			}
-		}
 
-		if values[0] == 0 && values[1] == 0 {
-			values[0] = values[2];
-			values[1] = values[3];
-			values[2] = 0;
-			values[3] = 0;
+			let used = ojama.size.min(use_size);
+			ojama.size -= used;
+			use_size -= used;
 		}
 
-		self.0 = std::mem::transmute(values);
+		self.pending.retain(|ojama| ojama.size != 0);
 	}
+
+	///確定したお邪魔を最大max_size個取り出す、1回の設置で降る分
 	#[inline]
-	pub unsafe fn get_raw(&self) -> [u16; 4] {
-		std::mem::transmute::<u64, [u16; 4]>(self.0)
+	pub fn take_receivable(&mut self, max_size: usize) -> usize {
+		let size = self.get_receivable_ojama_size().min(max_size);
+		self.use_ojama(size);
+		size
 	}
 
 	#[inline]
-	pub unsafe fn get_time_to_receive(&self) -> u16 {
-		let mut time_to_receive = 0;
-		let values = std::mem::transmute::<u64, [u16; 4]>(self.0);
-
-		//短い方を取得
-		if values[0] != 0 {
-			time_to_receive = values[1];
-		}
-		if values[2] != 0 {
-			if time_to_receive > values[3] {
-				time_to_receive = values[3];
-			}
-		}
-
-		time_to_receive
+	pub fn pending(&self) -> &VecDeque<PendingOjama> {
+		&self.pending
 	}
 
-	/*	unsafe fn try_pack(&mut self) {
-			let mut values = std::mem::transmute::<u64, [u16; 4]>(self.0);
-			if values[0] == 0 && values[1] == 0 {
-				values[0] = values[2];
-				values[1] = values[3];
-				values[2] = 0;
-				values[3] = 0;
-			}
-			self.0 = std::mem::transmute(values);
-		}*/
-
-
-	/*	pub fn get_ojama_size(&self) -> &u16 { &self.ojama_size }
-		pub fn get_receive_time(&self) -> &u16 { &self.receive_time }*/
-}
\ No newline at end of file
+	///一番早く確定するお邪魔までのフレーム、なければ0
+	#[inline]
+	pub fn get_time_to_receive(&self) -> u16 {
+		self.pending.iter()
+			.map(|ojama| ojama.receive_frame)
+			.min()
+			.unwrap_or(0)
+			.min(u16::MAX as usize) as u16
+	}
+}
@@ -0,0 +1,417 @@
+﻿
+use crate::ojama_placer::OjamaPlacer;
+use crate::ojama_status::OjamaStatus;
+use crate::board_mask::{column_mask, GHOST_ROW, VANISH_ROW};
+use crate::portable::board_bit::{from_epi64x, BoardBit};
+use crate::puyo_kind::{COLOR_PUYOS, PuyoKind};
+use crate::puyo_status::PuyoStatus;
+use crate::score_bonus::{get_chain_bonus, get_color_bonus, get_link_bonus};
+use crate::vector2::Vector2;
+use crate::zobrist;
+
+pub const WIDTH: u8 = 6;
+pub const WIDTH_WITH_BORDER: u8 = 8;
+
+pub const HEIGHT: u8 = 14;
+pub const HEIGHT_WITH_BORDER: u8 = 16;
+
+///u128で表したBoard、SIMD版と同じbitの並びと関数を持つ
+///2つ目はzobristのハッシュ、ぷよを置く・消す・お邪魔を降らせるときに変わったマスの分だけ更新する
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Board(pub [u128; 3], u64);
+
+
+impl Board {
+	#[inline]
+	pub unsafe fn default() -> Board {
+		Self::with_hash([0, from_epi64x(0b1111111111111111000000000000000100000000000000010000000000000001u64 as i64, 0b0000000000000001000000000000000100000000000000011111111111111111u64 as i64), 0])
+	}
+
+	#[inline]
+	fn with_hash(raw: [u128; 3]) -> Board {
+		let mut board = Board(raw, 0);
+		board.1 = board.columns_hash(0xFF);
+		board
+	}
+
+	///盤面のハッシュ、同じ盤面なら同じ値
+	#[inline]
+	pub fn hash(&self) -> u64 {
+		self.1
+	}
+
+	///columnsのbitが立っている列のハッシュ、全ての列なら盤面のハッシュと同じ
+	pub fn columns_hash(&self, columns: u8) -> u64 {
+		let mut hash = 0;
+		for x in 0..8 {
+			if (columns >> x) & 1 == 1 {
+				hash ^= zobrist::column_hash(x, BoardBit::get_column(self.0[0], x), BoardBit::get_column(self.0[1], x), BoardBit::get_column(self.0[2], x));
+			}
+		}
+
+		hash
+	}
+
+	///落ちた量
+	#[inline]
+	pub unsafe fn put_puyo(&mut self, puyo_status: &PuyoStatus, center: &PuyoKind, movable: &PuyoKind, put_pos: &mut Vector2) -> u8 {
+		let mut drop_count: u8;
+
+		//14段目に置かれる分はset_flag_under_vanish_rowで消える
+		let mut heights = self.get_heights();
+
+		put_pos.x = puyo_status.position.x;
+		put_pos.y = heights[puyo_status.position.x as usize] as i8;
+
+		let puyo_center_x = puyo_status.position.x as u8;
+		let puyo_center_y = puyo_status.position.y as u8;
+		let puyo_movable_x = (puyo_status.position.x + puyo_status.position_diff.x) as u8;
+		let puyo_movable_y = (puyo_status.position.y + puyo_status.position_diff.y) as u8;
+
+		if puyo_center_y > puyo_movable_y {
+			let board_filled_count = heights[puyo_movable_x as usize];
+			self.set_flag_under_vanish_row(&puyo_movable_x, &(board_filled_count as u8), movable);
+			heights[puyo_movable_x as usize] += 1;
+
+			drop_count = puyo_movable_y.saturating_sub(board_filled_count as u8);
+
+			let board_filled_count = heights[puyo_center_x as usize];
+			self.set_flag_under_vanish_row(&puyo_center_x, &(board_filled_count as u8), center);
+
+			let center_drop_count = puyo_center_y.saturating_sub(board_filled_count as u8);
+			if drop_count < center_drop_count {
+				drop_count = center_drop_count;
+			}
+
+			drop_count
+		} else {
+			let board_filled_count = heights[puyo_center_x as usize];
+			self.set_flag_under_vanish_row(&puyo_center_x, &(board_filled_count as u8), center);
+			heights[puyo_center_x as usize] += 1;
+
+			drop_count = puyo_center_y.saturating_sub(board_filled_count as u8);
+
+			let board_filled_count = heights[puyo_movable_x as usize];
+			self.set_flag_under_vanish_row(&puyo_movable_x, &(board_filled_count as u8), movable);
+
+			let center_drop_count = puyo_movable_y.saturating_sub(board_filled_count as u8);
+			if drop_count < center_drop_count {
+				drop_count = center_drop_count;
+			}
+
+			drop_count
+		}
+	}
+
+	///指定したxの高さの場所に上書きします
+	#[inline]
+	pub unsafe fn put_puyo_direct(&mut self, x: &u8, heights: &mut [u16; 8], puyo: &PuyoKind) {
+		let height = heights[*x as usize];
+
+		self.set_flag_under_vanish_row(x, &(height as u8), puyo);
+		heights[*x as usize] += 1;
+	}
+
+	#[inline]
+	pub unsafe fn get_heights(&self) -> [u16; 8] {
+		let mut heights: [u16; 8] = [0; 8];
+
+		let board = self.get_not_empty_board().mask_board_13().0;
+		for (x, height) in heights.iter_mut().enumerate() {
+			*height = BoardBit::get_column(board, x).count_ones() as u16;
+		}
+
+		heights
+	}
+
+	#[inline]
+	pub unsafe fn try_put_ojama(&mut self, ojama: &mut OjamaStatus, placer: &mut OjamaPlacer, max_ojama: usize) {
+		//1回の設置で降るのはmax_ojama個まで、残りは次の設置に回る
+		let ojama_to_receive = ojama.take_receivable(max_ojama);
+		let before_hash = self.columns_hash(0b1111110);
+
+		let heights = self.get_heights();
+
+		let row = ojama_to_receive / crate::env::WIDTH;
+
+		//お邪魔用のbitを作成し、状態を上書きする。
+		let ojama_mask_column_size: u16 = (1 << row) - 1;
+		//13段目より上には積もらない
+		let ghost_row_mask = column_mask(GHOST_ROW);
+
+		for x in 1..=6 {
+			//現在の高さ分shift
+			let ojama_mask_column = ojama_mask_column_size.wrapping_shl(heights[x] as u32) & ghost_row_mask;
+			self.put_ojama_column(x, &ojama_mask_column);
+		}
+
+		let selected_columns = placer.select_columns(ojama_to_receive % crate::env::WIDTH);
+		let heights = self.get_heights();
+
+		for pos in 1..=6usize {
+			if (selected_columns >> pos) & 1 == 0 {
+				continue;
+			}
+			let ojama_mask_column = 1u16.wrapping_shl(heights[pos] as u32) & ghost_row_mask;
+			self.put_ojama_column(pos, &ojama_mask_column);
+		}
+		self.1 ^= before_hash ^ self.columns_hash(0b1111110);
+	}
+
+	#[inline]
+	unsafe fn put_ojama_column(&mut self, x: usize, ojama_mask_column: &u16) {
+		let mut v1 = BoardBit::get_column(self.0[0], x);
+		let mut v2 = BoardBit::get_column(self.0[1], x);
+		let mut v3 = BoardBit::get_column(self.0[2], x);
+
+		BoardBit::set_bit_true_column(&mut v1, ojama_mask_column);
+		BoardBit::set_bit_false_column(&mut v2, ojama_mask_column);
+		BoardBit::set_bit_false_column(&mut v3, ojama_mask_column);
+
+		BoardBit::set_column(&mut self.0[0], x, v1);
+		BoardBit::set_column(&mut self.0[1], x, v2);
+		BoardBit::set_column(&mut self.0[2], x, v3);
+	}
+
+	///14段目より上に置いたぷよは設置した時点で消える
+	#[inline]
+	unsafe fn set_flag_under_vanish_row(&mut self, x: &u8, y: &u8, puyo_kind: &PuyoKind) {
+		if *y < VANISH_ROW {
+			self.set_flag(x, y, puyo_kind);
+		}
+	}
+
+	#[inline]
+	pub unsafe fn set_flag(&mut self, x: &u8, y: &u8, puyo_kind: &PuyoKind) {
+		self.1 ^= zobrist::cell_key(*x, *y, self.get_cell(*x as i16, *y as i16)) ^ zobrist::cell_key(*x, *y, *puyo_kind);
+
+		let bits = *puyo_kind as u8;
+		for i in 0..3 {
+			if (bits >> i) & 1 == 1 {
+				BoardBit::set_bit_true(&mut self.0[i], *x, *y);
+			} else {
+				BoardBit::set_bit_false(&mut self.0[i], *x, *y);
+			}
+		}
+	}
+
+	#[inline]
+	pub unsafe fn from_str(str: &str) -> Board {
+		let mut board = Board([0; 3], 0);
+
+		let mut counter = 0;
+		for x in 0..8 {
+			for y in 0..16 {
+				let puyo_kind = match str.chars().nth(y * 8 + x).unwrap() {
+					'Y' => PuyoKind::Yellow,
+					'G' => PuyoKind::Green,
+					'R' => PuyoKind::Red,
+					'B' => PuyoKind::Blue,
+					'P' => PuyoKind::Purple,
+					'O' => PuyoKind::Ojama,
+					'W' => PuyoKind::Wall,
+					'E' => PuyoKind::Empty,
+					_ => panic!()
+				};
+
+				let bits = puyo_kind as u8;
+				for i in 0..3 {
+					if (bits >> i) & 1 == 1 {
+						board.0[i] = BoardBit::set_bit(board.0[i], counter);
+					}
+				}
+				counter += 1;
+			}
+		}
+
+		board.1 = board.columns_hash(0xFF);
+		board
+	}
+
+	#[inline]
+	pub unsafe fn is_same(&self, v1: &u128, v2: &u128, v3: &u128) -> bool {
+		self.0[0] == *v1 && self.0[1] == *v2 && self.0[2] == *v3
+	}
+
+	///壁と床以外が空
+	#[inline]
+	pub unsafe fn is_all_cleared(&self) -> bool {
+		*self == Board::default()
+	}
+
+	///壁を含む座標のぷよ
+	#[inline]
+	pub fn get_cell(&self, x: i16, y: i16) -> PuyoKind {
+		let bit_position = (x * HEIGHT_WITH_BORDER as i16 + y) as u32;
+		let v1 = (self.0[0] >> bit_position) & 1;
+		let v2 = (self.0[1] >> bit_position) & 1;
+		let v3 = (self.0[2] >> bit_position) & 1;
+
+		PuyoKind::from_bits(((v3 << 2) | (v2 << 1) | v1) as u8)
+	}
+
+	#[inline]
+	pub unsafe fn to_str(&self) -> String {
+		let mut board = String::new();
+
+		for y in 0..16 {
+			for x in 0..8 {
+				board += self.get_cell(x, y).to_string();
+			}
+			board += "\r\n";
+		}
+
+		board
+	}
+
+	///ojama,red,green,blue,yellow,purple
+	#[inline]
+	pub unsafe fn get_not_empty_board(&self) -> BoardBit {
+		BoardBit(self.0[0] | self.0[1] | self.0[2])
+	}
+
+	#[inline]
+	pub unsafe fn get_not_empty_board_1(v0: &u128, v1: &u128, v2: &u128) -> BoardBit {
+		BoardBit(*v0 | *v1 | *v2)
+	}
+
+	#[inline]
+	pub unsafe fn is_empty_cell(&self, x: i16, y: i16) -> bool {
+		self.get_bits(PuyoKind::Empty).get_1_flag((x * HEIGHT_WITH_BORDER as i16 + y) as i8)
+	}
+
+	#[inline]
+	pub unsafe fn erase_if_needed(&self, chain_count: &u8, erased_flag: &mut BoardBit, waste_chain_link: &mut usize) -> u32 {
+		erased_flag.0 = 0;
+
+		let mut color_count = 0;
+		let mut erased_puyo_count = 0;
+		let mut link_bonus = 0;
+
+		for color_puyo in COLOR_PUYOS {
+			let mask = self.get_bits(color_puyo).mask_board_12();
+
+			let mut erasing_bit = BoardBit::default();
+
+			if !mask.find_erasing_flag(&mut erasing_bit) {
+				continue;
+			}
+
+			color_count += 1;
+			erased_flag.set_all(&erasing_bit);
+
+			let pop_count = erasing_bit.popcnt128();
+			erased_puyo_count += pop_count;
+
+			*waste_chain_link += pop_count as usize - 4;
+
+			if pop_count <= 7 {
+				link_bonus += get_link_bonus(&pop_count);
+				continue;
+			}
+
+			erasing_bit.iterate_bit_with_masking(|x: BoardBit| -> BoardBit{
+				let expanded = x.expand(mask);
+				let count = expanded.popcnt128();
+				link_bonus += get_link_bonus(&count);
+				return expanded;
+			});
+		}
+
+		if color_count == 0 {
+			return 0;
+		}
+
+		let color_bonus = get_color_bonus(&color_count);
+		let chain_bonus = get_chain_bonus(&(*chain_count + 1));
+
+		let ojama_erased = erased_flag.expand_edge().mask(&self.get_bits(PuyoKind::Ojama));
+		erased_flag.set_all(&ojama_erased);
+
+		let mut bonus = color_bonus as i32 + chain_bonus as i32 + link_bonus;
+		if bonus == 0 {
+			bonus = 1;
+		}
+
+		return (10 * erased_puyo_count * bonus) as u32;
+	}
+
+	///消えたぷよの上に残っていたぷよが落ちる最大の段数
+	#[inline]
+	pub unsafe fn drop_after_erased(&mut self, erased: &BoardBit) -> u8 {
+		let mut drop_count: u8 = 0;
+
+		let mut erased_columns = 0u8;
+		for x in 1..=6 {
+			erased_columns |= ((BoardBit::get_column(erased.0, x) != 0) as u8) << x;
+		}
+		let before_hash = self.columns_hash(erased_columns);
+
+		for i in 0..3 {
+			for x in 1..=6 {
+				let mut column = BoardBit::get_column(self.0[i], x);
+				Self::pop_column(&mut column, &BoardBit::get_column(erased.0, x), &mut drop_count);
+				BoardBit::set_column(&mut self.0[i], x, column);
+			}
+		}
+		self.1 ^= before_hash ^ self.columns_hash(erased_columns);
+
+		drop_count
+	}
+
+	#[inline]
+	fn pop_column(column: &mut u16, mask: &u16, drop_count: &mut u8) {
+		let dont_drop_mask = 0b1100000000000000u16;
+
+		let masked_column = *column & dont_drop_mask;
+		let extracted_column = *column & !dont_drop_mask;
+
+		//一番上に残るぷよより下で消えた数だけ落ちる
+		let remaining = extracted_column & !*mask;
+		if remaining != 0 {
+			let below_top = (1u16 << (15 - remaining.leading_zeros())) - 1;
+			let popcnt = (*mask & below_top).count_ones();
+			if *drop_count < popcnt as u8 {
+				*drop_count = popcnt as u8;
+			}
+		}
+
+		*column = Self::pext(extracted_column, !*mask) | masked_column;
+	}
+
+	///_pext_u32と同じ、maskの立っているbitを下に詰める
+	#[inline]
+	fn pext(value: u16, mut mask: u16) -> u16 {
+		let mut result = 0;
+		let mut bit = 1;
+		while mask != 0 {
+			let lowest = mask & mask.wrapping_neg();
+			if value & lowest != 0 {
+				result |= bit;
+			}
+			bit <<= 1;
+			mask &= mask - 1;
+		}
+
+		result
+	}
+
+	///指定したぷよのbitboardを作成
+	#[inline]
+	pub unsafe fn get_bits(&self, puyo_color: PuyoKind) -> BoardBit {
+		let v0 = self.0[0];
+		let v1 = self.0[1];
+		let v2 = self.0[2];
+
+		BoardBit(match puyo_color {
+			PuyoKind::Empty => !(v0 | v1 | v2),
+			PuyoKind::Ojama => !v2 & !v1 & v0,
+			PuyoKind::Wall => !v2 & !v0 & v1,
+			PuyoKind::Purple => !v2 & v1 & v0,
+			PuyoKind::Red => !v0 & !v1 & v2,
+			PuyoKind::Green => v0 & !v1 & v2,
+			PuyoKind::Blue => !v0 & v1 & v2,
+			PuyoKind::Yellow => v0 & v1 & v2
+		})
+	}
+}
@@ -0,0 +1,251 @@
+﻿use std::ops::{BitAnd, BitOr, BitXor};
+
+use crate::board_mask;
+
+//16bitずつの列ごとのマスク、列をまたいだshiftのはみ出しを消す
+const LANE_LOW_CLEARED: u128 = 0xFFFE_FFFE_FFFE_FFFE_FFFE_FFFE_FFFE_FFFE;
+const LANE_HIGH_CLEARED: u128 = 0x7FFF_7FFF_7FFF_7FFF_7FFF_7FFF_7FFF_7FFF;
+const LOW_64: u128 = u64::MAX as u128;
+
+const MASK_12: BoardBit = BoardBit(board_mask::MASK_12);
+const MASK_13: BoardBit = BoardBit(board_mask::MASK_13);
+
+///_mm_set_epi64xと同じ並びでu128を作る
+#[inline]
+pub const fn from_epi64x(high: i64, low: i64) -> u128 {
+	((high as u64 as u128) << 64) | low as u64 as u128
+}
+
+///x86以外でも動くBoardBit、bitの並びはSIMD版と同じ(x * 16 + y)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BoardBit(pub u128);
+
+impl BoardBit {
+	#[inline]
+	pub unsafe fn default() -> BoardBit {
+		BoardBit(0)
+	}
+	///_mm_set_epi64xと同じ並びで作る
+	#[inline]
+	pub unsafe fn from_epi64x(high: i64, low: i64) -> BoardBit {
+		BoardBit(from_epi64x(high, low))
+	}
+	#[inline]
+	pub unsafe fn l_shift(&self) -> BoardBit {
+		BoardBit((self.0 << 1) & LANE_LOW_CLEARED)
+	}
+	#[inline]
+	pub unsafe fn r_shift(&self) -> BoardBit {
+		BoardBit((self.0 >> 1) & LANE_HIGH_CLEARED)
+	}
+	#[inline]
+	pub unsafe fn d_shift(&self) -> BoardBit {
+		BoardBit(self.0 << 16)
+	}
+	#[inline]
+	pub unsafe fn u_shift(&self) -> BoardBit {
+		BoardBit(self.0 >> 16)
+	}
+	#[inline]
+	///同色を表すBoardBitのフラグから、消せるぷよ（＝4つ以上つながっているぷよ群）のフラグを作る
+	pub unsafe fn find_erasing_flag(&self, erasable: &mut BoardBit) -> bool {
+		let right = self.u_shift().0 & self.0;
+		let left = self.d_shift().0 & self.0;
+		let up = self.r_shift().0 & self.0;
+		let down = self.l_shift().0 & self.0;
+
+		let up_down_and = up & down;
+		let left_right_and = left & right;
+		let up_down_or = up | down;
+		let left_right_or = left | right;
+
+		let threes = (up_down_and & left_right_or) | (left_right_and & up_down_or);
+		let twos = (up_down_and | left_right_and) | (up_down_or & left_right_or);
+
+		let twos = BoardBit(twos);
+		let two_down = twos.l_shift().0 & twos.0;
+		let two_left = twos.d_shift().0 & twos.0;
+
+		erasable.0 = threes | two_down | two_left;
+
+		if erasable.is_empty() {
+			return false;
+		}
+
+		let two_up = twos.r_shift().0 & twos.0;
+		let two_right = twos.u_shift().0 & twos.0;
+
+		*erasable = BoardBit(erasable.0 | two_up | two_right).expand_1(self);
+		return true;
+	}
+	#[inline]
+	pub unsafe fn is_empty(&self) -> bool {
+		self.0 == 0
+	}
+	#[inline]
+	pub fn mask(&self, mask: &BoardBit) -> BoardBit {
+		*self & *mask
+	}
+	///maskの立っているところを落とす
+	#[inline]
+	pub unsafe fn and_not(&self, mask: &BoardBit) -> BoardBit {
+		BoardBit(self.0 & !mask.0)
+	}
+	///元のフラグに隣接してるやつを全列挙　マインスイーパーの空白空けるやつみたいな
+	#[inline]
+	pub unsafe fn expand(&self, mask: BoardBit) -> BoardBit {
+		let mut seed = *self;
+
+		loop {
+			let expanded = seed.expand_1(&mask);
+
+			if seed.0 & expanded.0 == expanded.0 {
+				return expanded;
+			}
+
+			seed = expanded;
+		}
+	}
+	//フラグを一回り拡張
+	#[inline]
+	pub unsafe fn expand_1(&self, mask: &BoardBit) -> BoardBit {
+		self.expand_1_without_mask() & *mask
+	}
+
+	#[inline]
+	pub unsafe fn expand_1_without_mask(&self) -> BoardBit {
+		let v1 = self.l_shift();
+		let v2 = self.r_shift();
+		let v3 = self.u_shift();
+		let v4 = self.d_shift();
+
+		return (*self | v1) | (v2 | v3) | v4;
+	}
+
+	#[inline]
+	pub unsafe fn expand_edge(&self) -> BoardBit {
+		let m1 = self.l_shift();
+		let m2 = self.r_shift();
+		let m3 = self.u_shift();
+		let m4 = self.d_shift();
+
+		(m1 | m2) | (m3 | m4)
+	}
+	#[inline]
+	pub unsafe fn mask_board_12(&self) -> BoardBit {
+		MASK_12 & *self
+	}
+	#[inline]
+	pub unsafe fn mask_board_13(&self) -> BoardBit {
+		MASK_13 & *self
+	}
+	#[inline]
+	pub unsafe fn horizontal_or16(&self) -> i32 {
+		let mut x = self.0 | (self.0 >> 64);
+		x |= x >> 32;
+		x |= x >> 16;
+		(x & 0xFFFF) as i32
+	}
+	///ビットが立っている列のフラグ、x列目ならx bit目
+	#[inline]
+	pub unsafe fn column_flags(&self) -> u8 {
+		let mut flags = 0u8;
+		for x in 0..8 {
+			flags |= ((Self::get_column(self.0, x) != 0) as u8) << x;
+		}
+		flags
+	}
+	#[inline]
+	pub unsafe fn popcnt128(&self) -> i32 {
+		self.0.count_ones() as i32
+	}
+	#[inline]
+	pub fn set_all(&mut self, fb: &BoardBit) {
+		*self = *self | *fb
+	}
+	///SIMD版と同じく上位64bitから、それぞれ下のbitから順に渡す
+	#[inline]
+	pub unsafe fn iterate_bit_with_masking<F>(&self, mut f: F)
+		where F: FnMut(BoardBit) -> BoardBit
+	{
+		let mut current = *self;
+		while current.0 & !LOW_64 != 0 {
+			let high = current.0 >> 64;
+			let z = (high & high.wrapping_neg()) << 64;
+			let mask = f(BoardBit(z));
+			current = BoardBit(current.0 & !mask.0);
+		}
+
+		while current.0 & LOW_64 != 0 {
+			let low = current.0 & LOW_64;
+			let z = low & low.wrapping_neg();
+			let mask = f(BoardBit(z));
+			current = BoardBit(current.0 & !mask.0);
+		}
+	}
+	#[inline]
+	pub unsafe fn get_1_flag(&self, bit_index: i8) -> bool {
+		(self.0 >> bit_index) & 1 == 1
+	}
+	#[inline]
+	pub fn set_bit(x: u128, bit_pos: u8) -> u128 {
+		x | 1u128 << bit_pos
+	}
+
+	#[inline]
+	pub unsafe fn set_bit_true(board: &mut u128, x: u8, y: u8) {
+		*board |= ((1u16 << y) as u128) << (x as u32 * 16);
+	}
+
+	#[inline]
+	pub unsafe fn set_bit_false(board: &mut u128, x: u8, y: u8) {
+		*board &= !(((1u16 << y) as u128) << (x as u32 * 16));
+	}
+
+	#[inline]
+	pub unsafe fn set_bit_true_column(board_column: &mut u16, mask: &u16) {
+		*board_column = *board_column | *mask;
+	}
+
+	#[inline]
+	pub unsafe fn set_bit_false_column(board_column: &mut u16, mask: &u16) {
+		*board_column = *board_column & !*mask;
+	}
+
+	///x列目の16bit
+	#[inline]
+	pub fn get_column(board: u128, x: usize) -> u16 {
+		(board >> (x * 16)) as u16
+	}
+
+	#[inline]
+	pub fn set_column(board: &mut u128, x: usize, column: u16) {
+		*board = (*board & !(0xFFFFu128 << (x * 16))) | (column as u128) << (x * 16);
+	}
+}
+
+
+impl BitAnd for BoardBit {
+	type Output = BoardBit;
+
+	fn bitand(self, rhs: BoardBit) -> BoardBit {
+		BoardBit(self.0 & rhs.0)
+	}
+}
+
+
+impl BitOr for BoardBit {
+	type Output = BoardBit;
+
+	fn bitor(self, rhs: Self) -> Self::Output {
+		BoardBit(self.0 | rhs.0)
+	}
+}
+
+impl BitXor for BoardBit {
+	type Output = BoardBit;
+
+	fn bitxor(self, rhs: BoardBit) -> Self::Output {
+		BoardBit(self.0 ^ rhs.0)
+	}
+}
@@ -0,0 +1,2 @@
+﻿pub mod board_bit;
+pub mod board;
@@ -1,7 +1,9 @@
 ﻿use strum::EnumIter;
 
 
-pub const COLOR_PUYOS: [PuyoKind; 4] = [PuyoKind::Red, PuyoKind::Yellow, PuyoKind::Blue, PuyoKind::Green];
+pub const COLOR_PUYOS: [PuyoKind; 5] = [PuyoKind::Red, PuyoKind::Yellow, PuyoKind::Blue, PuyoKind::Green, PuyoKind::Purple];
+///ツモに使う色の順番、色数が少ないルールでは前から使う
+pub const BAG_COLORS: [PuyoKind; 5] = [PuyoKind::Red, PuyoKind::Green, PuyoKind::Blue, PuyoKind::Yellow, PuyoKind::Purple];
 
 #[repr(u8)]
 #[derive(PartialEq, Debug, Copy, Clone, EnumIter)]
@@ -9,11 +11,10 @@ pub enum PuyoKind {
 	Empty = 0b000,
 	Ojama = 0b001,
 	Wall = 0b010,
-	Preserved = 0b011,//調整用
+	Purple = 0b011,
 	Red = 0b100,
 	Green = 0b101,
 	Blue = 0b110,
-	//Purple,
 	Yellow = 0b111,
 
 }
@@ -25,7 +26,7 @@ impl PuyoKind {
 			0b000 => PuyoKind::Empty,
 			0b001 => PuyoKind::Ojama,
 			0b010 => PuyoKind::Wall,
-			0b011 => PuyoKind::Preserved,
+			0b011 => PuyoKind::Purple,
 			0b100 => PuyoKind::Red,
 			0b101 => PuyoKind::Green,
 			0b110 => PuyoKind::Blue,
@@ -38,7 +39,7 @@ impl PuyoKind {
 			PuyoKind::Empty => "E",
 			PuyoKind::Ojama => "O",
 			PuyoKind::Wall => "W",
-			PuyoKind::Preserved => "P",
+			PuyoKind::Purple => "P",
 			PuyoKind::Red => "R",
 			PuyoKind::Green => "G",
 			PuyoKind::Blue => "B",
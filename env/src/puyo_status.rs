@@ -4,7 +4,7 @@ use crate::env::ROTATE_DIFF;
 use crate::rotation::Rotation;
 use crate::vector2::Vector2;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct PuyoStatus {
 	pub position: Vector2,
 	pub rotation: Rotation,
@@ -30,14 +30,6 @@ impl PuyoStatus {
 		}
 	}
 	#[inline]
-	pub fn clone(&self) -> PuyoStatus {
-		PuyoStatus {
-			position: self.position.clone(),
-			rotation: self.rotation,
-			position_diff: self.position_diff.clone(),
-		}
-	}
-	#[inline]
 	pub fn create_hash(&self, x_diff: i8, r_diff: i8) -> u16 {
 		let r = self.rotation + r_diff;
 
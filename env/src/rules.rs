@@ -0,0 +1,134 @@
+﻿use anyhow::{bail, Result};
+use serde_derive::{Deserialize, Serialize};
+
+use crate::env::{ALL_CLEAR_BONUS, BAG_SIZE, FrameNeeded, MAX_OJAMA_RECEIVE_COUNT, MAX_PUYO_COUNT, MIN_PUYO_COUNT, PUYO_COUNT};
+use crate::input_timing::InputTiming;
+use crate::puyo_kind::{BAG_COLORS, PuyoKind};
+
+///ぷよぷよ通のマージンタイム後のレート、16秒ごとに次へ下がり最後の値が続く
+pub const TSU_DECAY_RATES: [usize; 11] = [52, 34, 25, 16, 12, 8, 6, 4, 3, 2, 1];
+
+///対戦のルール、Env・BattleEnv・AIが同じものを参照する
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Rules {
+	///お邪魔1個になる得点、マージンタイムまではこのレート
+	pub target_point: usize,
+	///レートが下がり始めるまでのフレーム
+	pub margin_frame: usize,
+	///マージンタイム後、このフレームごとにdecay_ratesの次のレートへ下がる
+	pub decay_interval_frame: usize,
+	///マージンタイム後のレート、空ならtarget_pointのまま
+	pub decay_rates: Vec<usize>,
+	pub all_clear_bonus: usize,
+	///1回の設置で降るお邪魔の上限
+	pub max_ojama_per_drop: usize,
+	///使う色の数、MIN_PUYO_COUNTからMAX_PUYO_COUNTまで
+	pub color_count: usize,
+	///自然落下で1段下がるまでのフレーム、接地してからも同じフレームで設置される。0なら落下しない
+	#[serde(default)]
+	pub fall_interval_frame: usize,
+	///入力を受け付ける間隔
+	#[serde(default)]
+	pub input_timing: InputTiming,
+}
+
+impl Rules {
+	///ぷよぷよ通の公式ルール、マージンタイム96秒
+	pub fn tsu() -> Self {
+		Rules {
+			target_point: 70,
+			margin_frame: 96 * 60,
+			decay_interval_frame: 16 * 60,
+			decay_rates: TSU_DECAY_RATES.to_vec(),
+			all_clear_bonus: ALL_CLEAR_BONUS,
+			max_ojama_per_drop: MAX_OJAMA_RECEIVE_COUNT,
+			color_count: PUYO_COUNT,
+			fall_interval_frame: FrameNeeded::NATURAL_FALL,
+			input_timing: InputTiming::tsu(),
+		}
+	}
+
+	///マージンタイムを倍の192秒にした通ルール
+	pub fn tsu_long_margin() -> Self {
+		Rules {
+			margin_frame: 192 * 60,
+			..Self::tsu()
+		}
+	}
+
+	///レートが下がらない通ルール、学習用
+	pub fn tsu_without_margin() -> Self {
+		Rules {
+			decay_rates: Vec::new(),
+			..Self::tsu()
+		}
+	}
+
+	///3色の通ルール、練習用
+	pub fn tsu_three_colors() -> Self {
+		Rules {
+			color_count: 3,
+			..Self::tsu()
+		}
+	}
+
+	///5色の通ルール、お邪魔の多いモードで使う
+	pub fn tsu_five_colors() -> Self {
+		Rules {
+			color_count: 5,
+			..Self::tsu()
+		}
+	}
+
+	///自然落下しない通ルール、落下のなかった古いリプレイの再生用
+	pub fn tsu_without_gravity() -> Self {
+		Rules {
+			fall_interval_frame: 0,
+			..Self::tsu()
+		}
+	}
+
+	///Envに渡せるルールか確認する
+	pub fn validate(&self) -> Result<()> {
+		if !(MIN_PUYO_COUNT..=MAX_PUYO_COUNT).contains(&self.color_count) {
+			bail!("color_count must be between {MIN_PUYO_COUNT} and {MAX_PUYO_COUNT}: {}", self.color_count);
+		}
+		if self.target_point == 0 || self.decay_rates.contains(&0) {
+			bail!("ojama rate must not be 0");
+		}
+		if self.max_ojama_per_drop == 0 {
+			bail!("max_ojama_per_drop must not be 0");
+		}
+		if self.input_timing.tap_frame == 0 || self.input_timing.soft_drop_frame == 0 {
+			bail!("input interval must not be 0");
+		}
+
+		Ok(())
+	}
+
+	///指定フレームのレート
+	pub fn ojama_rate(&self, frame: usize) -> usize {
+		if frame < self.margin_frame || self.decay_rates.is_empty() {
+			return self.target_point;
+		}
+
+		let index = (frame - self.margin_frame) / self.decay_interval_frame.max(1);
+		self.decay_rates[index.min(self.decay_rates.len() - 1)]
+	}
+
+	///使う色、ツモはこの中から作られる。AIもこの色だけを置いて探索する
+	pub fn colors(&self) -> &'static [PuyoKind] {
+		&BAG_COLORS[..self.color_count.clamp(MIN_PUYO_COUNT, MAX_PUYO_COUNT)]
+	}
+
+	///混ぜる前の袋、colorsを順に繰り返してBAG_SIZE個並べる
+	pub fn bag(&self) -> Vec<PuyoKind> {
+		self.colors().iter().copied().cycle().take(BAG_SIZE).collect()
+	}
+}
+
+impl Default for Rules {
+	fn default() -> Self {
+		Self::tsu()
+	}
+}
@@ -0,0 +1,43 @@
+﻿//!連鎖の得点計算に使うボーナス、盤面の実装によらず共通
+
+#[inline]
+pub fn get_color_bonus(color_count: &u32) -> u32 {
+	match color_count {
+		1 => 0,
+		2 => 3,
+		3 => 6,
+		4 => 12,
+		5 => 24,
+		_ => panic!("unsupported")
+	}
+}
+#[inline]
+pub fn get_link_bonus(link_count: &i32) -> i32 {
+	match link_count {
+		0..=4 => 0,
+		5 => 2,
+		6 => 3,
+		7 => 4,
+		8 => 5,
+		9 => 6,
+		10 => 7,
+		_ => 10
+	}
+}
+#[inline]
+pub fn get_chain_bonus(chain_count: &u8) -> u32 {
+	return match chain_count {
+		0 => panic!("what"),
+		1 => 0,
+		2 => 8,
+		3 => 16,
+		4 => 32,
+		5 => 64,
+		6 => 96,
+		7 => 128,
+		_ => {
+			//8以降
+			128 + (*chain_count as u32 - 7) * 32
+		}
+	};
+}
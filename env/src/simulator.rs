@@ -0,0 +1,229 @@
+﻿use anyhow::{bail, Result};
+
+use crate::board::{Board, HEIGHT_WITH_BORDER, WIDTH_WITH_BORDER};
+use crate::chain_step::ChainStep;
+use crate::env::Env;
+use crate::ojama_placer::OjamaDropOrder;
+use crate::puyo_kind::PuyoKind;
+use crate::puyo_status::PuyoStatus;
+use crate::rules::Rules;
+
+///envの関数が使う拡張命令、確認済みであることを型で保証する
+#[derive(Debug, Clone, Copy)]
+pub struct CpuFeatures {
+	_private: (),
+}
+
+impl CpuFeatures {
+	///SSE4.1(_mm_test_all_ones等)、BMI2(_pext_u32)、POPCNTが使えるか確認する
+	#[cfg(all(target_arch = "x86_64", not(feature = "portable")))]
+	pub fn detect() -> Result<CpuFeatures> {
+		let mut missing = Vec::new();
+		if !is_x86_feature_detected!("sse4.1") {
+			missing.push("sse4.1");
+		}
+		if !is_x86_feature_detected!("bmi2") {
+			missing.push("bmi2");
+		}
+		if !is_x86_feature_detected!("popcnt") {
+			missing.push("popcnt");
+		}
+
+		if !missing.is_empty() {
+			bail!("cpu doesn't support required features: {}", missing.join(", "));
+		}
+
+		Ok(CpuFeatures { _private: () })
+	}
+
+	///u128版のBoardは拡張命令を使わない
+	#[cfg(any(not(target_arch = "x86_64"), feature = "portable"))]
+	pub fn detect() -> Result<CpuFeatures> {
+		Ok(CpuFeatures { _private: () })
+	}
+}
+
+///Envをunsafeなしで扱うためのラッパー、作成時に一度だけCPUの対応を確認する
+pub struct Simulator {
+	env: Env,
+	features: CpuFeatures,
+}
+
+impl Simulator {
+	pub fn new(seed: u32) -> Result<Simulator> {
+		Self::new_with_ojama_order(seed, OjamaDropOrder::Random)
+	}
+
+	pub fn new_with_ojama_order(seed: u32, ojama_drop_order: OjamaDropOrder) -> Result<Simulator> {
+		Self::new_with_rules(seed, ojama_drop_order, Rules::default())
+	}
+
+	pub fn new_with_rules(seed: u32, ojama_drop_order: OjamaDropOrder, rules: Rules) -> Result<Simulator> {
+		let features = CpuFeatures::detect()?;
+
+		rules.validate()?;
+
+		// SAFETY: 必要な拡張命令はdetectで確認済み
+		let env = unsafe {
+			let mut env = Env::new_with_rules(&seed, ojama_drop_order, rules);
+			env.init();
+			env
+		};
+
+		Ok(Simulator { env, features })
+	}
+
+	#[inline]
+	pub fn features(&self) -> CpuFeatures {
+		self.features
+	}
+
+	///読み取り専用で中のEnvを返す
+	#[inline]
+	pub fn env(&self) -> &Env {
+		&self.env
+	}
+
+	#[inline]
+	pub fn board(&self) -> &Board {
+		&self.env.board
+	}
+
+	// 以下のunsafeは全て、Simulatorが存在する時点でdetectが成功していることを根拠にする
+
+	pub fn update(&mut self) {
+		unsafe { self.env.update() }
+	}
+
+	///現在のフレームまでのイベントを処理する、連鎖の攻撃は相手がいれば送る
+	pub fn process_events(&mut self, opponent: Option<&mut Simulator>) -> bool {
+		self.env.process_events(self.env.current_frame, opponent.map(|opponent| &mut opponent.env))
+	}
+
+	pub fn create_new_puyo(&mut self) {
+		unsafe { self.env.create_new_puyo() }
+	}
+
+	pub fn move_right(&mut self) {
+		unsafe { self.env.move_right() }
+	}
+
+	pub fn move_left(&mut self) {
+		unsafe { self.env.move_left() }
+	}
+
+	pub fn rotate_cw(&mut self) {
+		unsafe { self.env.rotate_cw() }
+	}
+
+	pub fn rotate_ccw(&mut self) {
+		unsafe { self.env.rotate_ccw() }
+	}
+
+	pub fn rotate_180(&mut self) {
+		unsafe { self.env.rotate_180() }
+	}
+
+	///1段下げる、下がれなければ設置する
+	pub fn soft_drop(&mut self) {
+		unsafe { self.env.soft_drop() }
+	}
+
+	///自然落下を1フレーム進める、接地して設置したらtrue
+	pub fn fall_one_frame(&mut self) -> bool {
+		unsafe { self.env.fall_one_frame() }
+	}
+
+	///ぷよを設置して連鎖を処理する、攻撃はprocess_eventsで送られる
+	pub fn quick_drop(&mut self) {
+		unsafe { self.env.quick_drop() }
+	}
+
+	///操作中のぷよがあるか
+	#[inline]
+	pub fn is_controllable(&self) -> bool {
+		self.env.center_puyo != PuyoKind::Empty && self.env.movable_puyo != PuyoKind::Empty
+	}
+
+	///[軸ぷよ, 子ぷよ]
+	#[inline]
+	pub fn current_pair(&self) -> [PuyoKind; 2] {
+		[self.env.center_puyo, self.env.movable_puyo]
+	}
+
+	#[inline]
+	pub fn puyo_status(&self) -> &PuyoStatus {
+		&self.env.puyo_status
+	}
+
+	#[inline]
+	pub fn next(&self) -> [[PuyoKind; 2]; 2] {
+		self.env.next
+	}
+
+	#[inline]
+	pub fn score(&self) -> usize {
+		self.env.current_score
+	}
+
+	#[inline]
+	pub fn is_dead(&self) -> bool {
+		self.env.dead
+	}
+
+	#[inline]
+	pub fn is_all_cleared(&self) -> bool {
+		self.env.all_cleared
+	}
+
+	#[inline]
+	pub fn last_chain_count(&self) -> usize {
+		self.env.chain_steps.len()
+	}
+
+	///最後に設置したぷよで起きた連鎖の各段
+	#[inline]
+	pub fn chain_steps(&self) -> &[ChainStep] {
+		&self.env.chain_steps
+	}
+
+	pub fn ojama_size(&self) -> usize {
+		self.env.ojama.get_all_ojama_size()
+	}
+
+	pub fn receive_ojama(&mut self, ojama_size: usize, receive_frame: usize) {
+		self.env.ojama.push(ojama_size, receive_frame)
+	}
+
+	///各列の高さ、床と壁を含む
+	pub fn heights(&self) -> [u16; 8] {
+		unsafe { self.env.board.get_heights() }
+	}
+
+	///壁を含む座標のぷよ、範囲外ならNone
+	pub fn cell(&self, x: u8, y: u8) -> Option<PuyoKind> {
+		if x >= WIDTH_WITH_BORDER || y >= HEIGHT_WITH_BORDER {
+			return None;
+		}
+
+		Some(self.env.board.get_cell(x as i16, y as i16))
+	}
+
+	pub fn board_string(&self) -> String {
+		unsafe { self.env.board.to_str() }
+	}
+
+	///文字列から盤面を置き換える、形式はBoard::from_strと同じ
+	pub fn set_board_from_str(&mut self, board: &str) -> Result<()> {
+		let cells = board.chars().take(WIDTH_WITH_BORDER as usize * HEIGHT_WITH_BORDER as usize).collect::<Vec<_>>();
+		if cells.len() != WIDTH_WITH_BORDER as usize * HEIGHT_WITH_BORDER as usize {
+			bail!("board string is too short: {} chars", cells.len());
+		}
+		if let Some(c) = cells.iter().find(|c| !"EOWRGBYP".contains(**c)) {
+			bail!("invalid cell '{}'", c);
+		}
+
+		self.env.board = unsafe { Board::from_str(board) };
+		Ok(())
+	}
+}
@@ -0,0 +1,49 @@
+﻿use crate::puyo_kind::PuyoKind;
+
+///マスとぷよの種類ごとの乱数、Boardのハッシュはその盤面のマスの値を全てxorしたもの
+///空のマスは0なので、置いたマスと消えたマスだけxorし直せば更新できる
+pub static KEYS: [[[u64; 8]; 16]; 8] = generate_keys();
+
+const fn generate_keys() -> [[[u64; 8]; 16]; 8] {
+	let mut keys = [[[0; 8]; 16]; 8];
+	let mut state = 0x9E3779B97F4A7C15u64;
+
+	let mut x = 0;
+	while x < 8 {
+		let mut y = 0;
+		while y < 16 {
+			//PuyoKind::Emptyの0は飛ばす
+			let mut kind = 1;
+			while kind < 8 {
+				//splitmix64
+				state = state.wrapping_add(0x9E3779B97F4A7C15);
+				let mut z = state;
+				z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+				z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+				keys[x][y][kind] = z ^ (z >> 31);
+				kind += 1;
+			}
+			y += 1;
+		}
+		x += 1;
+	}
+
+	keys
+}
+
+#[inline]
+pub fn cell_key(x: u8, y: u8, puyo_kind: PuyoKind) -> u64 {
+	KEYS[x as usize][y as usize][puyo_kind as usize]
+}
+
+///1列分のハッシュ、v1からv3はその列の3枚のbitboard
+#[inline]
+pub fn column_hash(x: usize, v1: u16, v2: u16, v3: u16) -> u64 {
+	let mut hash = 0;
+	for (y, keys) in KEYS[x].iter().enumerate() {
+		let kind = ((v3 >> y) & 1) << 2 | ((v2 >> y) & 1) << 1 | ((v1 >> y) & 1);
+		hash ^= keys[kind as usize];
+	}
+
+	hash
+}
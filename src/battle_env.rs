@@ -1,40 +1,83 @@
-﻿use std::collections::VecDeque;
+﻿use std::fs;
+use std::io::Error;
+use std::path::PathBuf;
 use std::time::Instant;
-use rand::{Rng, thread_rng};
 
 use ai::build_ai::AI;
 use ai::evaluator::Evaluator;
 use ai::key_type::KeyType;
 use ai::opponent_status::OpponentStatus;
-use env::env::{Env, Event};
-use env::event_type::EventType;
+use env::env::Env;
+use env::ojama_placer::OjamaDropOrder;
 use env::puyo_kind::PuyoKind;
+use env::rules::Rules;
+
+use crate::controller::{AIController, Controller, PlayerView};
+use crate::replay::Replay;
+
+///1試合分のプレイヤーごとの集計
+#[derive(Debug, Clone, Default)]
+pub struct PlayerStats {
+	///設置した回数
+	pub drops: usize,
+	///発火した連鎖の連鎖数
+	pub chains: Vec<u8>,
+	///相殺した残りで相手に送ったお邪魔
+	pub sent_ojama: usize,
+}
+
+impl PlayerStats {
+	///入力した後に呼ぶ、ぷよを設置していれば数える
+	pub unsafe fn record(&mut self, env: &Env) {
+		if env.center_puyo == PuyoKind::Empty {
+			self.drops += 1;
+			if !env.chain_steps.is_empty() {
+				self.chains.push(env.chain_steps.len() as u8);
+			}
+		}
+	}
+}
 
-pub struct BattleEnv<E: Evaluator> {
+pub struct BattleEnv<C1: Controller, C2: Controller> {
 	pub player1: Env,
-	player1_inputs: VecDeque<KeyType>,
-	player1_ai: AI<E>,
+	player1_controller: C1,
 	player1_opponent_status: OpponentStatus,
 	pub player2: Env,
-	player2_inputs: VecDeque<KeyType>,
-	player2_ai: AI<E>,
+	player2_controller: C2,
 	player2_opponent_status: OpponentStatus,
 	pub game_frame: usize,
+	pub replay: Replay,
+	///Someなら決着時にリプレイをこのディレクトリへ保存する
+	pub replay_dir: Option<PathBuf>,
+	pub stats: [PlayerStats; 2],
+	///まだ押せない間隔だったので次のフレームに入力するキー
+	pending_keys: [Option<KeyType>; 2],
+}
+
+impl<E1: Evaluator, E2: Evaluator> BattleEnv<AIController<E1>, AIController<E2>> {
+	///両プレイヤーとAIに同じルールを使わせる
+	pub unsafe fn new_with_rules(player1_ai: AI<E1>, player2_ai: AI<E2>, seed: u32, rules: Rules) -> Self {
+		Self::new_with_controllers(AIController::new(player1_ai), AIController::new(player2_ai), seed, rules)
+	}
 }
 
-impl<E: Evaluator> BattleEnv<E> {
-	pub unsafe fn new(player1_ai: AI<E>, player2_ai: AI<E>) -> Self {
-		let seed = thread_rng().gen();
+impl<C1: Controller, C2: Controller> BattleEnv<C1, C2> {
+	pub unsafe fn new_with_controllers(mut player1_controller: C1, mut player2_controller: C2, seed: u32, rules: Rules) -> Self {
+		player1_controller.on_game_start(0, &rules);
+		player2_controller.on_game_start(1, &rules);
+
 		let mut battle_env = BattleEnv {
-			player1: Env::new(&seed),
-			player2: Env::new(&seed),
-			player1_ai,
-			player2_ai,
+			player1: Env::new_with_rules(&seed, OjamaDropOrder::Random, rules.clone()),
+			player2: Env::new_with_rules(&seed, OjamaDropOrder::Random, rules.clone()),
+			player1_controller,
+			player2_controller,
 			player1_opponent_status: OpponentStatus::default(),
 			player2_opponent_status: OpponentStatus::default(),
-			player1_inputs: VecDeque::new(),
-			player2_inputs: VecDeque::new(),
 			game_frame: 0,
+			replay: Replay::new(seed, rules),
+			replay_dir: None,
+			stats: Default::default(),
+			pending_keys: [None; 2],
 		};
 
 		battle_env.player1.init();
@@ -43,6 +86,11 @@ impl<E: Evaluator> BattleEnv<E> {
 		battle_env
 	}
 
+	///両プレイヤーのコントローラー
+	pub fn controllers_mut(&mut self) -> (&mut C1, &mut C2) {
+		(&mut self.player1_controller, &mut self.player2_controller)
+	}
+
 	///死んだプレイヤーを判定する、勝ったプレイヤー番号を返す、いなければ-1
 	#[inline]
 	pub fn check_winner(&self) -> i8 {
@@ -60,88 +108,102 @@ impl<E: Evaluator> BattleEnv<E> {
 		self.player2.update();
 
 		if self.game_frame % 60 == 0 {
-			self.player1_opponent_status = OpponentStatus::new(&self.player2.board);
-			self.player2_opponent_status = OpponentStatus::new(&self.player1.board);
+			self.player1_opponent_status = OpponentStatus::new(&self.player2.board, &self.player2.ojama_rate, self.player2.rules.colors());
+			self.player2_opponent_status = OpponentStatus::new(&self.player1.board, &self.player1.ojama_rate, self.player1.rules.colors());
 		}
 
-		if Self::update_player(self.game_frame, &mut self.player1.events) {
-			Self::process_key_inputs(&mut self.player1_inputs, &mut self.player1, &mut self.player1_ai, &mut self.player2, &self.player1_opponent_status);
+		let opponent_ojama = self.player2.ojama.get_all_ojama_size();
+		let controllable = self.player1.process_events(self.game_frame, Some(&mut self.player2));
+		let sent = self.player2.ojama.get_all_ojama_size().saturating_sub(opponent_ojama);
+		self.stats[0].sent_ojama += sent;
+		self.replay.record_ojama(0, self.game_frame, sent);
+		if controllable {
+			let (stats, replay, frame) = (&mut self.stats[0], &mut self.replay, self.game_frame);
+			process_key_inputs(&mut self.player1_controller, &mut self.player1, &mut self.pending_keys[0], &self.player1_opponent_status, frame, |key, env| {
+				stats.record(env);
+				replay.record_input(0, frame, key, env);
+			});
+		}
+		if self.player1.fall_one_frame() {
+			self.player1_controller.on_piece_locked(&PlayerView { env: &self.player1, opponent_status: &self.player1_opponent_status, frame: self.game_frame });
+			self.stats[0].record(&self.player1);
+			self.replay.record_lock(0, self.game_frame, &self.player1);
 		}
 
-		if Self::update_player(self.game_frame, &mut self.player2.events) {
-			Self::process_key_inputs(&mut self.player2_inputs, &mut self.player2, &mut self.player2_ai, &mut self.player1, &self.player2_opponent_status);
+		let opponent_ojama = self.player1.ojama.get_all_ojama_size();
+		let controllable = self.player2.process_events(self.game_frame, Some(&mut self.player1));
+		let sent = self.player1.ojama.get_all_ojama_size().saturating_sub(opponent_ojama);
+		self.stats[1].sent_ojama += sent;
+		self.replay.record_ojama(1, self.game_frame, sent);
+		if controllable {
+			let (stats, replay, frame) = (&mut self.stats[1], &mut self.replay, self.game_frame);
+			process_key_inputs(&mut self.player2_controller, &mut self.player2, &mut self.pending_keys[1], &self.player2_opponent_status, frame, |key, env| {
+				stats.record(env);
+				replay.record_input(1, frame, key, env);
+			});
+		}
+		if self.player2.fall_one_frame() {
+			self.player2_controller.on_piece_locked(&PlayerView { env: &self.player2, opponent_status: &self.player2_opponent_status, frame: self.game_frame });
+			self.stats[1].record(&self.player2);
+			self.replay.record_lock(1, self.game_frame, &self.player2);
 		}
 
 		self.game_frame += 1;
-	}
-	#[inline]
-	///イベント処理
-	fn update_player(current_frame: usize, events: &mut VecDeque<Event>) -> bool {
-		while events.len() != 0 {
-			if events[0].frame <= current_frame {
-				match events[0].kind {
-					EventType::Wait => {
-						if events[0].value == 0 {
-							events.remove(0);
-							continue;
-						}
-
-						events[0].value -= 1;
-						if events[0].value == 0 {
-							events.remove(0);
-						} else {
-							return false;
-						}
-					}
-					_ => panic!()
+
+		let winner = self.check_winner();
+		if winner != -1 && self.replay.winner == -1 {
+			self.replay.finish(winner, self.game_frame);
+			self.player1_controller.on_game_end(winner == 1);
+			self.player2_controller.on_game_end(winner == 2);
+
+			if let Some(dir) = &self.replay_dir {
+				if let Err(e) = Self::save_replay(&self.replay, dir) {
+					println!("failed to save replay: {e}");
 				}
-			} else {
-				break;
 			}
 		}
+	}
 
-		true
+	///リプレイを日時とseedの付いたファイル名で保存する
+	pub fn save_replay(replay: &Replay, dir: &PathBuf) -> Result<PathBuf, Error> {
+		fs::create_dir_all(dir)?;
+		let file_name = format!("replay_{}_{}.json", chrono::Local::now().format("%Y%m%d_%H%M%S%3f"), replay.seed);
+		let path = dir.join(file_name);
+		replay.save(&path)?;
+		Ok(path)
 	}
-	#[inline]
-	//指定したプレイヤーのAI操作をします
-	unsafe fn process_key_inputs(player_inputs: &mut VecDeque<KeyType>, env: &mut Env, ai: &mut AI<E>, opponent: &mut Env, opponent_status: &OpponentStatus) {
-		if env.center_puyo == PuyoKind::Empty &&
-			env.movable_puyo == PuyoKind::Empty {
-			env.create_new_puyo();
+}
+
+#[inline]
+///指定したプレイヤーをcontrollerで操作します、入力の間隔が空いていれば同じフレームに続けて入力する
+///入力したキーごとにon_inputを呼ぶ、入力で設置したらcontrollerに知らせる
+unsafe fn process_key_inputs<C: Controller>(controller: &mut C, env: &mut Env, pending_key: &mut Option<KeyType>, opponent_status: &OpponentStatus, frame: usize, mut on_input: impl FnMut(KeyType, &Env)) {
+	if env.center_puyo == PuyoKind::Empty &&
+		env.movable_puyo == PuyoKind::Empty {
+		env.create_new_puyo();
+		//自然落下で設置されて押せなかったキーは捨てる
+		*pending_key = None;
+		return;
+	}
+
+	while env.center_puyo != PuyoKind::Empty {
+		let key = match pending_key.take() {
+			Some(key) => key,
+			None => match controller.next_key(&PlayerView { env, opponent_status, frame }) {
+				Some(key) => key,
+				None => return,
+			},
+		};
+
+		if !env.can_input(key) {
+			*pending_key = Some(key);
 			return;
 		}
 
-		if player_inputs.len() == 0 {
-			let mut next = Vec::new();
-			for next_p in env.next[0] {
-				next.push(next_p);
-			}
-
-			//	for next_p in env.next[1] {
-			//		next.push(next_p);
-			//	}
-
-		//	let think_start = Instant::now();
-			ai.search(&env.board, &env.puyo_status, &next, &env.ojama, env.center_puyo, env.movable_puyo, env.all_cleared, &env.ojama_rate, opponent_status);
-		//	let time = think_start.elapsed().as_millis();
-			//println!("{}", format!("{:04}", time));
-			//dbg!(think_start.elapsed().as_millis());
-		//	dbg!(&ai.debug);
-
-			*player_inputs = ai.best_move.as_ref().unwrap().path.to_vec().into();
-		} else {
-			match player_inputs.pop_front().unwrap() {
-				KeyType::Right => { env.move_right() }
-				KeyType::Left => { env.move_left() }
-				KeyType::Top => { panic!() }
-				KeyType::Down => { panic!() }
-				KeyType::Drop => {
-					env.quick_drop(Some(opponent))
-				}
-				KeyType::RotateRight => { env.rotate_ccw() }
-				KeyType::RotateLeft => { env.rotate_cw() }
-				KeyType::Rotate180 => { env.rotate_180() }
-			}
+		env.input(key);
+		on_input(key, env);
+		if env.center_puyo == PuyoKind::Empty {
+			controller.on_piece_locked(&PlayerView { env, opponent_status, frame });
 		}
 	}
-}
\ No newline at end of file
+}
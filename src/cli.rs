@@ -0,0 +1,221 @@
+﻿use std::fs;
+use std::path::PathBuf;
+
+use clap::{Parser, Subcommand, ValueEnum};
+
+use env::rules::Rules;
+
+use crate::tournament::TournamentFormat;
+
+#[derive(Debug, Parser)]
+#[command(name = "puyoai", about = "ぷよぷよ通のAIの対戦・学習・評価")]
+pub struct Cli {
+	///rayonのスレッド数、省略すると論理コア数
+	#[arg(long, global = true)]
+	pub threads: Option<usize>,
+	#[command(subcommand)]
+	pub command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+	///コンソールでAIと対戦する
+	Play {
+		///相手のネットワーク
+		#[arg(long)]
+		net: PathBuf,
+		#[command(flatten)]
+		game: GameArgs,
+		///指定すると決着ごとにリプレイを保存する
+		#[arg(long)]
+		replay_dir: Option<PathBuf>,
+	},
+	///1人で積む練習、待ったとAIのヒントが使える
+	Solo {
+		///ヒントに使うネットワーク
+		#[arg(long)]
+		net: PathBuf,
+		#[command(flatten)]
+		game: GameArgs,
+	},
+	///2つのネットワークの対戦をコンソールに等速で表示する
+	Watch {
+		#[arg(long)]
+		net1: PathBuf,
+		#[arg(long)]
+		net2: PathBuf,
+		#[command(flatten)]
+		game: GameArgs,
+		///決着時にリプレイを保存するディレクトリ
+		#[arg(long, default_value = "replays")]
+		replay_dir: PathBuf,
+	},
+	///2つのネットワークを表示なしで先取まで対戦させる
+	Battle {
+		#[arg(long)]
+		net1: PathBuf,
+		#[arg(long)]
+		net2: PathBuf,
+		#[command(flatten)]
+		game: GameArgs,
+		///この勝利数に先に届いた方の勝ち
+		#[arg(long, default_value_t = 30)]
+		first_to: usize,
+		///指定すると決着ごとにリプレイを保存する
+		#[arg(long)]
+		replay_dir: Option<PathBuf>,
+	},
+	///対戦による神経進化で学習する
+	Train {
+		#[arg(long, default_value_t = 64)]
+		population: u32,
+		#[arg(long, default_value_t = 999999999)]
+		generations: u32,
+		///学習中の対戦のルール
+		#[arg(long, default_value = "tsu", value_parser = parse_rules)]
+		rules: Rules,
+		///学習過程のログ
+		#[arg(long, default_value = "log.txt")]
+		log: PathBuf,
+		///featuresで書き出したCSV、指定すると平均と標準偏差で入力を揃えて学習する
+		///正規化はカレントディレクトリのnormalization.jsonに保存され、同じディレクトリのネットワークを読む時に使われる
+		#[arg(long)]
+		features: Option<PathBuf>,
+	},
+	///複数のネットワークでリーグ戦を行いEloを求める
+	Tournament {
+		///参加するネットワーク、名前はファイル名になる。simpleと書くと手で調整したSimpleEvaluatorが参加する
+		#[arg(required = true, num_args = 2..)]
+		nets: Vec<PathBuf>,
+		#[arg(long, value_enum, default_value_t = TournamentFormat::RoundRobin)]
+		format: TournamentFormat,
+		///1組あたりの試合数
+		#[arg(long, default_value_t = 10)]
+		games: usize,
+		///最初の試合のseed
+		#[arg(long, default_value_t = 0)]
+		seed: u32,
+		///プリセット名かルールのjson
+		#[arg(long, default_value = "tsu", value_parser = parse_rules)]
+		rules: Rules,
+		///このフレームまでに決着しなければ引き分け
+		#[arg(long, default_value_t = 36000)]
+		max_frames: usize,
+		///結果のjsonを保存するディレクトリ
+		#[arg(long, default_value = ".")]
+		out: PathBuf,
+	},
+	///1人で置いた時に選んだ置き方の特徴量をCSVに書き出す
+	Features {
+		#[arg(long)]
+		net: PathBuf,
+		#[command(flatten)]
+		game: GameArgs,
+		///試合数、seedを指定した場合は1試合ごとに1つずつずらす
+		#[arg(long, default_value_t = 10)]
+		games: usize,
+		///1試合で置く最大の数
+		#[arg(long, default_value_t = 100)]
+		placements: usize,
+		#[arg(long, default_value = "features.csv")]
+		out: PathBuf,
+	},
+	///featuresで書き出したCSVから正規化を求めて、ネットワークのjsonに書き込む
+	Normalize {
+		net: PathBuf,
+		features: PathBuf,
+		#[arg(long, value_enum, default_value_t = NormalizationMethod::MeanStd)]
+		method: NormalizationMethod,
+		///省略するとnetを上書きする
+		#[arg(long)]
+		out: Option<PathBuf>,
+	},
+	///リプレイを検証して集計を表示する
+	Analyze {
+		replay: PathBuf,
+		///集計の後にリプレイビューアを開く
+		#[arg(long)]
+		view: bool,
+	},
+	///ぷよぷよeスポーツ上でAIを動かす
+	#[cfg(feature = "ppc")]
+	Ppc {
+		#[arg(long)]
+		net: PathBuf,
+	},
+}
+
+///特徴量の揃え方
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum NormalizationMethod {
+	///平均を0、標準偏差を1にする
+	MeanStd,
+	///最小値を0、最大値を1にする
+	MinMax,
+}
+
+///1試合ごとの設定
+#[derive(Debug, clap::Args)]
+pub struct GameArgs {
+	///ツモのseed、省略するとランダム
+	#[arg(long)]
+	pub seed: Option<u32>,
+	///プリセット名(tsu, tsu-long-margin, tsu-without-margin, tsu-three-colors, tsu-five-colors)かルールのjson
+	#[arg(long, default_value = "tsu", value_parser = parse_rules)]
+	pub rules: Rules,
+}
+
+///プリセット名でなければjsonファイルとして読む
+pub fn parse_rules(value: &str) -> Result<Rules, String> {
+	let rules = match value {
+		"tsu" => Rules::tsu(),
+		"tsu-long-margin" => Rules::tsu_long_margin(),
+		"tsu-without-margin" => Rules::tsu_without_margin(),
+		"tsu-three-colors" => Rules::tsu_three_colors(),
+		"tsu-five-colors" => Rules::tsu_five_colors(),
+		path => {
+			let json = fs::read_to_string(path).map_err(|e| format!("unknown preset and unreadable file {path}: {e}"))?;
+			serde_json::from_str(&json).map_err(|e| format!("invalid rules {path}: {e}"))?
+		}
+	};
+
+	rules.validate().map_err(|e| e.to_string())?;
+	Ok(rules)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn parse_rules_test() {
+		assert_eq!(parse_rules("tsu-five-colors").unwrap(), Rules::tsu_five_colors());
+		assert!(parse_rules("no-such-rules").is_err());
+	}
+
+	#[test]
+	fn parse_tournament_test() {
+		let cli = Cli::try_parse_from(["puyoai", "tournament", "a.json", "b.json", "--format", "gauntlet", "--threads", "4"]).unwrap();
+		assert_eq!(cli.threads, Some(4));
+		let Command::Tournament { nets, format, rules, .. } = cli.command else {
+			panic!("not a tournament");
+		};
+		assert_eq!(nets.len(), 2);
+		assert_eq!(format, TournamentFormat::Gauntlet);
+		assert_eq!(rules, Rules::tsu());
+
+		//参加者が1人では対戦できない
+		assert!(Cli::try_parse_from(["puyoai", "tournament", "a.json"]).is_err());
+	}
+
+	#[test]
+	fn parse_normalize_test() {
+		let cli = Cli::try_parse_from(["puyoai", "normalize", "net.json", "features.csv", "--method", "min-max"]).unwrap();
+		let Command::Normalize { net, method, out, .. } = cli.command else {
+			panic!("not normalize");
+		};
+		assert_eq!(net, PathBuf::from("net.json"));
+		assert_eq!(method, NormalizationMethod::MinMax);
+		assert_eq!(out, None);
+	}
+}
@@ -0,0 +1,501 @@
+﻿use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use ai::async_search::{AsyncSearch, SearchRequest};
+use ai::build_ai::AI;
+use ai::evaluator::Evaluator;
+use ai::key_type::KeyType;
+use ai::move_generator::{MoveGenerator, MoveOrigin, Placement};
+use ai::opponent_status::OpponentStatus;
+use env::env::Env;
+use env::puyo_kind::PuyoKind;
+use env::puyo_status::PuyoStatus;
+use env::rules::Rules;
+use env::vector2::Vector2;
+
+use crate::replay::{Replay, ReplayInput, ReplayLock};
+use crate::replay_player::ReplayMismatch;
+
+///操作する側から見た対戦の状態
+pub struct PlayerView<'a> {
+	pub env: &'a Env,
+	///1秒ごとに更新される相手の盤面の評価
+	pub opponent_status: &'a OpponentStatus,
+	pub frame: usize,
+}
+
+///BattleEnvのプレイヤーを操作する
+pub trait Controller {
+	///対戦が始まるときに1度呼ばれる
+	fn on_game_start(&mut self, _player_index: usize, _rules: &Rules) {}
+
+	///操作できるフレームごとに呼ばれる、入力するキーを返す。Noneならこのフレームは何もしない
+	unsafe fn next_key(&mut self, view: &PlayerView) -> Option<KeyType>;
+
+	///操作していたぷよが設置されたときに呼ばれる、自然落下で経路の途中で設置されることもある
+	fn on_piece_locked(&mut self, _view: &PlayerView) {}
+
+	///決着したときに1度呼ばれる
+	fn on_game_end(&mut self, _won: bool) {}
+}
+
+impl<C: Controller + ?Sized> Controller for Box<C> {
+	fn on_game_start(&mut self, player_index: usize, rules: &Rules) {
+		(**self).on_game_start(player_index, rules);
+	}
+
+	unsafe fn next_key(&mut self, view: &PlayerView) -> Option<KeyType> {
+		(**self).next_key(view)
+	}
+
+	fn on_piece_locked(&mut self, view: &PlayerView) {
+		(**self).on_piece_locked(view);
+	}
+
+	fn on_game_end(&mut self, won: bool) {
+		(**self).on_game_end(won);
+	}
+}
+
+///AIで操作する、探索したフレームから経路を押せる順に入力する
+///経路は探索したフレームの自然落下を数えて作られるので、そのフレームに最初のキーを押す
+pub struct AIController<E: Evaluator> {
+	pub ai: AI<E>,
+	inputs: VecDeque<KeyType>,
+}
+
+impl<E: Evaluator> AIController<E> {
+	pub fn new(ai: AI<E>) -> Self {
+		AIController {
+			ai,
+			inputs: VecDeque::new(),
+		}
+	}
+}
+
+impl<E: Evaluator> Controller for AIController<E> {
+	fn on_game_start(&mut self, _player_index: usize, rules: &Rules) {
+		self.ai.rules = rules.clone();
+		self.inputs.clear();
+	}
+
+	unsafe fn next_key(&mut self, view: &PlayerView) -> Option<KeyType> {
+		if let Some(key) = self.inputs.pop_front() {
+			return Some(key);
+		}
+
+		let env = view.env;
+		self.ai.search(&env.board, &MoveOrigin::from_env(env), &env.next, &env.ojama, env.center_puyo, env.movable_puyo, env.all_cleared, &env.ojama_rate, view.opponent_status);
+		self.inputs = self.ai.best_move.as_ref().unwrap().path.to_vec().into();
+		self.inputs.pop_front()
+	}
+
+	fn on_piece_locked(&mut self, _view: &PlayerView) {
+		//残りの経路は設置したぷよのものなので次のぷよには使わない
+		self.inputs.clear();
+	}
+}
+
+///AIを別スレッドで探索させて操作する、入力している間に次のぷよの置き方を先読みする
+///1フレームに探索を待つのはframe_budgetまでで、間に合わなければそのフレームは何もしない
+///待っている間もぷよは落ちるので、選んだ置き方へは結果を受け取ったフレームの位置から経路を作り直す
+pub struct AsyncAIController<E: Evaluator + 'static> {
+	ai: AI<E>,
+	frame_budget: Duration,
+	search: Option<AsyncSearch>,
+	inputs: VecDeque<KeyType>,
+	///今のぷよの探索の番号と、その時に予告されていたお邪魔の数と探索を始めた位置
+	target: Option<(u64, usize, MoveOrigin)>,
+	///先読みした局面の番号と、置く前の盤面とぷよ
+	prefetch: Option<(u64, String, [PuyoKind; 2])>,
+}
+
+impl<E: Evaluator + 'static> AsyncAIController<E> {
+	pub fn new(ai: AI<E>, frame_budget: Duration) -> Self {
+		AsyncAIController {
+			ai,
+			frame_budget,
+			search: None,
+			inputs: VecDeque::new(),
+			target: None,
+			prefetch: None,
+		}
+	}
+
+	///statusに今のぷよを置いた後の局面、まだ見えていない2つ目のネクストは含まない
+	unsafe fn predict(env: &Env, status: &PuyoStatus, view: &PlayerView) -> SearchRequest {
+		let mut placed = env.clone();
+		placed.puyo_status = status.clone();
+		placed.quick_drop();
+
+		let mut request = SearchRequest::from_env(&placed, view.opponent_status);
+		request.current = MoveOrigin::spawn(&env.rules);
+		request.movable_puyo = env.next[0][0];
+		request.center_puyo = env.next[0][1];
+		request.next = vec![env.next[1]];
+		request
+	}
+
+	///originから探索したpathと同じ盤面になる置き方へ、今の位置から動かす経路。落ちて行けなくなっていればNone
+	unsafe fn replan(env: &Env, origin: &MoveOrigin, path: &[KeyType]) -> Option<Placement> {
+		let timing = &env.rules.input_timing;
+		let planned = MoveGenerator::generate(&env.board, origin, env.center_puyo, env.movable_puyo, timing)
+			.into_iter()
+			.find(|placement| placement.path == path)?;
+		let target = Self::placed_board(env, &planned.status);
+
+		MoveGenerator::generate(&env.board, &MoveOrigin::from_env(env), env.center_puyo, env.movable_puyo, timing)
+			.into_iter()
+			.find(|placement| Self::placed_board(env, &placement.status) == target)
+	}
+
+	unsafe fn placed_board(env: &Env, status: &PuyoStatus) -> String {
+		let mut board = env.board.clone();
+		board.put_puyo(status, &env.center_puyo, &env.movable_puyo, &mut Vector2::default());
+		board.to_str()
+	}
+}
+
+impl<E: Evaluator + 'static> Controller for AsyncAIController<E> {
+	fn on_game_start(&mut self, _player_index: usize, rules: &Rules) {
+		self.ai.rules = rules.clone();
+		self.search = Some(AsyncSearch::new(self.ai.clone()));
+		self.inputs.clear();
+		self.target = None;
+		self.prefetch = None;
+	}
+
+	unsafe fn next_key(&mut self, view: &PlayerView) -> Option<KeyType> {
+		if let Some(key) = self.inputs.pop_front() {
+			return Some(key);
+		}
+
+		let env = view.env;
+		let search = self.search.as_mut().unwrap();
+		let deadline = Instant::now() + self.frame_budget;
+		let ojama_size = env.ojama.get_all_ojama_size();
+
+		//先読みが今の局面と同じなら、ネクストが1つ増えた探索が間に合わなかった時に使う
+		let mut fallback = None;
+		if self.target.is_none() {
+			if let Some((id, board, pair)) = self.prefetch.take() {
+				if id == search.latest_id() && board == env.board.to_str() && pair == [env.movable_puyo, env.center_puyo] {
+					fallback = search.result(deadline).map(|result| result.best_move.clone());
+				}
+			}
+		}
+
+		//新しいぷよか、お邪魔が届いたら探索し直す
+		if self.target.as_ref().is_none_or(|(_, size, _)| *size != ojama_size) {
+			let request = SearchRequest::from_env(env, view.opponent_status);
+			let origin = request.current.clone();
+			let id = search.start(request);
+			self.target = Some((id, ojama_size, origin));
+		}
+
+		let (origin, best_move) = match search.result(deadline) {
+			Some(result) => (self.target.as_ref().unwrap().2.clone(), result.best_move.clone()),
+			None => (MoveOrigin::spawn(&env.rules), fallback?),
+		};
+
+		//探索した位置から落ちているので、今の位置から同じ置き方への経路を作り直す
+		let Some(placement) = Self::replan(env, &origin, &best_move.path) else {
+			//もう置けないなら今の位置から探索し直す
+			self.target = None;
+			return None;
+		};
+
+		//入力している間に次のぷよを考えておく
+		let next_request = Self::predict(env, &placement.status, view);
+		let board = next_request.board.to_str();
+		let id = search.start(next_request);
+		self.prefetch = Some((id, board, env.next[0]));
+		self.target = None;
+
+		//経路はこのフレームから押す前提で作られている
+		self.inputs = placement.path.into();
+		self.inputs.pop_front()
+	}
+
+	fn on_piece_locked(&mut self, _view: &PlayerView) {
+		//残りの経路は設置したぷよのもの、先読みは盤面が違えば使われない
+		self.inputs.clear();
+		self.target = None;
+	}
+
+	fn on_game_end(&mut self, _won: bool) {
+		//探索スレッドを止める
+		self.search = None;
+	}
+}
+
+///キーボードで操作する、キーは呼び出し側がConsoleから読んでpushする
+///cloneしたものは同じキューを共有する。設置した時に残っていたキーは次のぷよに入力する
+#[derive(Clone, Default)]
+pub struct KeyboardController {
+	keys: Rc<RefCell<VecDeque<KeyType>>>,
+}
+
+impl KeyboardController {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	///操作できるようになったフレームから1フレームに1つずつ入力される
+	pub fn push(&self, key: KeyType) {
+		self.keys.borrow_mut().push_back(key);
+	}
+}
+
+impl Controller for KeyboardController {
+	fn on_game_start(&mut self, _player_index: usize, _rules: &Rules) {
+		self.keys.borrow_mut().clear();
+	}
+
+	unsafe fn next_key(&mut self, _view: &PlayerView) -> Option<KeyType> {
+		self.keys.borrow_mut().pop_front()
+	}
+}
+
+///リプレイに記録された入力を同じフレームで入力する、設置も記録と同じフレームで起きるので入力は捨てない
+///設置するごとに盤面を記録された設置と比べて、最初に違った所を覚えておく
+pub struct ReplayController {
+	player_index: usize,
+	inputs: VecDeque<ReplayInput>,
+	locks: Vec<ReplayLock>,
+	///ここまでに設置した回数
+	lock_count: usize,
+	mismatch: Option<ReplayMismatch>,
+}
+
+impl ReplayController {
+	pub fn new(replay: &Replay, player_index: usize) -> Self {
+		let record = &replay.players[player_index];
+		ReplayController {
+			player_index,
+			inputs: record.inputs.iter().cloned().collect(),
+			locks: record.locks.clone(),
+			lock_count: 0,
+			mismatch: None,
+		}
+	}
+
+	///記録と違った設置を取り出す、取り出した後も続けて比べる
+	pub fn take_mismatch(&mut self) -> Option<ReplayMismatch> {
+		self.mismatch.take()
+	}
+
+	///記録された設置のうちまだ起きていない最初のもの
+	pub fn missing_lock(&self) -> Option<ReplayMismatch> {
+		self.locks.get(self.lock_count).map(|lock| ReplayMismatch {
+			player: self.player_index,
+			frame: lock.frame,
+			lock_index: self.lock_count,
+			expected: Some(lock.board_hash),
+			actual: None,
+		})
+	}
+}
+
+impl Controller for ReplayController {
+	fn on_piece_locked(&mut self, view: &PlayerView) {
+		let lock_index = self.lock_count;
+		self.lock_count += 1;
+
+		let expected = self.locks.get(lock_index).map(|lock| lock.board_hash);
+		let actual = unsafe { Replay::board_hash(&view.env.board) };
+		if expected != Some(actual) && self.mismatch.is_none() {
+			self.mismatch = Some(ReplayMismatch {
+				player: self.player_index,
+				frame: view.frame,
+				lock_index,
+				expected,
+				actual: Some(actual),
+			});
+		}
+	}
+
+	unsafe fn next_key(&mut self, view: &PlayerView) -> Option<KeyType> {
+		//記録より遅れた入力も順番は変えずに入力する
+		if self.inputs.front()?.frame > view.frame {
+			return None;
+		}
+
+		self.inputs.pop_front().map(|input| input.key)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use ai::evaluator::simple_evaluator::SimpleEvaluator;
+	use ai::search_config::SearchConfig;
+
+	use crate::battle_env::BattleEnv;
+
+	use super::*;
+
+	unsafe fn create_env() -> Env {
+		let mut env = Env::new(&0);
+		env.init();
+		env
+	}
+
+	#[test]
+	fn ai_controller_test() {
+		unsafe {
+			let mut ai = AI::new(SimpleEvaluator::default());
+			ai.search_config = SearchConfig { depth: 1, ..SearchConfig::visible() };
+			let mut controller = AIController::new(ai);
+			controller.on_game_start(0, &Rules::default());
+
+			let env = create_env();
+			let opponent_status = OpponentStatus::default();
+			let view = PlayerView { env: &env, opponent_status: &opponent_status, frame: 0 };
+
+			//探索したフレームに最初のキーを押す
+			let first = controller.next_key(&view);
+			let rest = controller.inputs.clone();
+			assert!(first.is_some());
+			assert_eq!(rest.back().or(first.as_ref()), Some(&KeyType::Drop));
+			assert_eq!(controller.next_key(&view), rest.front().copied());
+
+			//経路の途中で自然落下で設置されたら、残りは捨てて次のぷよで探索し直す
+			controller.on_piece_locked(&view);
+			assert!(controller.inputs.is_empty());
+			assert_eq!(controller.next_key(&view), first);
+			assert_eq!(controller.inputs, rest);
+		}
+	}
+
+	///BattleEnvと同じく押せるキーを押して毎フレーム落下させ、設置した盤面を返す
+	unsafe fn play_path(env: &mut Env, path: &[KeyType]) -> String {
+		let mut keys = path.iter().copied().peekable();
+		while env.center_puyo != PuyoKind::Empty {
+			while let Some(&key) = keys.peek() {
+				if !env.can_input(key) {
+					break;
+				}
+				env.input(key);
+				keys.next();
+			}
+			if env.center_puyo != PuyoKind::Empty {
+				assert!(!env.fall_one_frame(), "{:?} was locked by gravity", path);
+				env.update();
+			}
+		}
+
+		env.board.to_str()
+	}
+
+	unsafe fn dropped_board(env: &Env, status: &PuyoStatus) -> String {
+		let mut dropped = env.clone();
+		dropped.puyo_status = status.clone();
+		dropped.quick_drop();
+		dropped.board.to_str()
+	}
+
+	#[test]
+	fn replan_test() {
+		unsafe {
+			let env = create_env();
+			let origin = MoveOrigin::from_env(&env);
+			let placements = MoveGenerator::generate(&env.board, &origin, env.center_puyo, env.movable_puyo, &env.rules.input_timing);
+
+			//探索している間に3段落ちても、同じ置き方へ今の位置から動かす
+			let mut fallen = env.clone();
+			while fallen.puyo_status.position.y > env.puyo_status.position.y - 3 {
+				assert!(!fallen.fall_one_frame());
+				fallen.update();
+			}
+			for placement in &placements {
+				let replanned = AsyncAIController::<SimpleEvaluator>::replan(&fallen, &origin, &placement.path).unwrap();
+				assert_eq!(play_path(&mut fallen.clone(), &replanned.path), dropped_board(&env, &placement.status));
+			}
+
+			//2フレームで設置される高さまで落ちたら、端の列にはもう動かせない
+			let mut fast = env.clone();
+			fast.rules.fall_interval_frame = 2;
+			let mut below = fast.puyo_status.clone();
+			while Env::move_puyo(&fast.board, &mut below, 0, -1) {
+				assert!(!fast.fall_one_frame());
+				fast.update();
+				below = fast.puyo_status.clone();
+			}
+			let far = placements.iter().find(|placement| placement.status.position.x == 6).unwrap();
+			assert!(AsyncAIController::<SimpleEvaluator>::replan(&fast, &origin, &far.path).is_none());
+		}
+	}
+
+	#[test]
+	fn async_ai_controller_test() {
+		unsafe {
+			let mut ai = AI::new(SimpleEvaluator::default());
+			ai.search_config = SearchConfig { depth: 2, ..SearchConfig::visible() };
+
+			//自然落下がある対戦でも、選んだ置き方へ設置し続けられる
+			let controller = || AsyncAIController::new(ai.clone(), Duration::from_secs(1));
+			let mut battle_env = BattleEnv::new_with_controllers(controller(), controller(), 1, Rules::default());
+			for _ in 0..1200 {
+				battle_env.update();
+			}
+
+			for stats in &battle_env.stats {
+				assert!(stats.drops >= 5);
+			}
+			assert!(!battle_env.player1.dead && !battle_env.player2.dead);
+		}
+	}
+
+	#[test]
+	fn keyboard_controller_test() {
+		unsafe {
+			let mut controller = KeyboardController::new();
+			let keyboard = controller.clone();
+			keyboard.push(KeyType::Left);
+			keyboard.push(KeyType::Drop);
+
+			let env = create_env();
+			let opponent_status = OpponentStatus::default();
+			let view = PlayerView { env: &env, opponent_status: &opponent_status, frame: 0 };
+			assert_eq!(controller.next_key(&view), Some(KeyType::Left));
+
+			//設置しても押したキーは残る
+			controller.on_piece_locked(&view);
+			assert_eq!(controller.next_key(&view), Some(KeyType::Drop));
+			assert_eq!(controller.next_key(&view), None);
+
+			//対戦が始まる前に押したキーは捨てる
+			keyboard.push(KeyType::Right);
+			controller.on_game_start(0, &Rules::default());
+			assert_eq!(controller.next_key(&view), None);
+		}
+	}
+
+	#[test]
+	fn replay_controller_test() {
+		unsafe {
+			let mut replay = Replay::new(0, Rules::default());
+			replay.players[0].inputs = vec![
+				ReplayInput { frame: 0, key: KeyType::Left },
+				ReplayInput { frame: 5, key: KeyType::Drop },
+			];
+			let mut controller = ReplayController::new(&replay, 0);
+
+			let env = create_env();
+			let opponent_status = OpponentStatus::default();
+			let view = |frame| PlayerView { env: &env, opponent_status: &opponent_status, frame };
+			assert_eq!(controller.next_key(&view(0)), Some(KeyType::Left));
+			assert_eq!(controller.next_key(&view(3)), None);
+
+			//設置しても記録した入力は捨てない、記録に無い設置は違ったとして覚えておく
+			controller.on_piece_locked(&view(3));
+			let mismatch = controller.take_mismatch().unwrap();
+			assert_eq!((mismatch.frame, mismatch.lock_index, mismatch.expected), (3, 0, None));
+			assert!(controller.take_mismatch().is_none());
+			assert_eq!(controller.next_key(&view(5)), Some(KeyType::Drop));
+			assert_eq!(controller.next_key(&view(6)), None);
+		}
+	}
+}
@@ -1,6 +1,7 @@
 ﻿use std::fs::{File, OpenOptions};
 use chrono::Local;
 use std::io::Write;
+use std::path::Path;
 
 #[derive(Debug, PartialEq, strum::EnumString, strum::Display)]
 pub enum LogType {
@@ -14,7 +15,7 @@ pub struct Log {
 }
 
 impl Log {
-	pub fn open(file_path: &str) -> Self {
+	pub fn open<P: AsRef<Path>>(file_path: P) -> Self {
 		let log_file = match OpenOptions::new()
 			.append(true)
 			.create(true)
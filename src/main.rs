@@ -1,31 +1,53 @@
-use std::{fs, thread};
-use std::io::stdin;
+﻿use std::{fs, thread};
+use std::fs::File;
+use std::io::{stdin, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant};
 
+use anyhow::{Context, Result};
+use clap::Parser;
 #[cfg(feature = "ppc")]
 use ppc::scp::Controller;
+use rand::{Rng, thread_rng};
 use revonet::ea::{EA};
 use revonet::ne::NE;
 use revonet::neuro::MultilayeredNetwork;
 
 use ai::build_ai::AI;
 use ai::evaluator::nn_evaluator::NNEvaluator;
+use ai::evaluator::simple_evaluator::SimpleEvaluator;
+use ai::features::{FeatureSet, Normalization, read_csv, write_csv_header, write_csv_row};
+use ai::network_file::{NetworkFile, NetworkSidecar};
+use ai::key_type::KeyType;
+#[cfg(feature = "ppc")]
+use ai::move_generator::MoveOrigin;
 use ai::opponent_status::OpponentStatus;
 use console::console::Console;
-use env::env::Env;
 use env::puyo_kind::PuyoKind;
 
 use crate::battle_env::BattleEnv;
+use crate::controller::{AsyncAIController, KeyboardController};
+use crate::cli::{Cli, Command, GameArgs, NormalizationMethod};
 use crate::log::Log;
 use crate::log::LogType::INFO;
 #[cfg(feature = "ppc")]
 use crate::ppc_wrapper::PpcWrapper;
 use crate::problems::battle_problem::BattleProblem;
+use crate::replay::Replay;
+use crate::replay_player::ReplayPlayer;
+use crate::solo::{run_solo, Solo};
+use crate::tournament::{Entrant, run_tournament, TournamentConfig};
 
+mod cli;
 mod log;
 mod battle_env;
+mod controller;
 mod problems;
+mod replay;
+mod replay_player;
+mod solo;
+mod tournament;
 
 #[cfg(feature = "ppc")]
 mod ppc_wrapper;
@@ -38,105 +60,32 @@ mod field;
 static COLOR_PUYOS: [PuyoKind; 4] = [PuyoKind::Green, PuyoKind::Red, PuyoKind::Blue, PuyoKind::Yellow];
 
 
-fn main() {
-	unsafe {
-		let mut input = String::new();
-		println!("Select mode.\n\
-		1.Console AI\n\
-		2.Console Human\n\
-		3.PPC AI\n\
-		4.Training\n\
-		5.Console Battle\n\
-		6.Console Auto Battle");
-		/*	io::stdin()
-				.read_line(&mut input)
-				.unwrap();
-	*/
-		input = "4".parse().unwrap();
-
-		match input.trim() {
-			"1" => {}
-			"2" => {
-				let mut env = Env::new(&0);
-				env.init();
-
-				let json_str = fs::read_to_string(r"test.json").unwrap();
-				let net: MultilayeredNetwork = serde_json::from_str(&json_str).unwrap();
-
-				let ai = AI::new(NNEvaluator::new(net));
-
-
-				loop {
-
-					//待機
-
-					Console::print(&env, 0, true, false);
-					let mut next = Vec::new();
-
-					//	for next_p in env.next {
-					for next_p2 in env.next[0] {
-						next.push(next_p2);
-					}
-					/*	for next_p2 in env.next[0] {
-							next.push(next_p2);
-						}*/
-
-					let mut input = Default::default();
-					if true {
-						input = "ai".parse().unwrap();
-					} else {
-						input = Console::get_input();
-					}
-				}
-			}
-			"3" => {
-				#[cfg(feature = "ppc")]
-				ppc();
+fn main() -> Result<()> {
+	let cli = Cli::parse();
+	if let Some(threads) = cli.threads {
+		rayon::ThreadPoolBuilder::new().num_threads(threads).build_global()?;
+	}
 
-				panic!("ppc module isn't loaded")
+	unsafe {
+		match cli.command {
+			Command::Play { net, game, replay_dir } => {
+				let ai = load_ai(&net)?;
+				human_battle(ai, &game, replay_dir);
 			}
-			"4" => {
-				println!("学習を開始します。");
-				println!("途中学習データを読み込みますか。(y/n)");
-				/*	let mut input = Default::default();
-					stdin().read_line(&mut input).unwrap();
-					match input.trim() {
-						"y" => {}
-						"n" => {}
-						_ => panic!()
-					}
-				*/
-				println!("ネットワークを用いた負荷分散を行いますか。(y/n)");
-
-				println!("学習を開始します。学習過程はlog.txtに保存されます。");
-				println!("各世代のbestが保存されます。");
-				let mut log = Log::open("log.txt");
-
-				log.write(INFO, "Training started");
-
-
-				let setting = revonet::settings::EASettings::new(64, 999999999, 30);
-				//let problem = ScoreProblem::new();
-				let problem = BattleProblem::new();
-				let mut ne: NE<BattleProblem> = NE::new(&problem);
-				//let mut ne: NE<ScoreProblem> = NE::new(&problem);
-				//let res = ne.run(setting, &false).unwrap();
-				let _ = ne.run(setting, &true).unwrap();
+			Command::Solo { net, game } => {
+				let solo = Solo::new(load_ai(&net)?, game.seed.unwrap_or_else(random_seed), game.rules);
+				run_solo(solo);
 			}
-			"5" => {
+			Command::Watch { net1, net2, game, replay_dir } => {
 				//	stdin().read_line(&mut "".to_string());
 				const FRAME_DURATION: Duration = Duration::from_millis(17);
 				let mut previous_time = Instant::now();
 
-				let json_str = fs::read_to_string(r"test.json").unwrap();
-				let net: MultilayeredNetwork = serde_json::from_str(&json_str).unwrap();
-				let ai = AI::new(NNEvaluator::new(net));
+				let ai = load_ai(&net1)?;
+				let ai2 = load_ai(&net2)?;
 
-				let json_str = fs::read_to_string(r"test2.json").unwrap();
-				let net: MultilayeredNetwork = serde_json::from_str(&json_str).unwrap();
-				let ai2 = AI::new(NNEvaluator::new(net));
-
-				let mut battle = BattleEnv::new(ai.clone(), ai2.clone());
+				let mut battle = BattleEnv::new_with_rules(ai, ai2, game.seed.unwrap_or_else(random_seed), game.rules);
+				battle.replay_dir = Some(replay_dir);
 
 				Console::clear();
 				loop {
@@ -145,7 +94,8 @@ fn main() {
 					battle.update();
 					if battle.check_winner() != -1 {
 						println!("どっちかがGAME OVER");
-						stdin().read_line(&mut "".to_string()).unwrap();
+						stdin().read_line(&mut "".to_string())?;
+						return Ok(());
 					}
 
 
@@ -166,18 +116,15 @@ fn main() {
 					}
 				}
 			}
-			"6" => {
-				let json_str = fs::read_to_string(r"test.json").unwrap();
-				let net: MultilayeredNetwork = serde_json::from_str(&json_str).unwrap();
-				let ai = AI::new(NNEvaluator::new(net));
-
-				let json_str = fs::read_to_string(r"test2.json").unwrap();
-				let net: MultilayeredNetwork = serde_json::from_str(&json_str).unwrap();
-				let ai2 = AI::new(NNEvaluator::new(net));
+			Command::Battle { net1, net2, game, first_to, replay_dir } => {
+				let ai = load_ai(&net1)?;
+				let ai2 = load_ai(&net2)?;
 
-				let mut battle = BattleEnv::new(ai.clone(), ai2.clone());
+				//seedを指定した場合は1試合ごとに1つずつずらす
+				let mut seed = game.seed.unwrap_or_else(random_seed);
+				let mut battle = BattleEnv::new_with_rules(ai.clone(), ai2.clone(), seed, game.rules.clone());
+				battle.replay_dir = replay_dir.clone();
 
-				let first_to = 30;
 				let mut player1_won = 0;
 				let mut player2_won = 0;
 				loop {
@@ -195,25 +142,304 @@ fn main() {
 
 						if player1_won == first_to || player2_won == first_to {
 							println!("player1:{player1_won} / player2:{player2_won}");
-
-							stdin().read_line(&mut "".to_string()).unwrap();
-						} else {
-							battle = BattleEnv::new(ai.clone(), ai2.clone());
+							return Ok(());
 						}
+
+						seed = match game.seed {
+							Some(_) => seed.wrapping_add(1),
+							None => random_seed(),
+						};
+						battle = BattleEnv::new_with_rules(ai.clone(), ai2.clone(), seed, game.rules.clone());
+						battle.replay_dir = replay_dir.clone();
 					}
 				}
 			}
-			_ => {}
+			Command::Train { population, generations, rules, log, features } => {
+				println!("学習を開始します。学習過程は{}に保存されます。", log.display());
+				println!("各世代のbestが保存されます。");
+				let mut log = Log::open(&log);
+
+				log.write(INFO, "Training started");
+
+
+				let setting = revonet::settings::EASettings::new(population, generations, 30);
+				//let problem = ScoreProblem::new();
+				let normalization = match &features {
+					Some(path) => Some(Normalization::mean_std(&read_features(path)?, FeatureSet::default().count())),
+					None => None,
+				};
+				//revonetはネットワークだけを保存するので、読み込む時に使う正規化を隣に書いておく
+				if let Some(normalization) = &normalization {
+					let sidecar = NetworkSidecar { feature_set: FeatureSet::default(), normalization: normalization.clone() };
+					let path = sidecar.save(".").context("failed to save the normalization")?;
+					println!("正規化を{}に保存しました、同じディレクトリのネットワークを読む時に使われます。", path.display());
+				}
+				let problem = BattleProblem::new_with_normalization(rules, normalization);
+				let mut ne: NE<BattleProblem> = NE::new(&problem);
+				//let mut ne: NE<ScoreProblem> = NE::new(&problem);
+				//let res = ne.run(setting, &false).unwrap();
+				let _ = ne.run(setting, &true).unwrap();
+			}
+			Command::Tournament { nets, format, games, seed, rules, max_frames, out } => {
+				let entrants = nets.iter()
+					.map(|path| {
+						if path.as_os_str() == "simple" {
+							return Ok(Entrant::from_ai("simple".to_owned(), AI::new(SimpleEvaluator::default())));
+						}
+						let name = path.file_stem().unwrap_or_default().to_string_lossy().into_owned();
+						Ok(Entrant::from_ai(name, load_ai(path)?))
+					})
+					.collect::<Result<Vec<_>>>()?;
+
+				let config = TournamentConfig {
+					format,
+					games_per_pair: games,
+					rules,
+					seed,
+					max_frames,
+				};
+
+				let report = run_tournament(&entrants, &config);
+				report.print_table();
+
+				fs::create_dir_all(&out)?;
+				let path = out.join(format!("tournament_{}.json", chrono::Local::now().format("%Y%m%d_%H%M%S")));
+				report.save(&path)?;
+				println!("結果を{}に保存しました。", path.display());
+			}
+			Command::Features { net, game, games, placements, out } => {
+				let count = dump_features(load_ai(&net)?, &game, games, placements, &out)?;
+				println!("{count}個の置き方の特徴量を{}に保存しました。", out.display());
+			}
+			Command::Normalize { net, features, method, out } => {
+				let mut file = NetworkFile::<MultilayeredNetwork>::load(&net).with_context(|| format!("failed to load {}", net.display()))?;
+				let samples = read_features(&features)?;
+				let count = file.feature_set.count();
+				file.normalization = Some(match method {
+					NormalizationMethod::MeanStd => Normalization::mean_std(&samples, count),
+					NormalizationMethod::MinMax => Normalization::min_max(&samples, count),
+				});
+
+				let out = out.unwrap_or(net);
+				file.save(&out).with_context(|| format!("failed to save {}", out.display()))?;
+				println!("{}個の置き方から求めた正規化を{}に保存しました。", samples.len(), out.display());
+			}
+			Command::Analyze { replay, view } => {
+				let replay = Replay::load(&replay).with_context(|| format!("failed to load {}", replay.display()))?;
+				analyze_replay(&replay);
+
+				if view {
+					replay_viewer(replay);
+				}
+			}
+			#[cfg(feature = "ppc")]
+			Command::Ppc { net } => ppc(&net)?,
+		}
+	}
+
+	Ok(())
+}
+
+///player1をキーボード、player2をAIにして等速で対戦する。決着したらrで再戦、qで終了
+unsafe fn human_battle(ai: AI<NNEvaluator<MultilayeredNetwork>>, game: &GameArgs, replay_dir: Option<PathBuf>) {
+	const FRAME_DURATION: Duration = Duration::from_millis(17);
+	let mut seed = game.seed.unwrap_or_else(random_seed);
+	let mut wins = [0; 2];
+
+	loop {
+		let keyboard = KeyboardController::new();
+		//AIの探索で画面が止まらないよう、1フレームに待つのは半分まで
+		let mut battle = BattleEnv::new_with_controllers(keyboard.clone(), AsyncAIController::new(ai.clone(), FRAME_DURATION / 2), seed, game.rules.clone());
+		battle.replay_dir = replay_dir.clone();
+
+		Console::clear();
+		let winner = loop {
+			let start_time = Instant::now();
+
+			while let Some(input) = Console::get_battle_input(Duration::ZERO) {
+				if input == "quit" {
+					return;
+				}
+				if let Some(key) = human_key(&input) {
+					keyboard.push(key);
+				}
+			}
+
+			battle.update();
+
+			Console::print(&battle.player1, 0, true, false);
+			Console::print(&battle.player2, 1, true, false);
+			let last_chain = |index: usize| battle.stats[index].chains.last().copied().unwrap_or(0);
+			Console::print_message(40, &format!("frame:{}  前回の連鎖 you:{} ai:{}  ←→:移動 ↓:下 ↑:設置 c/x:回転 v:180 q:終了",
+												battle.game_frame, last_chain(0), last_chain(1)));
+
+			let winner = battle.check_winner();
+			if winner != -1 {
+				break winner;
+			}
+
+			let elapsed_time = start_time.elapsed();
+			if elapsed_time < FRAME_DURATION {
+				thread::sleep(FRAME_DURATION - elapsed_time);
+			}
+		};
+
+		wins[winner as usize - 1] += 1;
+		let result = if winner == 1 { "YOU WIN" } else { "YOU LOSE" };
+		Console::print_message(40, &format!("{result}  you:{} ai:{}  最大連鎖 you:{} ai:{}  r:再戦 q:終了", wins[0], wins[1],
+											battle.stats[0].chains.iter().max().unwrap_or(&0), battle.stats[1].chains.iter().max().unwrap_or(&0)));
+		loop {
+			match Console::get_battle_input(Duration::from_secs(60)).as_deref() {
+				Some("retry") => break,
+				Some("quit") => return,
+				_ => {}
+			}
+		}
+
+		seed = match game.seed {
+			Some(_) => seed.wrapping_add(1),
+			None => random_seed(),
+		};
+	}
+}
+
+fn human_key(input: &str) -> Option<KeyType> {
+	match input {
+		"right" => Some(KeyType::Right),
+		"left" => Some(KeyType::Left),
+		"soft_drop" => Some(KeyType::Down),
+		"drop" => Some(KeyType::Drop),
+		"cw" => Some(KeyType::RotateRight),
+		"ccw" => Some(KeyType::RotateLeft),
+		"180" => Some(KeyType::Rotate180),
+		_ => None
+	}
+}
+
+fn random_seed() -> u32 {
+	thread_rng().gen()
+}
+
+///正規化を保存したファイルなら、評価の前に入力を揃える
+unsafe fn load_ai(path: &Path) -> Result<AI<NNEvaluator<MultilayeredNetwork>>> {
+	let file = NetworkFile::load(path).with_context(|| format!("failed to load network {}", path.display()))?;
+	Ok(AI::new(file.into_evaluator()))
+}
+
+fn read_features(path: &Path) -> Result<Vec<Vec<f32>>> {
+	let reader = BufReader::new(File::open(path).with_context(|| format!("failed to read {}", path.display()))?);
+	read_csv(reader, FeatureSet::default()).with_context(|| format!("invalid features {}", path.display()))
+}
+
+///gamesの試合を1人で置いて、AIが選んだ置き方の特徴量をoutに書き出す。書いた行数を返す
+unsafe fn dump_features(ai: AI<NNEvaluator<MultilayeredNetwork>>, game: &GameArgs, games: usize, placements: usize, out: &Path) -> Result<usize> {
+	let mut writer = BufWriter::new(File::create(out).with_context(|| format!("failed to create {}", out.display()))?);
+	write_csv_header(&mut writer, FeatureSet::default())?;
+
+	let mut seed = game.seed.unwrap_or_else(random_seed);
+	let mut count = 0;
+	for _ in 0..games {
+		let mut solo = Solo::new(ai.clone(), seed, game.rules.clone());
+		for _ in 0..placements {
+			let Some(hint) = solo.search_hint().cloned() else {
+				break;
+			};
+
+			if let Some(debug) = &hint.debug {
+				write_csv_row(&mut writer, &debug.features)?;
+				count += 1;
+			}
+			for key in hint.path {
+				solo.input(key);
+			}
+		}
+
+		seed = match game.seed {
+			Some(_) => seed.wrapping_add(1),
+			None => random_seed(),
+		};
+	}
+
+	writer.flush()?;
+	Ok(count)
+}
+
+///リプレイを最後まで再生して、検証結果とプレイヤーごとの集計を表示する
+unsafe fn analyze_replay(replay: &Replay) {
+	let mut player = ReplayPlayer::new(replay.clone());
+	let verified = player.verify();
+
+	println!("version:{} seed:{} frames:{} winner:{}", replay.version, replay.seed, replay.game_frame,
+			 if replay.winner == -1 { "none".to_owned() } else { format!("player{}", replay.winner) });
+	println!("rules:{:?}", replay.rules);
+	match &verified {
+		Ok(()) => println!("verified"),
+		Err(e) => println!("verification failed at frame {}: {e}", player.game_frame()),
+	}
+
+	println!("rerun: winner:{} frames:{}", player.battle.check_winner(), player.battle.game_frame);
+
+	println!("{:<8} {:>6} {:>7} {:>8} {:>4} {:>6}", "player", "drops", "chains", "average", "max", "sent");
+	for (index, stats) in player.battle.stats.iter().enumerate() {
+		let average = if stats.chains.is_empty() { 0. } else { stats.chains.iter().map(|&chain| chain as f64).sum::<f64>() / stats.chains.len() as f64 };
+		println!("{:<8} {:>6} {:>7} {:>8.2} {:>4} {:>6}", format!("player{}", index + 1), stats.drops, stats.chains.len(),
+				 average, stats.chains.iter().max().unwrap_or(&0), stats.sent_ojama);
+	}
+}
+
+///→/←で1フレーム、↑/↓で次/前の設置へ移動、スペースで再生/停止、qで終了
+unsafe fn replay_viewer(replay: Replay) {
+	const FRAME_DURATION: Duration = Duration::from_millis(17);
+	let mut player = ReplayPlayer::new(replay);
+	let mut playing = false;
+	let mut status = match player.verify() {
+		Ok(()) => "verified".to_owned(),
+		Err(e) => e.to_string(),
+	};
+	player.reset();
+
+	Console::clear();
+	loop {
+		Console::print(&player.battle.player1, 0, true, false);
+		Console::print(&player.battle.player2, 1, true, false);
+		Console::print_message(40, &format!("frame:{} / {}  winner:player{}  {}",
+											player.game_frame(), player.replay.game_frame, player.replay.winner, status));
+
+		let timeout = if playing { FRAME_DURATION } else { Duration::from_secs(60) };
+		let target = match Console::get_replay_input(timeout).as_deref() {
+			Some("step") => Some(player.game_frame() + 1),
+			Some("back") => Some(player.game_frame().saturating_sub(1)),
+			Some("next_lock") => player.next_lock_frame(),
+			Some("prev_lock") => Some(player.prev_lock_frame().unwrap_or(0)),
+			Some("play") => {
+				playing = !playing;
+				None
+			}
+			Some("quit") => break,
+			_ if playing => Some(player.game_frame() + 1),
+			_ => None
+		};
+
+		if let Some(target) = target {
+			match player.seek(target) {
+				Ok(()) => status.clear(),
+				Err(e) => {
+					playing = false;
+					status = e.to_string();
+				}
+			}
+
+			if player.is_finished() {
+				playing = false;
+			}
 		}
 	}
 }
 
 
 #[cfg(feature = "ppc")]
-unsafe fn ppc() {
-	let json_str = fs::read_to_string(r"test.json").unwrap();
-	let net: MultilayeredNetwork = serde_json::from_str(&json_str).unwrap();
-	let mut ai = AI::new(NNEvaluator::new(net));
+unsafe fn ppc(net: &Path) -> Result<()> {
+	let mut ai = load_ai(net)?;
 
 	//let a = Arc::new(Mutex::new(Env::new(&0)));
 
@@ -240,7 +466,10 @@ unsafe fn ppc() {
 
 			let now = Instant::now();
 			if now.duration_since(timer) >= interval {
-				*ppc_opponent_status_clone.lock().unwrap() = OpponentStatus::new(&ppc_opponent_clone.clone().lock().unwrap().env.board.clone());
+				*ppc_opponent_status_clone.lock().unwrap() = {
+					let ppc_opponent = ppc_opponent_clone.lock().unwrap();
+					OpponentStatus::new(&ppc_opponent.env.board, &ppc_opponent.env.ojama_rate, ppc_opponent.env.rules.colors())
+				};
 
 				timer = now;
 			}
@@ -269,14 +498,9 @@ unsafe fn ppc() {
 				}
 
 				//dbg!(&field.current);
-				let mut next = Vec::new();
-
-				next.push(ppc_player.env.next[0][0]);
-				next.push(ppc_player.env.next[0][1]);
-
 				ai.search(&ppc_player.env.board,
-						  &ppc_player.env.puyo_status,
-						  &next,
+						  &MoveOrigin::from_env(&ppc_player.env),
+						  &ppc_player.env.next,
 						  &ppc_player.env.ojama,
 						  ppc_player.env.center_puyo,
 						  ppc_player.env.movable_puyo,
@@ -297,4 +521,6 @@ unsafe fn ppc() {
 
 	opponent_handle.join().unwrap();
 	player_handle.join().unwrap();
+
+	Ok(())
 }
\ No newline at end of file
@@ -145,12 +145,8 @@ impl PpcWrapper {
 					break;
 				}
 
-				elapsed_frame += FrameNeeded::VANISH_PUYO_ANIMATION;
 				let drop_count = env.board.drop_after_erased(&board_mask);
-				if drop_count > 0 {
-					elapsed_frame += FrameNeeded::TEAR_PUYO_DROP_PER_1_BLOCK * drop_count as usize;
-					elapsed_frame += FrameNeeded::LAND_PUYO_ANIMATION;
-				}
+				elapsed_frame += FrameNeeded::chain_step(drop_count);
 
 				chain_score += score as usize;
 				chain += 1;
@@ -408,7 +404,7 @@ impl PpcWrapper {
 			PpcPuyoKind::Green => { PuyoKind::Green }
 			PpcPuyoKind::Blue => { PuyoKind::Blue }
 			PpcPuyoKind::Yellow => { PuyoKind::Yellow }
-			PpcPuyoKind::Purple => { PuyoKind::Preserved }//なければなんでもいい
+			PpcPuyoKind::Purple => { PuyoKind::Purple }
 			PpcPuyoKind::Garbage => { PuyoKind::Ojama }
 		}
 	}
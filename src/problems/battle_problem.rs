@@ -1,23 +1,38 @@
-﻿use rand::thread_rng;
+﻿use rand::{Rng, thread_rng};
 use revonet::neproblem::NeuroProblem;
 use revonet::neuro::{ActivationFunctionType, MultilayeredNetwork, NeuralArchitecture, NeuralNetwork};
 
 use ai::build_ai::AI;
 use ai::evaluator::nn_evaluator::NNEvaluator;
+use ai::features::{FeatureSet, Normalization};
+use env::rules::Rules;
 
 use crate::battle_env::BattleEnv;
+use crate::controller::AIController;
 
 #[derive(Clone)]
-pub struct BattleProblem {}
+pub struct BattleProblem {
+	rules: Rules,
+	///対戦させるネットワークの入力に掛ける、学習したネットワークには同じものを保存する
+	normalization: Option<Normalization>,
+}
 
 #[allow(dead_code)]
 impl BattleProblem {
-	pub fn new() -> BattleProblem { BattleProblem {} }
+	pub fn new(rules: Rules) -> BattleProblem { Self::new_with_normalization(rules, None) }
+
+	pub fn new_with_normalization(rules: Rules, normalization: Option<Normalization>) -> BattleProblem { BattleProblem { rules, normalization } }
+
+	unsafe fn new_battle<T: NeuralNetwork>(&self, net1: &T, net2: &T) -> BattleEnv<AIController<NNEvaluator<T>>, AIController<NNEvaluator<T>>> {
+		let ai1 = AI::new(NNEvaluator::with_features(net1.clone(), FeatureSet::default(), self.normalization.clone()));
+		let ai2 = AI::new(NNEvaluator::with_features(net2.clone(), FeatureSet::default(), self.normalization.clone()));
+		BattleEnv::new_with_rules(ai1, ai2, thread_rng().gen(), self.rules.clone())
+	}
 }
 
 impl NeuroProblem for BattleProblem {
 	fn get_inputs_num(&self) -> usize {
-		31
+		FeatureSet::default().count()
 	}
 
 	fn get_outputs_num(&self) -> usize {
@@ -46,9 +61,7 @@ impl NeuroProblem for BattleProblem {
 		//return (1., 0.);
 		let result;
 		unsafe {
-			let ai1 = AI::new(NNEvaluator::new(net1.clone()));
-			let ai2 = AI::new(NNEvaluator::new(net2.clone()));
-			let mut battle = BattleEnv::new(ai1, ai2);
+			let mut battle = self.new_battle(net1, net2);
 
 			loop {
 				battle.update();
@@ -68,10 +81,7 @@ impl NeuroProblem for BattleProblem {
 
 				if battle.game_frame == 36000 {
 					println!("警告：何らかの理由によりゲームが終わってない可能性があります、ゲームを強制リセット");
-					let ai1 = AI::new(NNEvaluator::new(net1.clone()));
-					let ai2 = AI::new(NNEvaluator::new(net2.clone()));
-
-					battle = BattleEnv::new(ai1, ai2);
+					battle = self.new_battle(net1, net2);
 				}
 			}
 		}
@@ -5,6 +5,7 @@ use revonet::neuro::{ActivationFunctionType, MultilayeredNetwork, NeuralArchitec
 use ai::build_ai::AI;
 use ai::evaluator::nn_evaluator::NNEvaluator;
 use ai::key_type::KeyType;
+use ai::move_generator::MoveOrigin;
 use ai::opponent_status::OpponentStatus;
 use env::env::Env;
 
@@ -38,35 +39,20 @@ impl NeuroProblem for ScoreProblem {
 		//7200フレームゲームをプレイ
 		let clone_net = net.clone();
 		let mut ai = AI::new(unsafe { NNEvaluator::new(clone_net) });
-		let mut next = Vec::new();
 		unsafe {
 			let seed = thread_rng().gen_range(0, 65535) as u32;
 			let mut env = Env::new(&seed);
 			env.init();
 
 			while env.current_frame <= 7200 && !env.dead {
-				next.clear();
-				for next_p2 in env.next[0] {
-					next.push(next_p2);
-				}
-
 				let opponent_status = OpponentStatus::default();
-				ai.search(&env.board, &env.puyo_status, &next, &env.ojama, env.center_puyo, env.movable_puyo, env.all_cleared, &env.ojama_rate, &opponent_status);
+				ai.search(&env.board, &MoveOrigin::from_env(&env), &env.next, &env.ojama, env.center_puyo, env.movable_puyo, env.all_cleared, &env.ojama_rate, &opponent_status);
 
 				let path = ai.best_move.as_ref().unwrap();
-				for key in path.path.iter() {
-					match key {
-						KeyType::Right => env.move_right(),
-						KeyType::Left => env.move_left(),
-						KeyType::Top => panic!(),
-						KeyType::Down => panic!(),
-						KeyType::Drop => {
-							env.quick_drop(None);
-							break;
-						}
-						KeyType::RotateRight => env.rotate_ccw(),
-						KeyType::RotateLeft => env.rotate_cw(),
-						KeyType::Rotate180 => { env.rotate_180() }
+				for &key in path.path.iter() {
+					env.input(key);
+					if key == KeyType::Drop {
+						break;
 					}
 				}
 			}
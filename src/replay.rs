@@ -0,0 +1,184 @@
+﻿use std::fs;
+use std::io::{Error, ErrorKind};
+use std::path::Path;
+
+use serde_derive::{Deserialize, Serialize};
+
+use ai::key_type::KeyType;
+use env::board::Board;
+use env::env::Env;
+use env::puyo_kind::PuyoKind;
+use env::rules::Rules;
+
+pub const REPLAY_VERSION: u32 = 4;
+///これより前のリプレイはお邪魔を設置した時にまとめて送っていたので再現できない
+pub const MIN_REPLAY_VERSION: u32 = 2;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReplayInput {
+	pub frame: usize,
+	pub key: KeyType,
+}
+
+///ぷよを設置した直後の盤面
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReplayLock {
+	pub frame: usize,
+	pub board_hash: u64,
+}
+
+///相殺した残りで相手に送ったお邪魔
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReplayOjama {
+	pub frame: usize,
+	pub player: usize,
+	pub size: usize,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PlayerRecord {
+	pub inputs: Vec<ReplayInput>,
+	pub locks: Vec<ReplayLock>,
+}
+
+///seedと入力だけで対戦を再現するためのリプレイ
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Replay {
+	pub version: u32,
+	pub seed: u32,
+	///ルールがない2のリプレイは自然落下のない通ルールとして読む
+	///3のリプレイは自然落下の間隔がないので、Rulesのdefaultで自然落下なしになる
+	#[serde(default = "Rules::tsu_without_gravity")]
+	pub rules: Rules,
+	pub players: [PlayerRecord; 2],
+	pub ojama_events: Vec<ReplayOjama>,
+	///勝ったプレイヤー番号、決着していなければ-1
+	pub winner: i8,
+	pub game_frame: usize,
+}
+
+impl Replay {
+	pub fn new(seed: u32, rules: Rules) -> Self {
+		Replay {
+			version: REPLAY_VERSION,
+			seed,
+			rules,
+			players: Default::default(),
+			ojama_events: Vec::new(),
+			winner: -1,
+			game_frame: 0,
+		}
+	}
+
+	///入力を記録、ぷよを設置していれば設置後の盤面も記録する
+	pub unsafe fn record_input(&mut self, player_index: usize, frame: usize, key: KeyType, env: &Env) {
+		let record = &mut self.players[player_index];
+		record.inputs.push(ReplayInput { frame, key });
+
+		if env.center_puyo == PuyoKind::Empty {
+			self.record_lock(player_index, frame, env);
+		}
+	}
+
+	///設置後の盤面を記録する、自然落下で設置したときは入力なしでこれだけ呼ぶ
+	pub unsafe fn record_lock(&mut self, player_index: usize, frame: usize, env: &Env) {
+		self.players[player_index].locks.push(ReplayLock {
+			frame,
+			board_hash: Self::board_hash(&env.board),
+		});
+	}
+
+	///連鎖の段で相手に送ったお邪魔を記録する、0なら何もしない
+	pub fn record_ojama(&mut self, player_index: usize, frame: usize, size: usize) {
+		if size != 0 {
+			self.ojama_events.push(ReplayOjama {
+				frame,
+				player: player_index,
+				size,
+			});
+		}
+	}
+
+	pub fn finish(&mut self, winner: i8, game_frame: usize) {
+		self.winner = winner;
+		self.game_frame = game_frame;
+	}
+
+	///盤面の文字列表現からFNV-1aで作るハッシュ、内部表現に依存しない
+	pub unsafe fn board_hash(board: &Board) -> u64 {
+		let mut hash: u64 = 0xcbf29ce484222325;
+		for byte in board.to_str().bytes() {
+			hash ^= byte as u64;
+			hash = hash.wrapping_mul(0x100000001b3);
+		}
+		hash
+	}
+
+	pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+		let json = serde_json::to_string(self)?;
+		fs::write(path, json)
+	}
+
+	///MIN_REPLAY_VERSIONからREPLAY_VERSIONまでのリプレイを読む、それ以外の版はエラー
+	pub fn load<P: AsRef<Path>>(path: P) -> Result<Replay, Error> {
+		let json = fs::read_to_string(path)?;
+		let replay: Replay = serde_json::from_str(&json)?;
+		if !(MIN_REPLAY_VERSION..=REPLAY_VERSION).contains(&replay.version) {
+			return Err(Error::new(ErrorKind::InvalidData, format!("unsupported replay version {} (supported: {MIN_REPLAY_VERSION}..={REPLAY_VERSION})", replay.version)));
+		}
+		Ok(replay)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use ai::build_ai::AI;
+	use ai::evaluator::simple_evaluator::SimpleEvaluator;
+	use ai::search_config::SearchConfig;
+
+	use crate::battle_env::BattleEnv;
+	use crate::replay_player::ReplayPlayer;
+
+	use super::*;
+
+	unsafe fn create_ai() -> AI<SimpleEvaluator> {
+		let mut ai = AI::new(SimpleEvaluator::default());
+		ai.search_config = SearchConfig { depth: 2, ..SearchConfig::visible() };
+		ai
+	}
+
+	#[test]
+	fn save_load_test() {
+		unsafe {
+			let mut battle_env = BattleEnv::new_with_rules(create_ai(), create_ai(), 1, Rules::default());
+			while battle_env.game_frame < 600 && battle_env.check_winner() == -1 {
+				battle_env.update();
+			}
+			if battle_env.replay.winner == -1 {
+				battle_env.replay.finish(-1, battle_env.game_frame);
+			}
+			assert!(!battle_env.replay.players[0].locks.is_empty());
+
+			let dir = std::env::temp_dir().join(format!("puyoai_replay_test_{}", std::process::id()));
+			fs::create_dir_all(&dir).unwrap();
+			let path = dir.join("replay.json");
+			battle_env.replay.save(&path).unwrap();
+			let replay = Replay::load(&path).unwrap();
+			assert_eq!(replay.version, REPLAY_VERSION);
+			assert_eq!(replay.game_frame, battle_env.game_frame);
+
+			let mut player = ReplayPlayer::new(replay);
+			player.verify().unwrap();
+			assert_eq!(player.battle.player1.board.to_str(), battle_env.player1.board.to_str());
+			assert_eq!(player.battle.player2.board.to_str(), battle_env.player2.board.to_str());
+
+			//知らない版は読まない
+			let mut replay = battle_env.replay.clone();
+			replay.version = REPLAY_VERSION + 1;
+			replay.save(&path).unwrap();
+			assert_eq!(Replay::load(&path).unwrap_err().kind(), ErrorKind::InvalidData);
+
+			fs::remove_dir_all(&dir).unwrap();
+		}
+	}
+}
@@ -0,0 +1,119 @@
+﻿use std::fmt;
+
+use crate::battle_env::BattleEnv;
+use crate::controller::ReplayController;
+use crate::replay::Replay;
+
+///再生した設置がリプレイに記録された設置と一致しなかった
+///expectedがNoneなら記録より多く設置した、actualがNoneなら記録された設置が起きなかった
+#[derive(Debug, Clone)]
+pub struct ReplayMismatch {
+	pub player: usize,
+	pub frame: usize,
+	pub lock_index: usize,
+	pub expected: Option<u64>,
+	pub actual: Option<u64>,
+}
+
+impl fmt::Display for ReplayMismatch {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "player{} lock#{} (frame {}) ", self.player + 1, self.lock_index, self.frame)?;
+		match (self.expected, self.actual) {
+			(Some(expected), Some(actual)) => write!(f, "hash mismatch: expected {:016x}, actual {:016x}", expected, actual),
+			(None, _) => write!(f, "was not recorded"),
+			(_, None) => write!(f, "was not replayed"),
+		}
+	}
+}
+
+///リプレイの入力でBattleEnvを1フレームずつ動かし直す、設置の検証はReplayControllerが設置ごとに行う
+pub struct ReplayPlayer {
+	pub replay: Replay,
+	pub battle: BattleEnv<ReplayController, ReplayController>,
+}
+
+impl ReplayPlayer {
+	pub unsafe fn new(replay: Replay) -> Self {
+		let battle = Self::create_battle(&replay);
+		ReplayPlayer {
+			replay,
+			battle,
+		}
+	}
+
+	unsafe fn create_battle(replay: &Replay) -> BattleEnv<ReplayController, ReplayController> {
+		BattleEnv::new_with_controllers(ReplayController::new(replay, 0), ReplayController::new(replay, 1), replay.seed, replay.rules.clone())
+	}
+
+	pub unsafe fn reset(&mut self) {
+		self.battle = Self::create_battle(&self.replay);
+	}
+
+	///次に処理するフレーム
+	#[inline]
+	pub fn game_frame(&self) -> usize {
+		self.battle.game_frame
+	}
+
+	#[inline]
+	pub fn is_finished(&self) -> bool {
+		self.battle.game_frame >= self.replay.game_frame
+	}
+
+	///1フレーム進める、このフレームの設置が記録と違えば返す
+	pub unsafe fn step(&mut self) -> Result<(), ReplayMismatch> {
+		self.battle.update();
+
+		let (player1, player2) = self.battle.controllers_mut();
+		match player1.take_mismatch().or_else(|| player2.take_mismatch()) {
+			Some(mismatch) => Err(mismatch),
+			None => Ok(()),
+		}
+	}
+
+	///指定フレームまで進める、戻る場合は最初から再シミュレーションする
+	pub unsafe fn seek(&mut self, frame: usize) -> Result<(), ReplayMismatch> {
+		let frame = frame.min(self.replay.game_frame);
+		if frame < self.battle.game_frame {
+			self.reset();
+		}
+
+		while self.battle.game_frame < frame {
+			self.step()?;
+		}
+
+		Ok(())
+	}
+
+	///現在より後で最初にぷよが設置された直後のフレーム
+	pub fn next_lock_frame(&self) -> Option<usize> {
+		self.lock_frames()
+			.filter(|&frame| frame > self.battle.game_frame)
+			.min()
+	}
+
+	///現在より前で最後にぷよが設置された直後のフレーム
+	pub fn prev_lock_frame(&self) -> Option<usize> {
+		self.lock_frames()
+			.filter(|&frame| frame < self.battle.game_frame)
+			.max()
+	}
+
+	fn lock_frames(&self) -> impl Iterator<Item=usize> + '_ {
+		self.replay.players.iter()
+			.flat_map(|record| record.locks.iter())
+			.map(|lock| lock.frame + 1)
+	}
+
+	///最後まで再生して全ての設置を検証する、記録された設置が全て起きたかも確かめる
+	pub unsafe fn verify(&mut self) -> Result<(), ReplayMismatch> {
+		self.reset();
+		self.seek(self.replay.game_frame)?;
+
+		let (player1, player2) = self.battle.controllers_mut();
+		match player1.missing_lock().or_else(|| player2.missing_lock()) {
+			Some(mismatch) => Err(mismatch),
+			None => Ok(()),
+		}
+	}
+}
@@ -0,0 +1,241 @@
+﻿use ai::build_ai::AI;
+use ai::debug::Debug;
+use ai::evaluator::Evaluator;
+use ai::key_type::KeyType;
+use ai::move_generator::MoveOrigin;
+use ai::opponent_status::OpponentStatus;
+use ai::potential::Potential;
+use console::console::Console;
+use env::env::Env;
+use env::ojama_placer::OjamaDropOrder;
+use env::puyo_kind::PuyoKind;
+use env::puyo_status::PuyoStatus;
+use env::rules::Rules;
+
+///AIが勧める置き方
+#[derive(Debug, Clone)]
+pub struct Hint {
+	pub path: Vec<KeyType>,
+	pub eval: f32,
+	pub debug: Option<Debug>,
+	///置いた後の軸ぷよと子ぷよの(x, y, 色)
+	pub puyos: [(i8, i8, PuyoKind); 2],
+}
+
+///1人用の練習、設置ごとに待ったができる
+pub struct Solo<E: Evaluator> {
+	pub env: Env,
+	ai: AI<E>,
+	///設置する直前の状態、待ったで1つずつ戻す
+	history: Vec<Env>,
+	pub hint: Option<Hint>,
+}
+
+impl<E: Evaluator> Solo<E> {
+	pub unsafe fn new(mut ai: AI<E>, seed: u32, rules: Rules) -> Self {
+		ai.rules = rules.clone();
+
+		let mut env = Env::new_with_rules(&seed, OjamaDropOrder::Random, rules);
+		env.init();
+
+		Solo {
+			env,
+			ai,
+			history: Vec::new(),
+			hint: None,
+		}
+	}
+
+	///キーを1つ入力する、設置したら連鎖を最後まで進めて次のぷよを出す
+	pub unsafe fn input(&mut self, key: KeyType) {
+		if self.env.dead {
+			return;
+		}
+
+		let before = self.env.clone();
+		self.env.input(key);
+
+		if self.env.center_puyo == PuyoKind::Empty {
+			self.history.push(before);
+			self.skip_events();
+			self.env.create_new_puyo();
+			self.skip_events();
+			self.hint = None;
+		}
+	}
+
+	///最後の設置を取り消す、戻せなければfalse
+	pub fn undo(&mut self) -> bool {
+		let Some(env) = self.history.pop() else {
+			return false;
+		};
+
+		self.env = env;
+		self.hint = None;
+		true
+	}
+
+	#[inline]
+	pub fn placed_count(&self) -> usize {
+		self.history.len()
+	}
+
+	///現在のぷよの置き方をAIに探索させる
+	pub unsafe fn search_hint(&mut self) -> Option<&Hint> {
+		if self.env.dead {
+			return None;
+		}
+
+		let env = &self.env;
+		self.ai.search(&env.board, &MoveOrigin::from_env(env), &env.next, &env.ojama, env.center_puyo, env.movable_puyo, env.all_cleared, &env.ojama_rate, &OpponentStatus::default());
+
+		let best_move = self.ai.best_move.as_ref()?;
+		let mut target = env.clone();
+		for &key in &best_move.path {
+			if key == KeyType::Drop {
+				break;
+			}
+			target.input(key);
+		}
+
+		self.hint = Some(Hint {
+			path: best_move.path.clone(),
+			eval: best_move.eval,
+			debug: self.ai.debug.clone(),
+			puyos: Self::landing_puyos(&env.board.get_heights(), &target.puyo_status, target.center_puyo, target.movable_puyo),
+		});
+		self.hint.as_ref()
+	}
+
+	///今の盤面で最大何連鎖まで伸ばせそうか
+	pub unsafe fn potential(&self) -> Potential {
+		AI::<E>::get_potential_chain_all(&self.env.board, self.env.rules.colors())
+	}
+
+	///待ちのイベントを全部処理して操作できる状態にする
+	unsafe fn skip_events(&mut self) {
+		while !self.env.process_events(self.env.current_frame, None) {
+			self.env.update();
+		}
+	}
+
+	///その場からまっすぐ落としたときの位置、縦置きなら下のぷよから積む
+	fn landing_puyos(heights: &[u16; 8], status: &PuyoStatus, center_puyo: PuyoKind, movable_puyo: PuyoKind) -> [(i8, i8, PuyoKind); 2] {
+		let center_x = status.position.x;
+		let movable_x = center_x + status.position_diff.x;
+		let center_height = heights[center_x as usize] as i8;
+		let movable_height = heights[movable_x as usize] as i8;
+
+		if status.position_diff.x != 0 {
+			[(center_x, center_height, center_puyo), (movable_x, movable_height, movable_puyo)]
+		} else if status.position_diff.y > 0 {
+			[(center_x, center_height, center_puyo), (center_x, center_height + 1, movable_puyo)]
+		} else {
+			[(center_x, center_height + 1, center_puyo), (center_x, center_height, movable_puyo)]
+		}
+	}
+}
+
+///キーボードで1人で積む。zで待った、hでヒント、qで終了
+pub unsafe fn run_solo<E: Evaluator>(mut solo: Solo<E>) {
+	let mut status = String::new();
+
+	Console::clear();
+	loop {
+		Console::print(&solo.env, 0, true, true);
+		if let Some(hint) = &solo.hint {
+			Console::print_ghost(0, &hint.puyos);
+		}
+
+		let potential = solo.potential();
+		Console::print_message(20, &format!("設置:{}  得点:{}  潜在連鎖:{} (あと{}個 発火点{:?})",
+											solo.placed_count(), solo.env.current_score, potential.chain, potential.added_count,
+											(potential.ignite_pos.x, potential.ignite_pos.y)));
+		match &solo.hint {
+			Some(hint) => {
+				Console::print_message(21, &format!("ヒント 評価値:{:.3} 入力:{:?}", hint.eval, hint.path));
+				Console::print_message(22, &format!("{:?}", hint.debug));
+			}
+			None => {
+				Console::print_message(21, "");
+				Console::print_message(22, "");
+			}
+		}
+		Console::print_message(23, &status);
+		Console::print_message(24, "m/.:移動 c/x:回転 v:180 k:設置 z:待った h:ヒント q:終了");
+
+		status.clear();
+		match Console::get_input().as_str() {
+			"right" => solo.input(KeyType::Right),
+			"left" => solo.input(KeyType::Left),
+			"cw" => solo.input(KeyType::RotateRight),
+			"ccw" => solo.input(KeyType::RotateLeft),
+			"180" => solo.input(KeyType::Rotate180),
+			"drop" => {
+				solo.input(KeyType::Drop);
+
+				let chain = solo.env.chain_steps.len();
+				if chain != 0 {
+					status = format!("{chain}連鎖 {}点", solo.env.chain_steps.iter().map(|step| step.score as usize).sum::<usize>());
+				}
+				if solo.env.dead {
+					status = "GAME OVER zで待った".to_owned();
+				}
+			}
+			"undo" => {
+				if !solo.undo() {
+					status = "これ以上戻せません".to_owned();
+				}
+			}
+			"hint" => {
+				if solo.search_hint().is_none() {
+					status = "ヒントがありません".to_owned();
+				}
+			}
+			"quit" => break,
+			_ => {}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use revonet::neproblem::NeuroProblem;
+
+	use ai::evaluator::nn_evaluator::NNEvaluator;
+
+	use crate::problems::battle_problem::BattleProblem;
+
+	use super::*;
+
+	#[test]
+	fn undo_test() {
+		unsafe {
+			let net = BattleProblem::new(Rules::default()).get_default_net();
+			let mut solo = Solo::new(AI::new(NNEvaluator::new(net)), 0, Rules::default());
+			assert!(!solo.undo());
+
+			let first_next = solo.env.next;
+			solo.input(KeyType::Left);
+			solo.input(KeyType::Drop);
+			solo.input(KeyType::Drop);
+			assert_eq!(solo.placed_count(), 2);
+
+			assert!(solo.undo());
+			assert!(solo.undo());
+			assert_eq!(solo.env.next, first_next);
+			assert_eq!(solo.env.board.get_heights(), Env::new(&0).board.get_heights());
+			//設置の直前、左に動かした状態まで戻る
+			assert_eq!(solo.env.puyo_status.position.x, 2);
+
+			//ヒントの通りに置ける
+			let hint = solo.search_hint().unwrap().clone();
+			for key in hint.path {
+				solo.input(key);
+			}
+			for (x, y, puyo) in hint.puyos {
+				assert_eq!(solo.env.board.get_cell(x as i16, y as i16), puyo);
+			}
+		}
+	}
+}
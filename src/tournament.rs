@@ -0,0 +1,388 @@
+﻿use std::fs;
+use std::io::Error;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use clap::ValueEnum;
+use rayon::prelude::*;
+use serde_derive::Serialize;
+
+use ai::build_ai::AI;
+use ai::evaluator::Evaluator;
+use env::rules::Rules;
+
+use crate::battle_env::BattleEnv;
+use crate::controller::{AIController, Controller};
+
+///Bradley-Terryの強さの対数をEloに直す係数、400差で勝率が10倍
+const ELO_SCALE: f64 = 400. / std::f64::consts::LN_10;
+///仮想の基準プレイヤーのElo
+pub const BASE_ELO: f64 = 1500.;
+const CONFIDENCE_Z: f64 = 1.96;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ValueEnum)]
+pub enum TournamentFormat {
+	///全員が総当たりで対戦する
+	RoundRobin,
+	///最初の参加者が残りの全員と対戦する
+	Gauntlet,
+}
+
+#[derive(Debug, Clone)]
+pub struct TournamentConfig {
+	pub format: TournamentFormat,
+	///1組あたりの試合数、同じseedを先後入れ替えて2試合ずつ行う
+	pub games_per_pair: usize,
+	pub rules: Rules,
+	///各試合のseedはここから順番に作る
+	pub seed: u32,
+	///このフレームまでに決着しなければ引き分け
+	pub max_frames: usize,
+}
+
+impl Default for TournamentConfig {
+	fn default() -> Self {
+		TournamentConfig {
+			format: TournamentFormat::RoundRobin,
+			games_per_pair: 10,
+			rules: Rules::default(),
+			seed: 0,
+			max_frames: 36000,
+		}
+	}
+}
+
+///試合ごとに新しいControllerを作る
+pub type ControllerFactory = Box<dyn Fn() -> Box<dyn Controller> + Send + Sync>;
+
+///参加者ごとに評価関数や操作の仕方が違ってもよい
+pub struct Entrant {
+	pub name: String,
+	pub controller: ControllerFactory,
+}
+
+impl Entrant {
+	pub fn new(name: String, controller: impl Fn() -> Box<dyn Controller> + Send + Sync + 'static) -> Self {
+		Entrant {
+			name,
+			controller: Box::new(controller),
+		}
+	}
+
+	///試合ごとにaiをcloneしてAIControllerで操作する
+	pub fn from_ai<E: Evaluator + 'static>(name: String, ai: AI<E>) -> Self {
+		Self::new(name, move || Box::new(AIController::new(ai.clone())))
+	}
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct GameRecord {
+	///player1とplayer2の参加者番号
+	pub players: [usize; 2],
+	pub seed: u32,
+	///勝った参加者番号、引き分けならNone
+	pub winner: Option<usize>,
+	pub frames: usize,
+	///それぞれが発火した連鎖の連鎖数
+	pub chains: [Vec<u8>; 2],
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct EntrantReport {
+	pub name: String,
+	pub games: usize,
+	pub wins: usize,
+	pub losses: usize,
+	pub draws: usize,
+	///引き分けは0.5勝
+	pub win_rate: f64,
+	pub elo: f64,
+	///Eloの95%信頼区間
+	pub elo_low: f64,
+	pub elo_high: f64,
+	pub average_chain: f64,
+	pub max_chain: u8,
+	pub average_frames: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TournamentReport {
+	pub format: TournamentFormat,
+	pub rules: Rules,
+	pub entrants: Vec<EntrantReport>,
+	pub games: Vec<GameRecord>,
+}
+
+impl TournamentReport {
+	pub fn print_table(&self) {
+		println!("{:<20} {:>6} {:>5} {:>5} {:>5} {:>7} {:>7} {:>17} {:>6} {:>4} {:>8}",
+				 "name", "games", "win", "lose", "draw", "rate", "elo", "95% CI", "chain", "max", "frames");
+
+		let mut entrants: Vec<&EntrantReport> = self.entrants.iter().collect();
+		entrants.sort_by(|a, b| b.elo.total_cmp(&a.elo));
+		for entrant in entrants {
+			println!("{:<20} {:>6} {:>5} {:>5} {:>5} {:>6.1}% {:>7.0} {:>17} {:>6.2} {:>4} {:>8.0}",
+					 entrant.name, entrant.games, entrant.wins, entrant.losses, entrant.draws,
+					 entrant.win_rate * 100., entrant.elo, format!("[{:.0}, {:.0}]", entrant.elo_low, entrant.elo_high),
+					 entrant.average_chain, entrant.max_chain, entrant.average_frames);
+		}
+	}
+
+	pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+		let json = serde_json::to_string_pretty(self)?;
+		fs::write(path, json)
+	}
+}
+
+///対戦する組み合わせ
+pub fn pairings(format: TournamentFormat, entrant_count: usize) -> Vec<(usize, usize)> {
+	match format {
+		TournamentFormat::RoundRobin => (0..entrant_count)
+			.flat_map(|a| (a + 1..entrant_count).map(move |b| (a, b)))
+			.collect(),
+		TournamentFormat::Gauntlet => (1..entrant_count).map(|b| (0, b)).collect(),
+	}
+}
+
+///全試合をrayonで並列に行って集計する
+pub unsafe fn run_tournament(entrants: &[Entrant], config: &TournamentConfig) -> TournamentReport {
+	let mut schedule = Vec::new();
+	for (pair_index, (a, b)) in pairings(config.format, entrants.len()).into_iter().enumerate() {
+		for game in 0..config.games_per_pair {
+			let players = if game % 2 == 0 { [a, b] } else { [b, a] };
+			let seed = config.seed.wrapping_add((pair_index * config.games_per_pair + game / 2) as u32);
+			schedule.push((players, seed));
+		}
+	}
+
+	let finished = AtomicUsize::new(0);
+	let games: Vec<GameRecord> = schedule.par_iter()
+		.map(|&(players, seed)| {
+			let game = play_game(entrants, players, seed, config);
+
+			let count = finished.fetch_add(1, Ordering::Relaxed) + 1;
+			let result = match game.winner {
+				Some(winner) => format!("{} won", entrants[winner].name),
+				None => "draw".to_owned(),
+			};
+			println!("[{count}/{}] {} vs {}: {result} ({} frames)",
+					 schedule.len(), entrants[players[0]].name, entrants[players[1]].name, game.frames);
+
+			game
+		})
+		.collect();
+
+	TournamentReport {
+		format: config.format,
+		rules: config.rules.clone(),
+		entrants: summarize(entrants, &games),
+		games,
+	}
+}
+
+unsafe fn play_game(entrants: &[Entrant], players: [usize; 2], seed: u32, config: &TournamentConfig) -> GameRecord {
+	let player1 = (entrants[players[0]].controller)();
+	let player2 = (entrants[players[1]].controller)();
+	let mut battle = BattleEnv::new_with_controllers(player1, player2, seed, config.rules.clone());
+
+	let mut winner = -1;
+	while winner == -1 && battle.game_frame < config.max_frames {
+		battle.update();
+		winner = battle.check_winner();
+	}
+
+	GameRecord {
+		players,
+		seed,
+		winner: match winner {
+			1 => Some(players[0]),
+			2 => Some(players[1]),
+			_ => None
+		},
+		frames: battle.game_frame,
+		chains: [battle.stats[0].chains.clone(), battle.stats[1].chains.clone()],
+	}
+}
+
+fn summarize(entrants: &[Entrant], games: &[GameRecord]) -> Vec<EntrantReport> {
+	let results: Vec<(usize, usize, f64)> = games.iter()
+		.map(|game| {
+			let score = match game.winner {
+				Some(winner) if winner == game.players[0] => 1.,
+				Some(_) => 0.,
+				None => 0.5,
+			};
+			(game.players[0], game.players[1], score)
+		})
+		.collect();
+	let ratings = bradley_terry(entrants.len(), &results);
+
+	entrants.iter().enumerate()
+		.map(|(index, entrant)| {
+			let mut report = EntrantReport {
+				name: entrant.name.clone(),
+				games: 0,
+				wins: 0,
+				losses: 0,
+				draws: 0,
+				win_rate: 0.,
+				elo: ratings[index].0,
+				elo_low: ratings[index].0 - CONFIDENCE_Z * ratings[index].1,
+				elo_high: ratings[index].0 + CONFIDENCE_Z * ratings[index].1,
+				average_chain: 0.,
+				max_chain: 0,
+				average_frames: 0.,
+			};
+
+			let mut chain_count = 0;
+			let mut chain_sum = 0usize;
+			let mut frame_sum = 0usize;
+			for game in games {
+				let Some(side) = game.players.iter().position(|&player| player == index) else {
+					continue;
+				};
+
+				report.games += 1;
+				frame_sum += game.frames;
+				match game.winner {
+					Some(winner) if winner == index => report.wins += 1,
+					Some(_) => report.losses += 1,
+					None => report.draws += 1,
+				}
+
+				for &chain in &game.chains[side] {
+					chain_count += 1;
+					chain_sum += chain as usize;
+					report.max_chain = report.max_chain.max(chain);
+				}
+			}
+
+			if report.games != 0 {
+				report.win_rate = (report.wins as f64 + report.draws as f64 * 0.5) / report.games as f64;
+				report.average_frames = frame_sum as f64 / report.games as f64;
+			}
+			if chain_count != 0 {
+				report.average_chain = chain_sum as f64 / chain_count as f64;
+			}
+
+			report
+		})
+		.collect()
+}
+
+///(player1, player2, player1の得点)の結果からBradley-Terryモデルの強さを求め、(Elo, 標準誤差)で返す
+///全勝や全敗でも発散しないよう、全員に基準プレイヤー(BASE_ELO)との仮想の1勝1敗を加える
+pub fn bradley_terry(entrant_count: usize, results: &[(usize, usize, f64)]) -> Vec<(f64, f64)> {
+	//最後の1人は強さ1で固定した基準プレイヤー
+	let anchor = entrant_count;
+	let size = entrant_count + 1;
+
+	let mut games = vec![vec![0f64; size]; size];
+	let mut wins = vec![0f64; size];
+	for &(a, b, score) in results {
+		games[a][b] += 1.;
+		games[b][a] += 1.;
+		wins[a] += score;
+		wins[b] += 1. - score;
+	}
+	for player in 0..entrant_count {
+		games[player][anchor] += 2.;
+		games[anchor][player] += 2.;
+		wins[player] += 1.;
+	}
+
+	//minorization-maximizationで更新する
+	let mut strength = vec![1f64; size];
+	for _ in 0..10000 {
+		let mut max_diff = 0f64;
+		for player in 0..entrant_count {
+			let denominator: f64 = (0..size)
+				.filter(|&opponent| opponent != player && games[player][opponent] != 0.)
+				.map(|opponent| games[player][opponent] / (strength[player] + strength[opponent]))
+				.sum();
+			let updated = wins[player] / denominator;
+
+			max_diff = max_diff.max((updated.ln() - strength[player].ln()).abs());
+			strength[player] = updated;
+		}
+
+		if max_diff < 1e-10 {
+			break;
+		}
+	}
+
+	(0..entrant_count)
+		.map(|player| {
+			//Fisher情報量の対角成分から標準誤差を近似する
+			let information: f64 = (0..size)
+				.filter(|&opponent| opponent != player)
+				.map(|opponent| {
+					let p = strength[player] / (strength[player] + strength[opponent]);
+					games[player][opponent] * p * (1. - p)
+				})
+				.sum();
+
+			(BASE_ELO + ELO_SCALE * strength[player].ln(), ELO_SCALE / information.sqrt())
+		})
+		.collect()
+}
+
+#[cfg(test)]
+mod tests {
+	use revonet::neproblem::NeuroProblem;
+
+	use ai::evaluator::nn_evaluator::NNEvaluator;
+	use ai::evaluator::simple_evaluator::SimpleEvaluator;
+
+	use crate::problems::battle_problem::BattleProblem;
+
+	use super::*;
+
+	#[test]
+	fn mixed_entrants_test() {
+		unsafe {
+			let net = BattleProblem::new(Rules::default()).get_default_net();
+			let entrants = [
+				Entrant::from_ai("nn".to_owned(), AI::new(NNEvaluator::new(net))),
+				Entrant::from_ai("simple".to_owned(), AI::new(SimpleEvaluator::default())),
+			];
+			let config = TournamentConfig {
+				games_per_pair: 2,
+				max_frames: 300,
+				..TournamentConfig::default()
+			};
+
+			let report = run_tournament(&entrants, &config);
+			assert_eq!(report.games.len(), 2);
+			assert_eq!(report.entrants[0].games, 2);
+			assert_eq!(report.entrants[1].games, 2);
+		}
+	}
+
+	#[test]
+	fn pairings_test() {
+		assert_eq!(pairings(TournamentFormat::RoundRobin, 4).len(), 6);
+		assert_eq!(pairings(TournamentFormat::Gauntlet, 4), vec![(0, 1), (0, 2), (0, 3)]);
+	}
+
+	#[test]
+	fn bradley_terry_test() {
+		//五分なら基準と同じ
+		let even = bradley_terry(2, &[(0, 1, 1.), (1, 0, 1.), (0, 1, 0.5)]);
+		assert!((even[0].0 - BASE_ELO).abs() < 1e-6);
+		assert!((even[1].0 - BASE_ELO).abs() < 1e-6);
+
+		//勝ち越した方が高く、試合数が増えれば区間は狭くなる
+		let few = bradley_terry(2, &[(0, 1, 1.), (0, 1, 1.), (0, 1, 0.)]);
+		assert!(few[0].0 > few[1].0);
+
+		let many: Vec<(usize, usize, f64)> = (0..30).map(|i| (0, 1, if i % 3 == 0 { 0. } else { 1. })).collect();
+		let many = bradley_terry(2, &many);
+		assert!(many[0].0 > many[1].0);
+		assert!(many[0].1 < few[0].1);
+
+		//全勝でも発散しない
+		let sweep = bradley_terry(2, &[(0, 1, 1.); 10]);
+		assert!(sweep[0].0.is_finite());
+	}
+}
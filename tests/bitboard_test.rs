@@ -1,7 +1,11 @@
-﻿use std::arch::x86_64::{__m128i, _mm_and_si128, _mm_cmpeq_epi64, _mm_cmpeq_epi8, _mm_set_epi32, _mm_set_epi64x, _mm_setr_epi32, _mm_store_si128, _pext_u32};
+﻿//SIMD版のBoardBitを直接使うので、portableでは動かさない
+#![cfg(not(feature = "portable"))]
+
+mod common;
+
+use std::arch::x86_64::{__m128i, _mm_and_si128, _mm_cmpeq_epi64, _mm_cmpeq_epi8, _mm_set_epi32, _mm_set_epi64x, _mm_setr_epi32, _mm_store_si128, _pext_u32};
 use ai::debug::Debug;
 use ai::evaluator::Evaluator;
-use ai::evaluator::simple_evaluator::SimpleEvaluator;
 use env::board::Board;
 use env::board_bit::BoardBit;
 use env::puyo_kind::PuyoKind;
@@ -9,16 +13,28 @@ use log::debug;
 use revonet::neuro::NeuralNetwork;
 use ai::evaluator::nn_evaluator::NNEvaluator;
 use ai::opener_book::Template;
-use env::env::DEAD_POSITION;
+use env::env::{DEAD_POSITION, MAX_OJAMA_RECEIVE_COUNT};
 use env::split_board::SplitBoard;
+use env::board_mask::{column_mask, GHOST_ROW, MASK_12, MASK_13, VANISH_ROW, VISIBLE_ROW};
+use env::ojama_placer::{OjamaDropOrder, OjamaPlacer};
+use env::ojama_status::OjamaStatus;
+use env::puyo_status::PuyoStatus;
+use env::rotation::Rotation;
+use env::vector2::Vector2;
+
+use common::board_from_rows;
 
 
 #[test]
 fn get_bits() {
 	unsafe {
-		let board_str = "YRGBOWE";
-		//	let board_str = "YEEEEEE";
-		let board = Board::from_str(board_str);
+		//0列目に下からE,W,O,B,G,R,Y
+		let mut board_str = vec!['E'; 8 * 16];
+		for (y, c) in "EWOBGRY".chars().enumerate() {
+			board_str[y * 8] = c;
+		}
+		let board_str: String = board_str.into_iter().collect();
+		let board = Board::from_str(&board_str);
 		let y = board.get_bits(PuyoKind::Yellow);
 		let r = board.get_bits(PuyoKind::Red);
 		let g = board.get_bits(PuyoKind::Green);
@@ -115,15 +131,16 @@ fn get_bits() {
 #[test]
 fn get_erase_flag() {
 	unsafe {
-		let board =
-			"EEEEEE\
-		 ERRRRE\
-		 EEEEEE";
-		let board = Board::from_str(&board);
+		let board = board_from_rows(&[
+			"WREEEEEW",
+			"WREEEEEW",
+			"WREEEEEW",
+			"WREEEEEW",
+		]);
 		let mut board_mask = BoardBit::default();
-		board.erase_if_needed(&0, &mut board_mask);
+		board.erase_if_needed(&0, &mut board_mask, &mut 0);
 		let ans =
-			_mm_set_epi64x(0, 0b000000_011110_000000);
+			_mm_set_epi64x(0, 0b011110_0000000000000000);
 
 		let result = _mm_cmpeq_epi64(board_mask.0, ans);
 		let result: [u32; 4] = std::mem::transmute(result);
@@ -133,8 +150,12 @@ fn get_erase_flag() {
 
 #[test]
 fn get_erase_frag_with_ojama() {
+	//13段目は消えないので、連鎖するのは2段目
 	let board =
 		"WWWWWWWW\
+		 WEEOOEEW\
+		 WERRRROW\
+		 WEEEEEOW\
 		 WEEEEEEW\
 		 WEEEEEEW\
 		 WEEEEEEW\
@@ -146,19 +167,16 @@ fn get_erase_frag_with_ojama() {
 		 WEEEEEEW\
 		 WEEEEEEW\
 		 WEEEEEEW\
-		 WEEEEEOW\
-		 WERRRROW\
-		 WEEOOEEW\
 		 WWWWWWWW";
 	unsafe {
 		let board = Board::from_str(&board);
 		//	let test = board.get_bits(PuyoKind::Ojama);
 
 		let mut board_mask = BoardBit::default();
-		board.erase_if_needed(&0, &mut board_mask);
+		board.erase_if_needed(&0, &mut board_mask, &mut 0);
 
 		let ans =
-			_mm_set_epi64x(0b0000000000000000_0010000000000000_0010000000000000_0110000000000000, 0b0110000000000000_0010000000000000_0000000000000000_0000000000000000);
+			_mm_set_epi64x(0b0000000000000000_0000000000000100_0000000000000100_0000000000000110, 0b0000000000000110_0000000000000100_0000000000000000_0000000000000000);
 
 
 		let result = _mm_cmpeq_epi64(board_mask.0, ans);
@@ -192,7 +210,7 @@ fn get_erase_drop() {
 	unsafe {
 		let mut board1 = Board::from_str(&board);
 		let mut board_mask = BoardBit::default();
-		board1.erase_if_needed(&0, &mut board_mask);
+		board1.erase_if_needed(&0, &mut board_mask, &mut 0);
 		board1.drop_after_erased(&board_mask);
 		let after = Board::to_str(&board1);
 		//	let test_before = Board::new();
@@ -235,7 +253,7 @@ fn get_link2() {
 		let temp = board1.get_not_empty_board();
 
 		let mut board_mask = BoardBit::default();
-		board1.erase_if_needed(&0, &mut board_mask);
+		board1.erase_if_needed(&0, &mut board_mask, &mut 0);
 		board1.drop_after_erased(&board_mask);
 		let after = Board::to_str(&board1);
 		//	let test_before = Board::new();
@@ -277,9 +295,9 @@ fn gtr_score() {
 
 		let mut templates = Vec::new();
 		templates.push(Template(Box::new([
-			_mm_set_epi64x(8590589956, 0),
-			_mm_set_epi64x(51539869696, 0),
-			_mm_set_epi64x(10, 1125917086711808),
+			BoardBit::from_epi64x(8590589956, 0),
+			BoardBit::from_epi64x(51539869696, 0),
+			BoardBit::from_epi64x(10, 1125917086711808),
 		])));
 
 		let score = templates[0].evaluate(&board1);
@@ -289,3 +307,96 @@ fn gtr_score() {
 //	assert_eq!(board, ans);
 }
 
+#[test]
+fn row_masks() {
+	assert_eq!((VISIBLE_ROW, GHOST_ROW, VANISH_ROW), (12, 13, 14));
+	assert_eq!(column_mask(VISIBLE_ROW), 0b0001111111111111);
+	assert_eq!(column_mask(GHOST_ROW), 0b0011111111111111);
+
+	//以前の直書きの値と同じ
+	assert_eq!(MASK_12, ((-246294362775553i64 as u64 as u128) << 64) | 2305596714850975743u64 as u128);
+	assert_eq!(MASK_13, ((-211109453807617i64 as u64 as u128) << 64) | 4611474908973629439u64 as u128);
+}
+
+#[test]
+fn ghost_row_does_not_erase() {
+	unsafe {
+		let mut rows = vec!["WEEEEEEW"; 13];
+		for y in 0..9 {
+			rows[y] = if y % 2 == 0 { "WBYBYBYW" } else { "WYBYBYBW" };
+		}
+		//1列目は縦に4つ、13段目は横にも4つ並ぶが、13段目は見えていないので消えない
+		for y in 9..12 {
+			rows[y] = if y % 2 == 0 { "WRYBYBYW" } else { "WRBYBYBW" };
+		}
+		rows[12] = "WRGGGGEW";
+		let board = board_from_rows(&rows);
+
+		let mut board_mask = BoardBit::default();
+		assert_eq!(board.erase_if_needed(&0, &mut board_mask, &mut 0), 0);
+		assert!(board_mask.is_empty());
+	}
+}
+
+#[test]
+fn ghost_row_drops_after_erase() {
+	unsafe {
+		let mut rows = vec!["WEEEEEEW"; 13];
+		rows[0] = "WRRRREEW";
+		for y in 1..12 {
+			rows[y] = if y % 2 == 0 { "WBEEEEEW" } else { "WYEEEEEW" };
+		}
+		rows[12] = "WGEEEEEW";
+		let mut board = board_from_rows(&rows);
+
+		let mut board_mask = BoardBit::default();
+		assert_ne!(board.erase_if_needed(&0, &mut board_mask, &mut 0), 0);
+		assert_eq!(board.drop_after_erased(&board_mask), 1);
+
+		assert_eq!(board.get_cell(1, 12), PuyoKind::Green);
+		assert_eq!(board.get_cell(1, 13), PuyoKind::Empty);
+		assert_eq!(board.get_heights()[1], 13);
+	}
+}
+
+#[test]
+fn vanish_row_put_puyo() {
+	unsafe {
+		let mut rows = vec!["WEEEEEEW"; 12];
+		for y in 0..12 {
+			rows[y] = if y % 2 == 0 { "WEEBEEEW" } else { "WEEYEEEW" };
+		}
+		let mut board = board_from_rows(&rows);
+
+		//13段目に軸、14段目に子ぷよ
+		let puyo_status = PuyoStatus::new(Vector2::new(3, 13), Rotation::new(3));
+		assert_eq!(puyo_status.position_diff.y, 1);
+		board.put_puyo(&puyo_status, &PuyoKind::Red, &PuyoKind::Green, &mut Vector2::default());
+
+		assert_eq!(board.get_cell(3, GHOST_ROW as i16), PuyoKind::Red);
+		assert_eq!(board.get_cell(3, VANISH_ROW as i16), PuyoKind::Empty);
+		assert_eq!(board.get_heights()[3], 14);
+	}
+}
+
+#[test]
+fn ojama_stops_at_ghost_row() {
+	unsafe {
+		let mut rows = vec!["WEEEEEEW"; 11];
+		for y in 0..11 {
+			rows[y] = if y % 2 == 0 { "WBYBYBYW" } else { "WYBYBYBW" };
+		}
+		let mut board = board_from_rows(&rows);
+
+		let mut ojama = OjamaStatus::new();
+		ojama.push(30, 0);
+		board.try_put_ojama(&mut ojama, &mut OjamaPlacer::new(&0, OjamaDropOrder::Rotating), MAX_OJAMA_RECEIVE_COUNT);
+
+		for x in 1..=6 {
+			assert_eq!(board.get_cell(x, 12), PuyoKind::Ojama);
+			assert_eq!(board.get_cell(x, GHOST_ROW as i16), PuyoKind::Ojama);
+			assert_eq!(board.get_cell(x, VANISH_ROW as i16), PuyoKind::Empty);
+			assert_eq!(board.get_cell(x, 15), PuyoKind::Empty);
+		}
+	}
+}
@@ -0,0 +1,59 @@
+﻿mod common;
+
+use env::board::Board;
+use env::chain_step::{ChainGroup, ChainStep};
+use env::env::FrameNeeded;
+use env::event_type::EventType;
+use env::puyo_kind::PuyoKind;
+use env::simulator::Simulator;
+
+use common::{TWO_CHAIN, board_str_from_rows};
+
+#[test]
+fn chain_step_timeline() {
+	unsafe {
+		let mut board = Board::from_str(&board_str_from_rows(&TWO_CHAIN));
+		let steps = ChainStep::resolve_all(&mut board);
+
+		assert_eq!(steps.len(), 2);
+
+		assert_eq!(steps[0].chain, 1);
+		assert_eq!(steps[0].colors, vec![PuyoKind::Red]);
+		assert_eq!(steps[0].groups, vec![ChainGroup { color: PuyoKind::Red, size: 4 }]);
+		assert_eq!(steps[0].score, 40);
+		assert_eq!(steps[0].drop_distance, 2);
+		assert_eq!(steps[0].start_frame, 0);
+		assert_eq!(steps[0].frames, FrameNeeded::VANISH_PUYO_ANIMATION + FrameNeeded::TEAR_PUYO_DROP_PER_1_BLOCK * 2 + FrameNeeded::LAND_PUYO_ANIMATION);
+
+		assert_eq!(steps[1].chain, 2);
+		assert_eq!(steps[1].groups, vec![ChainGroup { color: PuyoKind::Blue, size: 4 }]);
+		assert_eq!(steps[1].score, 320);
+		assert_eq!(steps[1].drop_distance, 0);
+		assert_eq!(steps[1].start_frame, steps[0].end_frame());
+		assert_eq!(steps[1].frames, FrameNeeded::VANISH_PUYO_ANIMATION);
+
+		assert!(board.is_all_cleared());
+	}
+}
+
+#[test]
+fn quick_drop_pushes_attack_events() {
+	let mut player = Simulator::new(0).unwrap();
+	player.set_board_from_str(&board_str_from_rows(&TWO_CHAIN)).unwrap();
+
+	player.move_right();
+	player.move_right();
+	player.move_right();
+	player.quick_drop();
+
+	assert_eq!(player.last_chain_count(), 2);
+	assert_eq!(player.score(), 360);
+
+	let chains: Vec<u8> = player.env().events.iter()
+		.filter_map(|event| match &event.kind {
+			EventType::Attack(step) => Some(step.chain),
+			EventType::Wait => None,
+		})
+		.collect();
+	assert_eq!(chains, vec![1, 2]);
+}
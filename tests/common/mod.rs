@@ -0,0 +1,29 @@
+﻿//テストで共有する盤面、使わないテストもあるのでdead_codeは許す
+#![allow(dead_code)]
+
+use env::board::Board;
+
+///何も置いていない段
+pub const EMPTY_ROW: &str = "WEEEEEEW";
+
+///赤4つが消えて、落ちた青4つが2連鎖目になる。40点と320点でレート70なら5個送る
+pub const TWO_CHAIN: [&str; 3] = [
+	"WRRRBEEW",
+	"WRBBEEEW",
+	"WBEEEEEW",
+];
+
+///下の段から順に1段目以降を並べて盤面の文字列を作る、足りない段は空
+pub fn board_str_from_rows(rows: &[&str]) -> String {
+	let mut board = String::from("WWWWWWWW");
+	for y in 1..16 {
+		board += rows.get(y - 1).copied().unwrap_or(EMPTY_ROW);
+	}
+
+	board
+}
+
+///board_str_from_rowsの盤面
+pub unsafe fn board_from_rows(rows: &[&str]) -> Board {
+	Board::from_str(&board_str_from_rows(rows))
+}
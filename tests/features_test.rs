@@ -0,0 +1,82 @@
+﻿mod common;
+
+use ai::debug::Debug;
+use ai::evaluator::EvalContext;
+use ai::features::{FeatureSet, Normalization, write_csv_header, write_csv_row};
+use ai::opponent_status::OpponentStatus;
+use ai::potential::Potential;
+use env::ojama_status::OjamaStatus;
+use env::rules::Rules;
+
+use common::board_from_rows;
+
+#[test]
+fn extract_test() {
+	unsafe {
+		//1列目に赤が3つ、2列目に緑が2つ
+		let board = board_from_rows(&["WRGEEEEW", "WRGEEEEW", "WREEEEEW"]);
+
+		let mut debug = Debug::new();
+		let feature_set = FeatureSet::V1;
+		let features = feature_set.extract(&mut EvalContext {
+			put_board: &board,
+			sim_board: &board,
+			potential: &Potential::default(),
+			chain: 0,
+			score: 0,
+			elapse_frame: 0,
+			ojama: &OjamaStatus::default(),
+			ojama_rate: 70,
+			opponent_status: &OpponentStatus::default(),
+			waste_chain_link: 0,
+			one_side_chain_count: 0,
+			instant_attack_count: 0,
+			attack_value: 0,
+			colors: Rules::default().colors(),
+			debug: &mut debug,
+		}, &[]);
+
+		assert_eq!(features.len(), feature_set.count());
+		assert_eq!(feature_set.count(), 31);
+
+		let feature = |name: &str| features[feature_set.names().iter().position(|&n| n == name).unwrap()];
+		assert_eq!(feature("link2"), 1.);
+		assert_eq!(feature("link3"), 1.);
+		assert_eq!(feature("height1") - feature("height2"), 1.);
+		assert_eq!(feature("bump"), 3.);
+		assert_eq!(debug.link2_count, 1);
+		assert_eq!(debug.link3_count, 1);
+	}
+}
+
+#[test]
+fn normalization_test() {
+	let samples = vec![vec![0., 5., 10.], vec![10., 5., 30.]];
+
+	let mut values = samples[1].clone();
+	Normalization::mean_std(&samples, 3).apply(&mut values);
+	assert_eq!(values, [1., 0., 1.]);
+
+	let mut values = samples[0].clone();
+	Normalization::min_max(&samples, 3).apply(&mut values);
+	assert_eq!(values, [0., 0., 0.]);
+
+	let mut values = samples[0].clone();
+	Normalization::identity(3).apply(&mut values);
+	assert_eq!(values, samples[0]);
+}
+
+#[test]
+fn csv_test() {
+	let mut csv = Vec::new();
+	write_csv_header(&mut csv, FeatureSet::V1).unwrap();
+	write_csv_row(&mut csv, &[1., 0.5, -2.]).unwrap();
+
+	let csv = String::from_utf8(csv).unwrap();
+	let mut lines = csv.lines();
+	assert_eq!(lines.next().unwrap().split(',').count(), FeatureSet::V1.count());
+	assert_eq!(lines.next(), Some("1,0.5,-2"));
+	assert_eq!(lines.next(), None);
+
+	assert_eq!(serde_json::to_string(&FeatureSet::V1).unwrap(), "\"v1\"");
+}
@@ -0,0 +1,47 @@
+﻿use env::env::Env;
+use env::input_timing::InputTiming;
+use env::key_type::KeyType;
+use env::key_type::KeyType::{Drop, Left, Right, Rotate180, RotateLeft, RotateRight};
+
+#[test]
+fn frames_test() {
+	let timing = InputTiming::tsu();
+	assert_eq!(timing.frames(&[Drop]), 0);
+	//連打で2フレームごと
+	assert_eq!(timing.frames(&[Right, Right, Right, Drop]), 4);
+	//回転は横移動と同じフレームに押せる
+	assert_eq!(timing.frames(&[Right, RotateRight, Right, RotateRight, Drop]), 2);
+	assert_eq!(timing.frames(&[RotateRight, RotateLeft, Drop]), 2);
+	//向きを変えると押しっぱなしは続かない
+	assert_eq!(timing.frames(&[Right, Left, Right, Drop]), 4);
+	//クイックターンは回転を2回押す
+	assert_eq!(timing.frames(&[Rotate180, Drop]), 2);
+	assert_eq!(timing.frames(&[Rotate180, RotateRight, Drop]), 4);
+	assert_eq!(timing.frames(&[KeyType::Down, KeyType::Down, Drop]), 2);
+
+	//連打が遅ければ押しっぱなしの方が早い
+	let timing = InputTiming { tap_frame: 3, das_frame: 4, repeat_frame: 1, soft_drop_frame: 1 };
+	assert_eq!(timing.frames(&[Left, Left, Drop]), 3);
+	assert_eq!(timing.frames(&[Right, Right, Right, Drop]), 5);
+}
+
+#[test]
+fn can_input_test() {
+	unsafe {
+		let mut env = Env::new(&0);
+		env.init();
+
+		assert!(env.can_input(Right));
+		env.input(Right);
+		assert!(!env.can_input(Right));
+		assert!(!env.can_input(Left));
+		assert!(env.can_input(RotateRight));
+		assert!(env.can_input(Drop));
+
+		env.update();
+		assert!(!env.can_input(Right));
+		env.update();
+		assert!(env.can_input(Right));
+		assert_eq!(env.puyo_status.position.x, 4);
+	}
+}
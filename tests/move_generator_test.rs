@@ -0,0 +1,250 @@
+﻿use std::collections::{HashMap, HashSet, VecDeque};
+
+mod common;
+
+use ai::key_type::KeyType;
+use ai::move_generator::{MoveGenerator, MoveOrigin, Placement};
+use env::env::{Env, FrameNeeded};
+use env::input_timing::InputClock;
+use env::puyo_kind::PuyoKind;
+use env::vector2::Vector2;
+
+use common::board_from_rows;
+
+const KEYS: [KeyType; 6] = [KeyType::Right, KeyType::Left, KeyType::Down, KeyType::RotateRight, KeyType::RotateLeft, KeyType::Rotate180];
+
+///入力して動けたらtrue
+unsafe fn apply_effective(env: &mut Env, key: KeyType) -> bool {
+	let before = state(env);
+	env.input(key);
+	env.center_puyo != PuyoKind::Empty && state(env) != before
+}
+
+fn state(env: &Env) -> (i8, i8, u8) {
+	(env.puyo_status.position.x, env.puyo_status.position.y, env.puyo_status.rotation.0)
+}
+
+///その場で設置した盤面、連鎖は起こさない
+unsafe fn put_board(env: &Env) -> String {
+	let mut board = env.board.clone();
+	board.put_puyo(&env.puyo_status, &env.center_puyo, &env.movable_puyo, &mut Vector2::default());
+	board.to_str()
+}
+
+///Envを直接動かして幅優先で調べた、置いた後の盤面ごとの入力数が最小の経路
+unsafe fn search_with_env(env: &Env) -> HashMap<String, Vec<KeyType>> {
+	let mut visited = HashSet::from([state(env)]);
+	let mut queue = VecDeque::from([(env.clone(), vec![KeyType::Drop])]);
+	let mut boards = HashMap::new();
+
+	while let Some((env, path)) = queue.pop_front() {
+		boards.entry(put_board(&env)).or_insert(path.clone());
+
+		let mut rotatable = false;
+		for key in KEYS {
+			//クイックターンは両側が塞がれて回転できない時だけ
+			if key == KeyType::Rotate180 && rotatable {
+				continue;
+			}
+
+			let mut next = env.clone();
+			if !apply_effective(&mut next, key) {
+				continue;
+			}
+			if key == KeyType::RotateRight || key == KeyType::RotateLeft {
+				rotatable = true;
+			}
+			if visited.insert(state(&next)) {
+				let mut next_path = path.clone();
+				next_path.insert(next_path.len() - 1, key);
+				queue.push_back((next, next_path));
+			}
+		}
+	}
+
+	boards
+}
+
+///今のフレームから入力を始めて、自然落下をfall_interval_frameにした時の置き方
+unsafe fn generate(env: &Env, fall_interval_frame: usize) -> (Env, Vec<Placement>) {
+	let mut env = env.clone();
+	env.rules.fall_interval_frame = fall_interval_frame;
+	env.input_clock = InputClock::new(env.current_frame);
+	let placements = MoveGenerator::generate(&env.board, &MoveOrigin::from_env(&env), env.center_puyo, env.movable_puyo, &env.rules.input_timing);
+	(env, placements)
+}
+
+///BattleEnvと同じく押せるようになったキーから入力して毎フレーム自然落下させ、Dropを押すまでのフレームを返す
+unsafe fn input_with_timing(env: &mut Env, path: &[KeyType]) -> usize {
+	let start_frame = env.current_frame;
+	let mut keys = path.iter().copied().peekable();
+
+	loop {
+		while let Some(&key) = keys.peek() {
+			if !env.can_input(key) {
+				break;
+			}
+
+			assert!(key == KeyType::Drop || apply_effective(env, key), "{:?} had no effect in {:?}", key, path);
+			if key == KeyType::Drop {
+				env.input(key);
+				return env.current_frame - start_frame;
+			}
+			keys.next();
+		}
+
+		assert!(!env.fall_one_frame(), "{:?} was locked by gravity", path);
+		env.update();
+	}
+}
+
+///生成した全ての置き方を入力し直して、位置とフレームと盤面をEnvで調べた結果と比べる
+///自然落下がなければ全ての置き方が見つかり、あれば見つかった置き方がその通りに入力できる
+unsafe fn cross_check(env: &Env) {
+	let expected = search_with_env(env);
+
+	for fall_interval_frame in [0, FrameNeeded::NATURAL_FALL, 2] {
+		let (env, placements) = generate(env, fall_interval_frame);
+		let timing = env.rules.input_timing;
+
+		let mut boards = HashSet::new();
+		for placement in &placements {
+			assert_eq!(placement.path.last(), Some(&KeyType::Drop));
+			assert_eq!(placement.frames, timing.frames(&placement.path));
+
+			let mut dropped = env.clone();
+			dropped.puyo_status = placement.status.clone();
+			let board = put_board(&dropped);
+			dropped.quick_drop();
+			assert!(boards.insert(board.clone()), "duplicated placement {:?}", placement.path);
+
+			//入力の少ない経路より遅くならない
+			let shortest = expected.get(&board).unwrap();
+			if fall_interval_frame == 0 {
+				assert!(placement.frames <= timing.frames(shortest), "{:?} is slower than {:?}", placement.path, shortest);
+			}
+
+			let mut replayed = env.clone();
+			assert_eq!(input_with_timing(&mut replayed, &placement.path), placement.frames);
+			assert_eq!(replayed.center_puyo, PuyoKind::Empty);
+			assert_eq!(replayed.board.to_str(), dropped.board.to_str());
+		}
+
+		if fall_interval_frame == 0 {
+			assert_eq!(boards, expected.keys().cloned().collect());
+		}
+	}
+}
+
+unsafe fn env_with_board(rows: &[&str]) -> Env {
+	let mut env = Env::new(&0);
+	env.init();
+	env.board = board_from_rows(rows);
+	env
+}
+
+#[test]
+fn empty_board_test() {
+	unsafe {
+		let mut env = Env::new(&0);
+		env.init();
+
+		for (center, movable, count) in [(PuyoKind::Red, PuyoKind::Green, 22), (PuyoKind::Red, PuyoKind::Red, 11)] {
+			env.center_puyo = center;
+			env.movable_puyo = movable;
+			assert_eq!(generate(&env, 0).1.len(), count);
+			cross_check(&env);
+		}
+	}
+}
+
+#[test]
+fn played_boards_test() {
+	unsafe {
+		for seed in 0..3 {
+			let mut env = Env::new(&seed);
+			env.init();
+
+			for turn in 0..20 {
+				if env.dead {
+					break;
+				}
+
+				cross_check(&env);
+
+				let (_, placements) = generate(&env, 0);
+				let placement = &placements[(turn * 7 + seed as usize) % placements.len()];
+				for &key in &placement.path {
+					env.input(key);
+				}
+				env.create_new_puyo();
+			}
+		}
+	}
+}
+
+#[test]
+fn lowered_pair_test() {
+	unsafe {
+		let mut env = env_with_board(&["WOEEEOOW"; 6]);
+		for _ in 0..5 {
+			env.soft_drop();
+		}
+		assert_eq!(env.puyo_status.position.y, 7);
+		cross_check(&env);
+	}
+}
+
+#[test]
+fn well_test() {
+	unsafe {
+		for rows in [["WEOEOEEW"; 12], ["WOOEOEEW"; 12], ["WEEEOEOW"; 12]] {
+			let mut env = env_with_board(&rows);
+			cross_check(&env);
+
+			env.center_puyo = PuyoKind::Red;
+			env.movable_puyo = PuyoKind::Blue;
+			cross_check(&env);
+		}
+
+		//両側が塞がれた井戸では上下を入れ替えた置き方がクイックターンで見つかる
+		let mut env = env_with_board(&["WEOEOEEW"; 12]);
+		env.center_puyo = PuyoKind::Red;
+		env.movable_puyo = PuyoKind::Blue;
+		let (_, placements) = generate(&env, 0);
+		assert_eq!(placements.len(), 2);
+		assert!(placements.iter().any(|placement| placement.path == [KeyType::Rotate180, KeyType::Drop]));
+
+		//3列目以外が12段目の手前まで積まれている
+		let env = env_with_board(&["WOOEOOOW"; 11]);
+		cross_check(&env);
+	}
+}
+
+#[test]
+fn gravity_test() {
+	unsafe {
+		//10段目まで積まれていて、出現したぷよはすぐに接地する
+		let mut env = env_with_board(&["WOOOOOOW"; 10]);
+		env.center_puyo = PuyoKind::Red;
+		env.movable_puyo = PuyoKind::Green;
+		assert_eq!(generate(&env, 0).1.len(), 22);
+
+		//2フレームごとに落ちると端まで動かす前に設置される置き方がある
+		let (fast, placements) = generate(&env, 2);
+		assert!(!placements.is_empty() && placements.len() < 22);
+		for placement in &placements {
+			let mut replayed = fast.clone();
+			assert_eq!(input_with_timing(&mut replayed, &placement.path), placement.frames);
+		}
+
+		//途中まで数えた落下も引き継ぐ
+		env.fall_frame = FrameNeeded::NATURAL_FALL - 1;
+		cross_check(&env);
+
+		//出現したぷよは操作できるまでの間も落下を数える
+		let origin = MoveOrigin::spawn(&env.rules);
+		assert_eq!(origin.fall_clock.fall_frame, FrameNeeded::SPAWN_NEW_PUYO);
+		assert_eq!(origin.frame, FrameNeeded::SPAWN_NEW_PUYO);
+	}
+}
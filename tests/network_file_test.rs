@@ -0,0 +1,64 @@
+﻿use std::fs;
+use std::io::{BufReader, ErrorKind};
+
+use revonet::neuro::MultilayeredNetwork;
+
+use ai::features::{FeatureSet, Normalization, read_csv, write_csv_header, write_csv_row};
+use ai::network_file::{NetworkFile, NetworkSidecar, SIDECAR_FILE_NAME};
+
+#[test]
+fn network_file_test() {
+	let dir = std::env::temp_dir().join(format!("puyoai_network_file_test_{}", std::process::id()));
+	fs::create_dir_all(&dir).unwrap();
+	let count = FeatureSet::default().count();
+
+	//正規化を入れる前のネットワークだけのファイルも読める
+	let plain = dir.join("plain.json");
+	fs::write(&plain, serde_json::to_string(&MultilayeredNetwork::new(count, 1)).unwrap()).unwrap();
+	let mut file = NetworkFile::<MultilayeredNetwork>::load(&plain).unwrap();
+	assert_eq!(file.feature_set, FeatureSet::V1);
+	assert_eq!(file.normalization, None);
+
+	//featuresのCSVから求めた正規化を一緒に保存する
+	let mut csv = Vec::new();
+	write_csv_header(&mut csv, FeatureSet::V1).unwrap();
+	write_csv_row(&mut csv, &vec![1.; count]).unwrap();
+	write_csv_row(&mut csv, &vec![3.; count]).unwrap();
+	let samples = read_csv(BufReader::new(csv.as_slice()), FeatureSet::V1).unwrap();
+	file.normalization = Some(Normalization::mean_std(&samples, count));
+
+	let normalized = dir.join("normalized.json");
+	file.save(&normalized).unwrap();
+	let loaded = NetworkFile::<MultilayeredNetwork>::load(&normalized).unwrap();
+	let normalization = loaded.normalization.unwrap();
+	assert_eq!(normalization.offset, vec![2.; count]);
+	assert_eq!(normalization.scale, vec![1.; count]);
+
+	//特徴量の数と合わない正規化は読まない
+	file.normalization = Some(Normalization::identity(count - 1));
+	file.save(&normalized).unwrap();
+	assert_eq!(NetworkFile::<MultilayeredNetwork>::load(&normalized).unwrap_err().kind(), ErrorKind::InvalidData);
+
+	//学習で保存されたネットワークだけのファイルは、隣の正規化を使う
+	let sidecar = NetworkSidecar { feature_set: FeatureSet::V1, normalization: normalization.clone() };
+	assert_eq!(sidecar.save(&dir).unwrap(), dir.join(SIDECAR_FILE_NAME));
+	assert_eq!(NetworkFile::<MultilayeredNetwork>::load(&plain).unwrap().normalization, Some(normalization));
+
+	//正規化を一緒に保存したファイルは隣の正規化を使わない
+	file.normalization = None;
+	file.save(&normalized).unwrap();
+	assert_eq!(NetworkFile::<MultilayeredNetwork>::load(&normalized).unwrap().normalization, None);
+
+	//隣の正規化も特徴量の数と合わなければ読まない
+	NetworkSidecar { feature_set: FeatureSet::V1, normalization: Normalization::identity(count - 1) }.save(&dir).unwrap();
+	assert_eq!(NetworkFile::<MultilayeredNetwork>::load(&plain).unwrap_err().kind(), ErrorKind::InvalidData);
+
+	fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn read_csv_test() {
+	//列名が違うCSVは別の特徴量なので読まない
+	let csv = "link2,link3\n1,2\n";
+	assert!(read_csv(BufReader::new(csv.as_bytes()), FeatureSet::V1).is_err());
+}
@@ -0,0 +1,155 @@
+﻿mod common;
+
+use env::env::{Env, MAX_OJAMA_RECEIVE_COUNT};
+use env::ojama_status::{OjamaStatus, PendingOjama};
+use env::puyo_kind::PuyoKind;
+
+use common::{TWO_CHAIN, board_from_rows};
+
+///BattleEnvと同じ順番でフレームを進める2人対戦、ぷよの操作はテスト側で行う
+struct Match {
+	players: [Env; 2],
+	frame: usize,
+}
+
+impl Match {
+	unsafe fn new() -> Self {
+		let mut player1 = Env::new(&0);
+		let mut player2 = Env::new(&0);
+		player1.init();
+		player2.init();
+		Match { players: [player1, player2], frame: 0 }
+	}
+
+	unsafe fn step(&mut self) {
+		for player in &mut self.players {
+			player.update();
+		}
+
+		let [player1, player2] = &mut self.players;
+		player1.process_events(self.frame, Some(player2));
+		player2.process_events(self.frame, Some(player1));
+		self.frame += 1;
+	}
+
+	unsafe fn step_until(&mut self, condition: impl Fn(&Match) -> bool) {
+		let start = self.frame;
+		while !condition(self) {
+			assert!(self.frame - start < 10000, "condition was never met");
+			self.step();
+		}
+	}
+
+	///2連鎖の盤面にして右端に置く
+	unsafe fn fire_two_chain(&mut self, player_index: usize) {
+		let player = &mut self.players[player_index];
+		player.board = board_from_rows(&TWO_CHAIN);
+		player.puyo_status.position.x = 6;
+		player.quick_drop();
+	}
+}
+
+unsafe fn ojama_count(env: &Env) -> i32 {
+	env.board.get_bits(PuyoKind::Ojama).popcnt128()
+}
+
+#[test]
+fn ojama_queue_keeps_every_attack() {
+	let mut ojama = OjamaStatus::new();
+	ojama.push(10, 5);
+	ojama.push(20, 0);
+	ojama.push(15, 0);
+	ojama.push(0, 0);
+
+	assert_eq!(ojama.pending().len(), 3);
+	assert_eq!(ojama.get_all_ojama_size(), 45);
+	assert_eq!(ojama.get_receivable_ojama_size(), 35);
+	assert_eq!(ojama.get_time_to_receive(), 0);
+
+	//古い予告から相殺する
+	assert_eq!(ojama.offset(12), 0);
+	assert_eq!(ojama.pending()[0], PendingOjama { size: 18, receive_frame: 0 });
+	assert_eq!(ojama.get_all_ojama_size(), 33);
+
+	assert_eq!(ojama.take_receivable(MAX_OJAMA_RECEIVE_COUNT), 30);
+	assert_eq!(ojama.pending()[0], PendingOjama { size: 3, receive_frame: 0 });
+
+	assert_eq!(ojama.offset(10), 7);
+	assert!(ojama.is_empty());
+}
+
+#[test]
+fn ojama_falls_thirty_per_drop() {
+	unsafe {
+		let mut env = Env::new(&0);
+		env.init();
+		env.ojama.push(40, 0);
+
+		env.quick_drop();
+		env.create_new_puyo();
+		assert_eq!(ojama_count(&env), 30);
+		assert_eq!(env.ojama.get_all_ojama_size(), 10);
+
+		env.quick_drop();
+		env.create_new_puyo();
+		assert_eq!(ojama_count(&env), 40);
+		assert!(env.ojama.is_empty());
+	}
+}
+
+#[test]
+fn ojama_waits_until_attacker_chain_finishes() {
+	unsafe {
+		let mut game = Match::new();
+		game.fire_two_chain(0);
+
+		let steps = game.players[0].chain_steps.clone();
+		assert_eq!(steps.len(), 2);
+
+		//1連鎖目の40点は端数として持ち越され、2連鎖目で5個になる
+		game.step_until(|game| !game.players[1].ojama.is_empty());
+		assert_eq!(game.players[1].ojama.pending()[0], PendingOjama { size: 5, receive_frame: steps[1].frames - 1 });
+		assert_eq!(game.players[0].attack_carry, 10);
+
+		//相手の連鎖中に置いてもまだ降らない
+		let defender = &mut game.players[1];
+		defender.quick_drop();
+		defender.create_new_puyo();
+		assert_eq!(ojama_count(defender), 0);
+		assert_eq!(defender.ojama.get_receivable_ojama_size(), 0);
+
+		game.step_until(|game| game.players[0].events.is_empty());
+		let defender = &mut game.players[1];
+		assert_eq!(defender.ojama.get_receivable_ojama_size(), 5);
+
+		defender.quick_drop();
+		defender.create_new_puyo();
+		assert_eq!(ojama_count(defender), 5);
+		assert!(defender.ojama.is_empty());
+	}
+}
+
+#[test]
+fn ojama_counter_during_chain() {
+	unsafe {
+		let mut game = Match::new();
+
+		//自分の予告2個を相殺した残りの3個だけが届く
+		game.players[0].ojama.push(2, 0);
+		game.fire_two_chain(0);
+		game.step_until(|game| !game.players[1].ojama.is_empty());
+		assert!(game.players[0].ojama.is_empty());
+		assert_eq!(game.players[1].ojama.get_all_ojama_size(), 3);
+		assert_eq!(game.players[1].ojama.get_receivable_ojama_size(), 0);
+
+		//打ち返した連鎖で予告を相殺して、余りを送り返す
+		game.fire_two_chain(1);
+		let steps = game.players[1].chain_steps.clone();
+		game.step_until(|game| !game.players[0].ojama.is_empty());
+		assert!(game.players[1].ojama.is_empty());
+		assert_eq!(game.players[0].ojama.pending()[0], PendingOjama { size: 2, receive_frame: steps[1].frames - 1 });
+
+		game.step_until(|game| game.players[1].events.is_empty());
+		assert_eq!(game.players[0].ojama.get_receivable_ojama_size(), 2);
+	}
+}
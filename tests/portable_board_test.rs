@@ -0,0 +1,170 @@
+﻿#![cfg(target_arch = "x86_64")]
+
+use env::board::Board;
+use env::board_bit::BoardBit;
+use env::env::MAX_OJAMA_RECEIVE_COUNT;
+use env::ojama_placer::{OjamaDropOrder, OjamaPlacer};
+use env::ojama_status::OjamaStatus;
+use env::portable::board::Board as PortableBoard;
+use env::portable::board_bit::BoardBit as PortableBoardBit;
+use env::puyo_kind::PuyoKind;
+use env::puyo_status::PuyoStatus;
+use env::rotation::Rotation;
+use env::vector2::Vector2;
+
+const CELLS: [char; 7] = ['R', 'G', 'B', 'Y', 'P', 'O', 'E'];
+
+struct Lcg(u32);
+
+impl Lcg {
+	fn next(&mut self, max: u32) -> u32 {
+		self.0 = self.0.wrapping_mul(0x5D588B65).wrapping_add(0x269EC3);
+		(self.0 >> 16) % max
+	}
+}
+
+///床と壁付きで、各列を下から適当に埋めた盤面の文字列を作る
+fn random_board_str(rng: &mut Lcg) -> String {
+	let mut cells = vec!['E'; 8 * 16];
+	for x in 0..8 {
+		cells[x] = 'W';
+	}
+	for y in 0..16 {
+		cells[y * 8] = 'W';
+		cells[y * 8 + 7] = 'W';
+	}
+
+	for x in 1..=6 {
+		let height = rng.next(14) as usize;
+		for y in 1..=height {
+			cells[y * 8 + x] = CELLS[rng.next(CELLS.len() as u32 - 1) as usize];
+		}
+	}
+
+	cells.into_iter().collect()
+}
+
+unsafe fn assert_same_bits(simd: &BoardBit, portable: &PortableBoardBit) {
+	for i in 0..=127 {
+		assert_eq!(simd.get_1_flag(i), portable.get_1_flag(i), "bit {i}");
+	}
+}
+
+///差分で更新したハッシュが、盤面全体から計算し直したものとSIMD版・u128版の両方で一致する
+unsafe fn assert_same_hash(simd: &Board, portable: &PortableBoard) {
+	assert_eq!(simd.hash(), simd.columns_hash(0xFF));
+	assert_eq!(simd.hash(), portable.hash());
+	assert_eq!(portable.hash(), portable.columns_hash(0xFF));
+}
+
+#[test]
+fn portable_board_heights_and_bits() {
+	unsafe {
+		let mut rng = Lcg(1);
+		for _ in 0..500 {
+			let board_str = random_board_str(&mut rng);
+			let simd = Board::from_str(&board_str);
+			let portable = PortableBoard::from_str(&board_str);
+
+			assert_eq!(simd.to_str(), portable.to_str());
+			assert_same_hash(&simd, &portable);
+			assert_eq!(simd.get_heights(), portable.get_heights());
+			assert_eq!(simd.is_all_cleared(), portable.is_all_cleared());
+
+			for puyo_kind in [PuyoKind::Empty, PuyoKind::Ojama, PuyoKind::Wall, PuyoKind::Red, PuyoKind::Green, PuyoKind::Blue, PuyoKind::Yellow, PuyoKind::Purple] {
+				let simd_bits = simd.get_bits(puyo_kind);
+				let portable_bits = portable.get_bits(puyo_kind);
+				assert_same_bits(&simd_bits, &portable_bits);
+				assert_same_bits(&simd_bits.mask_board_12(), &portable_bits.mask_board_12());
+				assert_same_bits(&simd_bits.mask_board_13(), &portable_bits.mask_board_13());
+				assert_same_bits(&simd_bits.expand_edge(), &portable_bits.expand_edge());
+				assert_same_bits(&simd_bits.and_not(&simd_bits.l_shift()), &portable_bits.and_not(&portable_bits.l_shift()));
+				assert_eq!(simd_bits.popcnt128(), portable_bits.popcnt128());
+				assert_eq!(simd_bits.horizontal_or16(), portable_bits.horizontal_or16());
+				assert_eq!(simd_bits.column_flags(), portable_bits.column_flags());
+
+				let mut simd_erasing = BoardBit::default();
+				let mut portable_erasing = PortableBoardBit::default();
+				assert_eq!(simd_bits.mask_board_12().find_erasing_flag(&mut simd_erasing),
+						   portable_bits.mask_board_12().find_erasing_flag(&mut portable_erasing));
+				assert_same_bits(&simd_erasing, &portable_erasing);
+
+				let simd_seed = simd_bits & simd.get_bits(PuyoKind::Empty).expand_edge();
+				let portable_seed = portable_bits & portable.get_bits(PuyoKind::Empty).expand_edge();
+				assert_same_bits(&simd_seed.expand(simd_bits), &portable_seed.expand(portable_bits));
+			}
+		}
+	}
+}
+
+#[test]
+fn portable_board_chain() {
+	unsafe {
+		let mut rng = Lcg(2);
+		for _ in 0..500 {
+			let board_str = random_board_str(&mut rng);
+			let mut simd = Board::from_str(&board_str);
+			let mut portable = PortableBoard::from_str(&board_str);
+
+			let mut chain = 0u8;
+			loop {
+				let mut simd_erased = BoardBit::default();
+				let mut portable_erased = PortableBoardBit::default();
+				let mut simd_waste = 0;
+				let mut portable_waste = 0;
+
+				let score = simd.erase_if_needed(&chain, &mut simd_erased, &mut simd_waste);
+				assert_eq!(score, portable.erase_if_needed(&chain, &mut portable_erased, &mut portable_waste));
+				assert_eq!(simd_waste, portable_waste);
+				assert_same_bits(&simd_erased, &portable_erased);
+
+				if score == 0 {
+					break;
+				}
+
+				assert_eq!(simd.drop_after_erased(&simd_erased), portable.drop_after_erased(&portable_erased));
+				assert_eq!(simd.to_str(), portable.to_str());
+				assert_same_hash(&simd, &portable);
+				chain += 1;
+			}
+		}
+	}
+}
+
+#[test]
+fn portable_board_put() {
+	unsafe {
+		let mut rng = Lcg(3);
+		for _ in 0..500 {
+			let board_str = random_board_str(&mut rng);
+			let mut simd = Board::from_str(&board_str);
+			let mut portable = PortableBoard::from_str(&board_str);
+
+			let heights = simd.get_heights();
+			let x = 1 + rng.next(6) as i8;
+			let rotation = rng.next(4) as u8;
+			let puyo_status = PuyoStatus::new(Vector2::new(x, 13), Rotation::new(rotation));
+			let movable_x = x + puyo_status.position_diff.x;
+			if movable_x < 1 || movable_x > 6 || heights[x as usize] > 12 || heights[movable_x as usize] > 12 {
+				continue;
+			}
+
+			let mut simd_pos = Vector2::default();
+			let mut portable_pos = Vector2::default();
+			assert_eq!(simd.put_puyo(&puyo_status, &PuyoKind::Red, &PuyoKind::Blue, &mut simd_pos),
+					   portable.put_puyo(&puyo_status, &PuyoKind::Red, &PuyoKind::Blue, &mut portable_pos));
+			assert_eq!(simd.to_str(), portable.to_str());
+			assert_same_hash(&simd, &portable);
+
+			let ojama_size = rng.next(40) as usize;
+			let mut simd_ojama = OjamaStatus::new();
+			let mut portable_ojama = OjamaStatus::new();
+			simd_ojama.push(ojama_size, 0);
+			portable_ojama.push(ojama_size, 0);
+			simd.try_put_ojama(&mut simd_ojama, &mut OjamaPlacer::new(&0, OjamaDropOrder::Rotating), MAX_OJAMA_RECEIVE_COUNT);
+			portable.try_put_ojama(&mut portable_ojama, &mut OjamaPlacer::new(&0, OjamaDropOrder::Rotating), MAX_OJAMA_RECEIVE_COUNT);
+			assert_eq!(simd.to_str(), portable.to_str());
+			assert_same_hash(&simd, &portable);
+		}
+	}
+}
@@ -0,0 +1,118 @@
+﻿mod common;
+
+use env::simulator::Simulator;
+
+use common::board_str_from_rows;
+
+///2列目と4列目を指定の段まで積んだ井戸、3列目はwell_heightまで積む
+fn well(side_height: usize, well_height: usize) -> String {
+	let rows = (1..=side_height)
+		.map(|y| if y <= well_height { "WEOOOEEW" } else { "WEOEOEEW" })
+		.collect::<Vec<_>>();
+	board_str_from_rows(&rows)
+}
+
+fn position(player: &Simulator) -> (i8, i8, u8) {
+	let status = player.puyo_status();
+	(status.position.x, status.position.y, status.rotation.0)
+}
+
+#[test]
+fn wall_kick_test() {
+	let mut player = Simulator::new(0).unwrap();
+	for _ in 0..3 {
+		player.move_right();
+	}
+	assert_eq!(position(&player), (6, 12, 3));
+
+	//右の壁にめり込む分だけ左へずれる
+	player.rotate_cw();
+	assert_eq!(position(&player), (5, 12, 0));
+
+	let mut player = Simulator::new(0).unwrap();
+	for _ in 0..2 {
+		player.move_left();
+	}
+	player.rotate_ccw();
+	assert_eq!(position(&player), (2, 12, 2));
+}
+
+#[test]
+fn floor_kick_test() {
+	let mut player = Simulator::new(0).unwrap();
+	player.rotate_cw();
+	while player.puyo_status().position.y > 1 {
+		player.soft_drop();
+	}
+	assert_eq!(position(&player), (3, 1, 0));
+
+	//子ぷよが床に埋まる分だけ上へずれる
+	player.rotate_cw();
+	assert_eq!(position(&player), (3, 2, 1));
+	assert!(player.is_controllable());
+}
+
+#[test]
+fn stack_kick_test() {
+	//4列目が軸ぷよより高いので左へずれる
+	let mut player = Simulator::new(0).unwrap();
+	player.set_board_from_str(&board_str_from_rows(&["WEEEOEEW"; 12])).unwrap();
+	player.rotate_cw();
+	assert_eq!(position(&player), (2, 12, 0));
+	assert!(!player.env().quick_turn);
+}
+
+#[test]
+fn quick_turn_test() {
+	let mut player = Simulator::new(0).unwrap();
+	player.set_board_from_str(&well(12, 0)).unwrap();
+	let [center, movable] = player.current_pair();
+
+	//1回目は回転できず、2回目で上下が入れ替わる
+	player.rotate_cw();
+	assert_eq!(position(&player), (3, 12, 3));
+	assert!(player.env().quick_turn);
+	player.rotate_ccw();
+	assert_eq!(position(&player), (3, 12, 1));
+	assert!(!player.env().quick_turn);
+
+	player.quick_drop();
+	assert_eq!(player.cell(3, 1), Some(movable));
+	assert_eq!(player.cell(3, 2), Some(center));
+}
+
+#[test]
+fn quick_turn_in_shallow_well_test() {
+	//井戸の底が軸ぷよのすぐ下なら、子ぷよと軸ぷよの位置を入れ替える
+	let mut player = Simulator::new(0).unwrap();
+	player.set_board_from_str(&well(12, 11)).unwrap();
+	let [center, movable] = player.current_pair();
+
+	player.rotate_cw();
+	player.rotate_cw();
+	assert_eq!(position(&player), (3, 13, 1));
+
+	player.quick_drop();
+	assert_eq!(player.cell(3, 12), Some(movable));
+	assert_eq!(player.cell(3, 13), Some(center));
+}
+
+#[test]
+fn rotate_180_test() {
+	//反対側が空いていればその場で回る
+	let mut player = Simulator::new(0).unwrap();
+	player.rotate_cw();
+	player.rotate_180();
+	assert_eq!(position(&player), (3, 12, 2));
+
+	//左が塞がれていれば軸ぷよと子ぷよを入れ替える
+	let mut player = Simulator::new(0).unwrap();
+	player.rotate_cw();
+	for _ in 0..2 {
+		player.move_right();
+	}
+	assert_eq!(position(&player), (5, 12, 0));
+	player.set_board_from_str(&board_str_from_rows(&["WEEEOEEW"; 12])).unwrap();
+	player.rotate_180();
+	assert_eq!(position(&player), (6, 12, 2));
+}
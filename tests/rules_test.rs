@@ -0,0 +1,85 @@
+﻿mod common;
+
+use env::board::Board;
+use env::board_bit::BoardBit;
+use env::env::BAG_SIZE;
+use env::ojama_placer::OjamaDropOrder;
+use env::puyo_kind::PuyoKind;
+use env::rules::Rules;
+use env::simulator::Simulator;
+
+use common::board_str_from_rows;
+
+#[test]
+fn tsu_margin_time() {
+	let rules = Rules::tsu();
+
+	assert_eq!(rules.ojama_rate(0), 70);
+	assert_eq!(rules.ojama_rate(96 * 60 - 1), 70);
+	assert_eq!(rules.ojama_rate(96 * 60), 52);
+	assert_eq!(rules.ojama_rate(112 * 60 - 1), 52);
+	assert_eq!(rules.ojama_rate(112 * 60), 34);
+	assert_eq!(rules.ojama_rate(255 * 60), 2);
+	assert_eq!(rules.ojama_rate(256 * 60), 1);
+	assert_eq!(rules.ojama_rate(usize::MAX), 1);
+
+	assert_eq!(Rules::tsu_long_margin().ojama_rate(96 * 60), 70);
+	assert_eq!(Rules::tsu_without_margin().ojama_rate(usize::MAX), 70);
+}
+
+#[test]
+fn rules_colors() {
+	let colors = Rules::tsu_three_colors().colors();
+	assert_eq!(colors, [PuyoKind::Red, PuyoKind::Green, PuyoKind::Blue]);
+
+	let mut player = Simulator::new_with_rules(0, OjamaDropOrder::Random, Rules::tsu_three_colors()).unwrap();
+	let empty_board = player.board_string().replace("\r\n", "");
+	for _ in 0..50 {
+		for puyo in player.current_pair() {
+			assert!(colors.contains(&puyo));
+		}
+		player.quick_drop();
+		player.set_board_from_str(&empty_board).unwrap();
+		player.create_new_puyo();
+	}
+
+	//袋には使う色がほぼ同じ数ずつ入る
+	let bag = Rules::tsu_three_colors().bag();
+	assert_eq!(bag.len(), BAG_SIZE);
+	for color in colors {
+		let count = bag.iter().filter(|&puyo| puyo == color).count();
+		assert!(count.abs_diff(BAG_SIZE / colors.len()) <= 1);
+	}
+
+	let invalid = Rules { color_count: 2, ..Rules::tsu() };
+	assert!(Simulator::new_with_rules(0, OjamaDropOrder::Random, invalid).is_err());
+}
+
+#[test]
+fn five_colors() {
+	unsafe {
+		//5色を同時に消すと色数ボーナスは24
+		let board_str = board_str_from_rows(&["WRGBYPEW"; 4]);
+		let board = Board::from_str(&board_str);
+		assert_eq!(board.get_cell(5, 1), PuyoKind::Purple);
+		assert_eq!(board.to_str().replace("\r\n", ""), board_str);
+
+		let mut erased = BoardBit::default();
+		assert_eq!(board.erase_if_needed(&0, &mut erased, &mut 0), 10 * 20 * 24);
+		assert_eq!(erased.popcnt128(), 20);
+	}
+
+	let colors = Rules::tsu_five_colors().colors();
+	assert_eq!(colors.len(), 5);
+
+	let mut player = Simulator::new_with_rules(0, OjamaDropOrder::Random, Rules::tsu_five_colors()).unwrap();
+	let empty_board = player.board_string().replace("\r\n", "");
+	let mut purple_found = false;
+	for _ in 0..50 {
+		purple_found |= player.current_pair().contains(&PuyoKind::Purple);
+		player.quick_drop();
+		player.set_board_from_str(&empty_board).unwrap();
+		player.create_new_puyo();
+	}
+	assert!(purple_found);
+}
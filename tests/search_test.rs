@@ -0,0 +1,170 @@
+﻿use std::time::{Duration, Instant};
+
+mod common;
+
+use ai::async_search::{AsyncSearch, SearchRequest};
+use ai::build_ai::AI;
+use ai::debug::Debug;
+use ai::evaluator::{EvalContext, Evaluator};
+use ai::evaluator::simple_evaluator::SimpleEvaluator;
+use ai::key_type::KeyType;
+use ai::move_generator::MoveOrigin;
+use ai::opponent_status::OpponentStatus;
+use ai::potential::Potential;
+use ai::search_config::SearchConfig;
+use env::env::Env;
+use env::ojama_status::OjamaStatus;
+use env::puyo_kind::PuyoKind;
+use env::rules::Rules;
+
+use common::board_from_rows;
+
+///得点だけで評価する
+struct ScoreEvaluator;
+
+impl Evaluator for ScoreEvaluator {
+	type Worker = ();
+
+	fn worker(&self) {}
+
+	fn evaluate(&self, context: &mut EvalContext, _worker: &mut ()) -> f32 {
+		context.score as f32
+	}
+
+	fn clone(&self) -> Self {
+		ScoreEvaluator
+	}
+}
+
+///1列目に赤が3つ積まれていて、赤のペアは2つ目のネクストで来る
+unsafe fn request() -> SearchRequest {
+	SearchRequest {
+		board: board_from_rows(&["WREEEEEW"; 3]),
+		current: MoveOrigin::spawn(&Rules::default()),
+		next: vec![[PuyoKind::Yellow, PuyoKind::Blue], [PuyoKind::Red, PuyoKind::Yellow]],
+		ojama: OjamaStatus::default(),
+		center_puyo: PuyoKind::Green,
+		movable_puyo: PuyoKind::Blue,
+		all_cleared: false,
+		ojama_rate: Env::new(&0).ojama_rate,
+		opponent_status: OpponentStatus::default(),
+	}
+}
+
+unsafe fn search(config: SearchConfig) -> f32 {
+	let mut ai = AI::new(ScoreEvaluator);
+	ai.search_config = config;
+	let request = request();
+	ai.search(&request.board, &request.current, &request.next, &request.ojama, request.center_puyo, request.movable_puyo, request.all_cleared, &request.ojama_rate, &request.opponent_status);
+
+	let best_move = ai.best_move.unwrap();
+	assert_eq!(best_move.path.last(), Some(&KeyType::Drop));
+	best_move.eval
+}
+
+#[test]
+fn lookahead_test() {
+	unsafe {
+		let two_pairs = search(SearchConfig { depth: 2, ..SearchConfig::visible() });
+		assert_eq!(two_pairs, 0.);
+
+		let three_pairs = search(SearchConfig::visible());
+		assert!(three_pairs > 0.);
+	}
+}
+
+#[test]
+fn sampling_and_time_budget_test() {
+	unsafe {
+		//見えているツモで消せるので、引き直した後も得点は残る
+		let sampled = search(SearchConfig { depth: 5, sample_count: 4, ..SearchConfig::visible() });
+		assert!(sampled > 0.);
+
+		//時間がなくてもカレントの置き方は選ぶ
+		assert_eq!(search(SearchConfig { time_budget_ms: Some(0), ..SearchConfig::visible() }), 0.);
+	}
+}
+
+#[test]
+fn sampling_seed_test() {
+	unsafe {
+		let best_move = |config: SearchConfig, request: &SearchRequest| {
+			let mut ai = AI::new(SimpleEvaluator::default());
+			ai.search_config = config;
+			ai.search(&request.board, &request.current, &request.next, &request.ojama, request.center_puyo, request.movable_puyo, request.all_cleared, &request.ojama_rate, &request.opponent_status);
+			ai.best_move.unwrap()
+		};
+
+		//引き直すツモはseedと盤面で決まるので、同じ局面なら何度探索しても同じ置き方になる
+		let config = SearchConfig { beam_width: 8, depth: 4, sample_count: 3, ..SearchConfig::visible() };
+		let mut env = Env::new(&0);
+		env.init();
+		for _ in 0..5 {
+			let request = SearchRequest::from_env(&env, &OpponentStatus::default());
+			let first = best_move(config.clone(), &request);
+			let second = best_move(config.clone(), &request);
+			assert_eq!(first.path, second.path);
+			assert_eq!(first.eval, second.eval);
+
+			let sequential = best_move(SearchConfig { parallel: false, ..config.clone() }, &request);
+			assert_eq!(first.eval, sequential.eval);
+
+			for &key in &first.path {
+				env.input(key);
+			}
+			env.create_new_puyo();
+		}
+	}
+}
+
+#[test]
+fn async_search_test() {
+	unsafe {
+		let mut async_search = AsyncSearch::new(AI::new(ScoreEvaluator));
+
+		//startし直すと前の局面の結果は返らない
+		let mut two_pairs = request();
+		two_pairs.next.pop();
+		async_search.start(two_pairs);
+		let id = async_search.start(request());
+
+		let result = async_search.result(Instant::now() + Duration::from_secs(30)).unwrap();
+		assert_eq!(result.id, id);
+		assert_eq!(result.best_move.eval, search(SearchConfig::visible()));
+	}
+}
+
+#[test]
+fn parallel_search_test() {
+	unsafe {
+		let best_move = |config: SearchConfig, request: &SearchRequest| {
+			let mut ai = AI::new(ScoreEvaluator);
+			ai.search_config = config;
+			ai.search(&request.board, &request.current, &request.next, &request.ojama, request.center_puyo, request.movable_puyo, request.all_cleared, &request.ojama_rate, &request.opponent_status);
+			ai.best_move.unwrap()
+		};
+
+		//ほとんどの置き方が同じ評価になるので、並列にしても同じ順で選ばれることを確かめる
+		for seed in 0..3 {
+			let mut env = Env::new(&seed);
+			env.init();
+
+			for _ in 0..10 {
+				if env.dead {
+					break;
+				}
+
+				let request = SearchRequest::from_env(&env, &OpponentStatus::default());
+				let parallel = best_move(SearchConfig::visible(), &request);
+				let sequential = best_move(SearchConfig { parallel: false, ..SearchConfig::visible() }, &request);
+				assert_eq!(parallel.path, sequential.path);
+				assert_eq!(parallel.eval, sequential.eval);
+
+				for &key in &parallel.path {
+					env.input(key);
+				}
+				env.create_new_puyo();
+			}
+		}
+	}
+}
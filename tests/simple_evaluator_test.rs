@@ -0,0 +1,73 @@
+﻿mod common;
+
+use ai::async_search::SearchRequest;
+use ai::build_ai::AI;
+use ai::debug::Debug;
+use ai::evaluator::simple_evaluator::SimpleEvaluator;
+use ai::evaluator::{EvalContext, Evaluator};
+use ai::opponent_status::OpponentStatus;
+use ai::potential::Potential;
+use ai::search_config::SearchConfig;
+use env::env::Env;
+use env::ojama_status::OjamaStatus;
+use env::rules::Rules;
+
+use common::board_from_rows;
+
+///1段目から順に並べた盤面を評価する
+unsafe fn evaluate(rows: &[&str]) -> f32 {
+	let board = board_from_rows(rows);
+
+	let mut debug = Debug::new();
+	SimpleEvaluator::default().evaluate(&mut EvalContext {
+		put_board: &board,
+		sim_board: &board,
+		potential: &Potential::default(),
+		chain: 0,
+		score: 0,
+		elapse_frame: 0,
+		ojama: &OjamaStatus::default(),
+		ojama_rate: 70,
+		opponent_status: &OpponentStatus::default(),
+		waste_chain_link: 0,
+		one_side_chain_count: 0,
+		instant_attack_count: 0,
+		attack_value: 0,
+		colors: Rules::default().colors(),
+		debug: &mut debug,
+	}, &mut ())
+}
+
+#[test]
+fn simple_evaluator_test() {
+	unsafe {
+		//同じ数のぷよなら繋がっている方が良い
+		let linked = evaluate(&["WREEEEEW", "WREEEEEW", "WREEEEEW"]);
+		let scattered = evaluate(&["WREERERW"]);
+		assert!(linked > scattered);
+
+		//3列目が埋まると死ぬ
+		assert_eq!(evaluate(&["WEEREEEW"; 12]), f32::MIN);
+	}
+}
+
+#[test]
+fn simple_evaluator_play_test() {
+	unsafe {
+		let mut ai = AI::new(SimpleEvaluator::default());
+		ai.search_config = SearchConfig { depth: 2, ..SearchConfig::visible() };
+
+		let mut env = Env::new(&0);
+		env.init();
+		for _ in 0..15 {
+			let request = SearchRequest::from_env(&env, &OpponentStatus::default());
+			ai.search(&request.board, &request.current, &request.next, &request.ojama, request.center_puyo, request.movable_puyo, request.all_cleared, &request.ojama_rate, &request.opponent_status);
+
+			for &key in &ai.best_move.as_ref().unwrap().path {
+				env.input(key);
+			}
+			env.create_new_puyo();
+			assert!(!env.dead);
+		}
+	}
+}
@@ -0,0 +1,80 @@
+﻿use env::ojama_placer::OjamaDropOrder;
+use env::puyo_kind::PuyoKind;
+use env::rules::Rules;
+use env::simulator::Simulator;
+
+#[test]
+fn simulator_test() {
+	let mut player = Simulator::new(0).unwrap();
+	let mut opponent = Simulator::new(0).unwrap();
+
+	assert!(player.is_controllable());
+	assert_eq!(player.cell(0, 5), Some(PuyoKind::Wall));
+	assert_eq!(player.cell(3, 1), Some(PuyoKind::Empty));
+	assert_eq!(player.cell(8, 0), None);
+
+	player.move_left();
+	player.quick_drop();
+	assert!(!player.process_events(Some(&mut opponent)));
+	assert_eq!(opponent.ojama_size(), 0);
+	assert!(!player.is_controllable());
+	assert_eq!(player.heights()[2], 3);
+
+	player.create_new_puyo();
+	assert!(player.is_controllable());
+	assert!(!player.is_dead());
+}
+
+#[test]
+fn simulator_board_str_test() {
+	let mut player = Simulator::new(0).unwrap();
+	assert!(player.set_board_from_str("WEEEEEEW").is_err());
+
+	let board = player.board_string().replace("\r\n", "");
+	player.set_board_from_str(&board).unwrap();
+	assert_eq!(player.board_string().replace("\r\n", ""), board);
+}
+
+#[test]
+fn soft_drop_test() {
+	let mut player = Simulator::new(0).unwrap();
+	let spawn_y = player.env().puyo_status.position.y;
+
+	player.soft_drop();
+	assert!(player.is_controllable());
+	assert_eq!(player.env().puyo_status.position.y, spawn_y - 1);
+
+	//床まで下げると次の入力で設置される
+	while player.is_controllable() {
+		player.soft_drop();
+	}
+	assert_eq!(player.heights()[3], 3);
+}
+
+#[test]
+fn fall_one_frame_test() {
+	let rules = Rules::tsu();
+	let mut player = Simulator::new_with_rules(0, OjamaDropOrder::Random, rules.clone()).unwrap();
+	let spawn_y = player.env().puyo_status.position.y;
+
+	for _ in 1..rules.fall_interval_frame {
+		assert!(!player.fall_one_frame());
+	}
+	assert!(!player.fall_one_frame());
+	assert_eq!(player.env().puyo_status.position.y, spawn_y - 1);
+
+	//床に着いてからもう1段分待つと設置される
+	let mut frame = 0;
+	while !player.fall_one_frame() {
+		frame += 1;
+	}
+	assert_eq!(frame + 1, (spawn_y as usize - 1) * rules.fall_interval_frame);
+	assert!(!player.is_controllable());
+	assert_eq!(player.heights()[3], 3);
+
+	let mut player = Simulator::new_with_rules(0, OjamaDropOrder::Random, Rules::tsu_without_gravity()).unwrap();
+	for _ in 0..rules.fall_interval_frame * 20 {
+		assert!(!player.fall_one_frame());
+	}
+	assert_eq!(player.env().puyo_status.position.y, spawn_y);
+}
@@ -0,0 +1,26 @@
+﻿use ai::transposition_table::{TableEntry, TranspositionTable};
+use env::board::Board;
+
+#[test]
+fn transposition_table_test() {
+	unsafe {
+		let mut table = TranspositionTable::new(4);
+		let board = Board::default();
+		let key = TranspositionTable::potential_key(board.hash(), 0, 0, 4);
+		assert_eq!(table.get(key), None);
+
+		table.insert(key, TableEntry::Potential { chain: 3, added_count: 2 });
+		assert_eq!(table.get(key), Some(TableEntry::Potential { chain: 3, added_count: 2 }));
+
+		//同じ場所に入る別のキーで上書きされる
+		let other = key + (1 << 4);
+		let generation = table.next_generation();
+		table.insert(other, TableEntry::Node { generation, index: 0 });
+		assert_eq!(table.get(key), None);
+		assert!(table.get(other).is_some());
+
+		//連鎖数や色数が違えば別のキーになる
+		assert_ne!(key, TranspositionTable::potential_key(board.hash(), 1, 0, 4));
+		assert_ne!(key, TranspositionTable::potential_key(board.hash(), 0, 0, 5));
+	}
+}